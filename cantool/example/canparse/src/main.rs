@@ -1,36 +1,32 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
 use cantool::can_tool::*;
-use std::path::Path;
+use futures_util::StreamExt;
 use logging::logging::MyLogging;
 
+/// Usage: canparse [interface] [dbc file], defaults to can0 and the default DBC path
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
+async fn main() -> ExitCode {
     // init logger
     let console_log = MyLogging::default();
     console_log.init_logger();
-    if let Ok(mut _cantool) = CanUtils::new("can0", None, vec![]).await {
-        // Start a task to handle incoming signals
-        let mut signals_handle = tokio::spawn(async move {
-            loop {
-                match _cantool.get_signals().await {
-                    Ok(_signal) => {
-                        println!("Received: {:?}", _signal);
-                    }
-                    Err(e) => {
-                        eprintln!("Error {}", e);
-                    }
-                }
-            }
-            println!("Signal stream ended.");
-        });
 
-        // Wait for the signal handling task to finish
-        if let Err(e) = signals_handle.await {
-            eprintln!("Signal handling task failed: {}", e);
+    let mut args = std::env::args().skip(1);
+    let ifname = args.next().unwrap_or_else(|| "can0".to_string());
+    let dbc_path = args.next().map(PathBuf::from);
+
+    let mut can_utils = match CanUtils::new(&ifname, dbc_path.as_deref(), vec![]).await {
+        Ok(can_utils) => can_utils,
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", ifname, e);
+            return ExitCode::FAILURE;
         }
-    }
+    };
 
-    // Keeping the main function alive
-    loop {
-        let _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let mut signals = can_utils.signal_stream();
+    while let Some(update) = signals.next().await {
+        println!("[{}] {:x}: {:?}", update.timestamp, update.frame_id, update.signals);
     }
+    println!("Signal stream ended.");
+    ExitCode::SUCCESS
 }
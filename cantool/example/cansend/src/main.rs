@@ -0,0 +1,40 @@
+use cantool::can_tool::*;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use logging::logging::MyLogging;
+
+// Usage: cansend <ifname> <dbc> <message> [signal=value ...]
+// e.g. cansend vcan0 consolidated.dbc BMS_Command BMS_ContactorReq=1
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // init logger
+    let console_log = MyLogging::default();
+    console_log.init_logger();
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        eprintln!("Usage: {} <ifname> <dbc> <message> [signal=value ...]", args[0]);
+        return;
+    }
+
+    let mut values = HashMap::new();
+    for arg in &args[4..] {
+        match arg.split_once('=').map(|(k, v)| (k, v.parse::<f64>())) {
+            Some((signal, Ok(value))) => {
+                values.insert(signal.to_string(), value);
+            }
+            _ => {
+                eprintln!("Invalid signal assignment: {}", arg);
+                return;
+            }
+        }
+    }
+
+    if let Ok(mut _cantool) = CanUtils::new(&args[1], Some(Path::new(&args[2])), vec![]).await {
+        match _cantool.encode_and_send(&args[3], values).await {
+            Ok(()) => println!("Sent {}", args[3]),
+            Err(e) => eprintln!("Error {}", e),
+        }
+    }
+}
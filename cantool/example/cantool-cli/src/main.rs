@@ -0,0 +1,572 @@
+use cantool::can_dbc::DbcInfo;
+use cantool::can_error::CanToolError;
+use cantool::can_filter::FrameFilter;
+use cantool::can_frame::RawFrame;
+use cantool::can_log::{self, CanLogWriter, LogEntry};
+use cantool::can_retry::{HealthMonitor, RetryPolicy};
+use cantool::can_signal::{SignalMeta, SignalValue};
+use cantool::can_sim::SimBus;
+use cantool::can_stats::BusStatsSnapshot;
+use cantool::can_tool::CanUtils;
+use canparse::pgn::PgnLibrary;
+use clap::{Args, Parser, Subcommand};
+use logging::logging::MyLogging;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// CAN bus tool using the DBC decoder of the gateway
+#[derive(Parser)]
+#[command(name = "cantool", version)]
+struct Cli {
+    /// CAN interface
+    #[arg(short, long, default_value = "can0", global = true)]
+    interface: String,
+
+    /// DBC file, the consolidated gateway DBC by default
+    #[arg(short, long, global = true)]
+    dbc: Option<PathBuf>,
+
+    /// Print library logs to the console
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print received frames, raw in candump -L format or decoded
+    Dump(DumpArgs),
+    /// Encode and transmit a DBC message, or transmit a raw frame
+    Send(SendArgs),
+    /// Record frames into a candump -L log, or Vector ASC for `.asc` files
+    Record(RecordArgs),
+    /// Replay a log onto the interface, or print its decoded signals
+    Replay(ReplayArgs),
+    /// Print bus load, frame rates and the controller error state
+    Stats(StatsArgs),
+    /// Inspect the DBC file
+    Dbc {
+        #[command(subcommand)]
+        command: DbcCommand,
+    },
+}
+
+#[derive(Args)]
+struct DumpArgs {
+    /// Decode frames with the DBC, frames of unknown IDs are skipped
+    #[arg(long)]
+    decode: bool,
+
+    /// Print one JSON object per line
+    #[arg(long)]
+    json: bool,
+
+    /// Only frames with this hex identifier, may be repeated
+    #[arg(long = "id", value_parser = parse_id)]
+    ids: Vec<u32>,
+
+    /// Only frames of this DBC message, may be repeated
+    #[arg(short, long)]
+    message: Vec<String>,
+
+    /// Only frames carrying this DBC signal, decoded output lists only the given signals
+    #[arg(short, long)]
+    signal: Vec<String>,
+
+    /// Exit after this many frames
+    #[arg(short = 'n', long)]
+    count: Option<u64>,
+}
+
+#[derive(Args)]
+struct SendArgs {
+    /// DBC message name, or a raw frame as `<id>#<data>` in hex, e.g. `123#DEADBEEF`, 8 ID
+    /// digits send an extended frame as in candump, e.g. `00000123#DEADBEEF`
+    frame: String,
+
+    /// Signal values as `<signal>=<value>`, signals not given are sent as 0
+    #[arg(value_parser = parse_assignment)]
+    values: Vec<(String, f64)>,
+}
+
+#[derive(Args)]
+struct RecordArgs {
+    /// Log file, `.asc` for Vector ASC, candump -L otherwise
+    file: PathBuf,
+
+    /// Stop after this many seconds, Ctrl-C otherwise
+    #[arg(short = 't', long)]
+    duration: Option<f64>,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    /// candump -L or ASC log file
+    file: PathBuf,
+
+    /// Replay speed factor, 0 sends without delay
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Print the decoded signals of the log instead of transmitting it
+    #[arg(long)]
+    decode: bool,
+
+    /// Print one JSON object per line, with --decode
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// Nominal bitrate of the bus in bit/s
+    #[arg(short, long, default_value_t = 500_000)]
+    bitrate: u32,
+
+    /// Seconds between reports
+    #[arg(long, default_value_t = 1.0)]
+    interval: f64,
+
+    /// Number of identifiers listed per report, busiest first
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+
+    /// Print one JSON object per report
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum DbcCommand {
+    /// List the messages and signals of the DBC
+    Info {
+        /// Only this message
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Print the messages as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+
+    // library logs would mix with the dump output, so they are opt-in
+    if cli.verbose {
+        let console_log = MyLogging::default();
+        console_log.init_logger();
+    }
+
+    let result = match &cli.command {
+        Command::Dump(args) => dump(&cli, args).await,
+        Command::Send(args) => send(&cli, args).await,
+        Command::Record(args) => record(&cli, args).await,
+        Command::Replay(args) => replay(&cli, args).await,
+        Command::Stats(args) => stats(&cli, args).await,
+        Command::Dbc { command: DbcCommand::Info { message, json } } => dbc_info(&cli, message.as_deref(), *json),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error {}", e);
+        process::exit(1);
+    }
+}
+
+/// Opens the interface, failing right away instead of retrying as the gateway does
+async fn open(cli: &Cli, filters: Vec<FrameFilter>) -> Result<CanUtils, CanToolError> {
+    let policy = RetryPolicy { max_attempts: Some(1), ..RetryPolicy::default() };
+    CanUtils::new_with_policy(&cli.interface, cli.dbc.as_deref(), filters, policy, HealthMonitor::new()).await
+}
+
+fn dbc_path(cli: &Cli) -> &Path {
+    cli.dbc.as_deref().unwrap_or_else(|| Path::new(CanUtils::DEFAULT_DBC_PATH))
+}
+
+async fn dump(cli: &Cli, args: &DumpArgs) -> CliResult {
+    let mut filters: Vec<FrameFilter> = args.ids.iter().copied().map(FrameFilter::Id).collect();
+    filters.extend(args.message.iter().cloned().map(FrameFilter::Message));
+    filters.extend(FrameFilter::signals(args.signal.iter().cloned()));
+    let mut can_utils = open(cli, filters).await?;
+
+    let mut printed = 0;
+    while args.count.is_none_or(|count| printed < count) {
+        let frame = match can_utils.recv_frame(RECV_TIMEOUT).await {
+            Ok(frame) => frame,
+            Err(e) if e.is_recoverable() => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let entry = LogEntry::now(&cli.interface, frame);
+
+        let line = if args.decode {
+            match decode_entry(&can_utils, &entry, &args.signal, args.json) {
+                Some(line) => line,
+                None => continue,
+            }
+        } else if args.json {
+            raw_json(&entry).to_string()
+        } else {
+            entry.to_candump()
+        };
+
+        println!("{}", line);
+        printed += 1;
+    }
+    Ok(())
+}
+
+async fn send(cli: &Cli, args: &SendArgs) -> CliResult {
+    let mut can_utils = open(cli, Vec::new()).await?;
+
+    if let Some((id, data)) = args.frame.split_once('#') {
+        let frame = RawFrame::from_dbc_id(parse_frame_id(id)?, &parse_hex(data)?);
+        if frame.fd {
+            can_utils.enable_fd()?;
+        }
+        can_utils.send_frame(&frame).await?;
+    } else {
+        let values: HashMap<String, f64> = args.values.iter().cloned().collect();
+        can_utils.encode_and_send(&args.frame, values).await?;
+    }
+
+    println!("Sent {}", args.frame);
+    Ok(())
+}
+
+async fn record(cli: &Cli, args: &RecordArgs) -> CliResult {
+    let mut writer = CanLogWriter::create(&args.file)?;
+    let duration = args.duration.map(Duration::from_secs_f64);
+
+    let count = tokio::select! {
+        count = can_log::record(&cli.interface, &mut writer, duration) => Some(count?),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+    // recording is stopped by Ctrl-C without a duration, the buffered frames are kept
+    writer.finish()?;
+
+    if let Some(count) = count {
+        eprintln!("Recorded {} frames into {}", count, args.file.display());
+    }
+    Ok(())
+}
+
+async fn replay(cli: &Cli, args: &ReplayArgs) -> CliResult {
+    let entries = can_log::read_log_file(&args.file, &cli.interface)?;
+
+    if !args.decode {
+        let count = can_log::replay_to_interface(&entries, &cli.interface, args.speed).await?;
+        eprintln!("Replayed {} frames onto {}", count, cli.interface);
+        return Ok(());
+    }
+
+    // decoding needs no interface, the DBC is loaded on a simulated bus
+    let can_utils = CanUtils::new_with_backend(
+        Arc::new(SimBus::new()),
+        &cli.interface,
+        cli.dbc.as_deref(),
+        Vec::new(),
+        RetryPolicy { max_attempts: Some(1), ..RetryPolicy::default() },
+        HealthMonitor::new(),
+    )
+    .await?;
+
+    for entry in entries.iter() {
+        if let Some(line) = decode_entry(&can_utils, entry, &[], args.json) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+async fn stats(cli: &Cli, args: &StatsArgs) -> CliResult {
+    let mut can_utils = open(cli, Vec::new()).await?;
+    let monitor = can_utils.enable_stats(args.bitrate)?;
+    let names: HashMap<u32, String> = can_utils
+        .dbc_info()
+        .messages()
+        .map(|message| (message.id, message.name.clone()))
+        .collect();
+
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(args.interval.max(0.1)));
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let snapshot = monitor.snapshot();
+                if args.json {
+                    println!("{}", stats_json(&snapshot, &names));
+                } else {
+                    print_stats(&snapshot, &names, args.top);
+                }
+            }
+            frame = can_utils.recv_frame(RECV_TIMEOUT) => match frame {
+                Ok(_) => {}
+                Err(e) if e.is_recoverable() => {}
+                Err(e) => return Err(e.into()),
+            },
+        }
+    }
+}
+
+fn dbc_info(cli: &Cli, message: Option<&str>, json: bool) -> CliResult {
+    let path = dbc_path(cli);
+    let dbc = DbcInfo::from_dbc_file(path)?;
+    let library = PgnLibrary::from_dbc_file(path).map_err(|e| CanToolError::DbcLoad(format!("{}: {}", path.display(), e)))?;
+
+    let mut messages: Vec<_> = match message {
+        Some(name) => vec![dbc.message(name).ok_or_else(|| CanToolError::MessageNotInDbc(name.to_string()))?],
+        None => dbc.messages().collect(),
+    };
+    messages.sort_by_key(|message| message.id & 0x1FFFFFFF);
+
+    let mut output = Vec::new();
+    for message in messages {
+        let metas: Vec<SignalMeta> = message
+            .signals
+            .iter()
+            .filter_map(|signal| library.get_spn(signal).map(|spn| SignalMeta::new(spn, &dbc, message.id)))
+            .collect();
+        let cycle = dbc.cycle_time(message.id).map(|cycle| cycle.as_millis() as u64);
+
+        if json {
+            output.push(json!({
+                "id": format_id(message.id),
+                "name": message.name,
+                "dlc": message.dlc,
+                "cycle_ms": cycle,
+                "signals": metas.iter().map(meta_json).collect::<Vec<Value>>(),
+            }));
+            continue;
+        }
+
+        let cycle = cycle.map(|cycle| format!(", every {} ms", cycle)).unwrap_or_default();
+        println!("{} {} ({} bytes{})", format_id(message.id), message.name, message.dlc, cycle);
+        for meta in metas.iter() {
+            let unit = if meta.unit.is_empty() { String::new() } else { format!(" [{}]", meta.unit) };
+            let range = if meta.has_range() { format!(" {}..{}", meta.min, meta.max) } else { String::new() };
+            println!("    {}{}{} scale {} offset {}", meta.name, unit, range, meta.scale, meta.offset);
+            for (raw, text) in meta.value_table.iter().flatten() {
+                println!("        {} = {}", raw, text);
+            }
+        }
+    }
+
+    if json {
+        println!("{}", Value::Array(output));
+    }
+    Ok(())
+}
+
+/// Decoded line of a frame, None for identifiers unknown to the DBC
+fn decode_entry(can_utils: &CanUtils, entry: &LogEntry, only: &[String], json: bool) -> Option<String> {
+    let mut signals = can_utils.decode_raw_frame(&entry.frame)?;
+    if !only.is_empty() {
+        signals.retain(|signal, _| only.contains(signal));
+    }
+
+    let described = can_utils.describe_all(entry.frame.dbc_id(), &signals);
+    let message = can_utils
+        .dbc_info()
+        .message_by_id(entry.frame.dbc_id())
+        .map(|message| message.name.as_str())
+        .unwrap_or("?");
+
+    let mut names: Vec<&String> = signals.keys().collect();
+    names.sort();
+
+    if json {
+        let signals: Map<String, Value> = names
+            .iter()
+            .map(|name| {
+                let value = match described.get(*name) {
+                    Some(described) => value_json(described),
+                    None => json!({ "value": signals[*name] }),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        return Some(
+            json!({
+                "timestamp": entry.timestamp,
+                "interface": entry.ifname,
+                "id": format_id(entry.frame.dbc_id()),
+                "message": message,
+                "signals": signals,
+            })
+            .to_string(),
+        );
+    }
+
+    let values: Vec<String> = names
+        .iter()
+        .map(|name| match described.get(*name) {
+            Some(described) => format!("{}={}", name, described),
+            None => format!("{}={}", name, signals[*name]),
+        })
+        .collect();
+    Some(format!(
+        "({:.6}) {} {} {}: {}",
+        entry.timestamp,
+        entry.ifname,
+        format_id(entry.frame.dbc_id()),
+        message,
+        values.join(" ")
+    ))
+}
+
+fn raw_json(entry: &LogEntry) -> Value {
+    json!({
+        "timestamp": entry.timestamp,
+        "interface": entry.ifname,
+        "id": format_id(entry.frame.dbc_id()),
+        "extended": entry.frame.extended,
+        "fd": entry.frame.fd,
+        "data": entry.frame.data.iter().map(|byte| format!("{:02X}", byte)).collect::<String>(),
+    })
+}
+
+fn value_json(value: &SignalValue) -> Value {
+    let mut object = json!({ "value": value.value });
+    if !value.unit.is_empty() {
+        object["unit"] = json!(value.unit);
+    }
+    if let Some(text) = value.text.as_ref() {
+        object["text"] = json!(text);
+    }
+    if value.out_of_range {
+        object["out_of_range"] = json!(true);
+    }
+    object
+}
+
+fn meta_json(meta: &SignalMeta) -> Value {
+    json!({
+        "name": meta.name,
+        "unit": meta.unit,
+        "min": meta.min,
+        "max": meta.max,
+        "scale": meta.scale,
+        "offset": meta.offset,
+        "values": meta.value_table.as_ref().map(|table| {
+            table.iter().map(|(raw, text)| (raw.to_string(), json!(text))).collect::<Map<String, Value>>()
+        }),
+    })
+}
+
+fn stats_json(snapshot: &BusStatsSnapshot, names: &HashMap<u32, String>) -> Value {
+    let ids: Map<String, Value> = snapshot
+        .ids
+        .iter()
+        .map(|(id, stats)| {
+            let value = json!({
+                "name": names.get(id),
+                "frames_per_s": stats.frames_per_s,
+                "bytes_per_s": stats.bytes_per_s,
+                "cycle_ms": stats.mean_cycle.map(|cycle| cycle.as_secs_f64() * 1000.0),
+                "jitter_ms": stats.jitter.map(|jitter| jitter.as_secs_f64() * 1000.0),
+            });
+            (format_id(*id), value)
+        })
+        .collect();
+
+    json!({
+        "timestamp": snapshot.timestamp.to_rfc3339(),
+        "bus_load": snapshot.bus_load,
+        "frames_per_s": snapshot.frames_per_s,
+        "bytes_per_s": snapshot.bytes_per_s,
+        "error_frames": snapshot.error_frames,
+        "error_state": format!("{:?}", snapshot.error_state),
+        "bus_off_count": snapshot.bus_off_count,
+        "ids": ids,
+    })
+}
+
+fn print_stats(snapshot: &BusStatsSnapshot, names: &HashMap<u32, String>, top: usize) {
+    println!(
+        "{} load {:.1}% {:.0} frames/s {:.0} B/s, {} error frames, {:?}",
+        snapshot.timestamp.format("%H:%M:%S"),
+        snapshot.bus_load,
+        snapshot.frames_per_s,
+        snapshot.bytes_per_s,
+        snapshot.error_frames,
+        snapshot.error_state
+    );
+
+    let mut ids: Vec<_> = snapshot.ids.iter().collect();
+    ids.sort_by(|a, b| b.1.frames_per_s.total_cmp(&a.1.frames_per_s));
+    for (id, stats) in ids.into_iter().take(top) {
+        let cycle = stats
+            .mean_cycle
+            .map(|cycle| format!("{:.1} ms", cycle.as_secs_f64() * 1000.0))
+            .unwrap_or_else(|| "-".to_string());
+        let jitter = stats
+            .jitter
+            .map(|jitter| format!("{:.2} ms", jitter.as_secs_f64() * 1000.0))
+            .unwrap_or_else(|| "-".to_string());
+        let name = names.get(id).map(String::as_str).unwrap_or("");
+        println!("    {:>8} {:>8.1}/s cycle {:>9} jitter {:>9} {}", format_id(*id), stats.frames_per_s, cycle, jitter, name);
+    }
+}
+
+/// DBC identifiers as candump prints them, 8 digits for extended frames
+fn format_id(dbc_id: u32) -> String {
+    if dbc_id & 0x80000000 != 0 {
+        format!("{:08X}", dbc_id & 0x1FFFFFFF)
+    } else {
+        format!("{:03X}", dbc_id)
+    }
+}
+
+fn parse_id(text: &str) -> Result<u32, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    match u32::from_str_radix(digits, 16) {
+        Ok(id) if id <= 0x1FFFFFFF => Ok(id),
+        _ => Err(format!("invalid CAN identifier: {}", text)),
+    }
+}
+
+/// DBC identifier of a candump style ID, 8 digits select the extended format, e.g. `00000123`
+fn parse_frame_id(text: &str) -> Result<u32, String> {
+    let id = parse_id(text)?;
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    if digits.len() == 8 || id > 0x7FF {
+        Ok(id | 0x80000000)
+    } else {
+        Ok(id)
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.replace('.', "");
+    // only ASCII hex digits, byte slicing could split a multi-byte character otherwise
+    if text.len() % 2 != 0 || text.len() > 128 || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("invalid frame data: {}", text));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| format!("invalid frame data: {}", text)))
+        .collect()
+}
+
+fn parse_assignment(text: &str) -> Result<(String, f64), String> {
+    match text.split_once('=').map(|(signal, value)| (signal, value.parse::<f64>())) {
+        Some((signal, Ok(value))) => Ok((signal.to_string(), value)),
+        _ => Err(format!("expected <signal>=<value>: {}", text)),
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, StreamExt};
+use log::{error, info, warn};
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, QoS};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, MissedTickBehavior};
+use crate::can_error::CanToolError;
+use crate::can_tool::{CanUtils, SignalUpdate};
+
+/// Payload format of a telemetry batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryFormat {
+    /// One JSON object per batch, terminated by a newline
+    JsonLines,
+    /// The same structure as a CBOR item, batches appended to a file form a CBOR sequence
+    Cbor,
+}
+
+/// Batching and downsampling of the telemetry bridge
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    /// Identifies the vehicle or gateway in every batch
+    pub source: String,
+    pub format: TelemetryFormat,
+    /// Signals to forward, empty forwards all decoded and virtual signals
+    pub signals: HashSet<String>,
+    /// Minimum time between two samples of a signal, zero keeps every sample
+    pub min_interval: Duration,
+    /// Per signal overrides of `min_interval`
+    pub signal_intervals: HashMap<String, Duration>,
+    /// A batch is published once it holds this many samples
+    pub max_batch_samples: usize,
+    /// A non-empty batch is published at least this often, at most every millisecond
+    pub max_batch_age: Duration,
+    /// Encoded batches kept while the sink fails, the oldest are dropped beyond
+    pub max_pending: usize,
+}
+
+impl BridgeConfig {
+    pub fn new(source: &str, format: TelemetryFormat) -> Self {
+        BridgeConfig {
+            source: source.to_string(),
+            format,
+            signals: HashSet::new(),
+            min_interval: Duration::from_secs(1),
+            signal_intervals: HashMap::new(),
+            max_batch_samples: 500,
+            max_batch_age: Duration::from_secs(10),
+            max_pending: 100,
+        }
+    }
+
+    fn interval(&self, signal: &str) -> Duration {
+        self.signal_intervals.get(signal).copied().unwrap_or(self.min_interval)
+    }
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self::new("cantool", TelemetryFormat::JsonLines)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetrySample {
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// Samples collected between two publishes, grouped by signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryBatch {
+    pub source: String,
+    /// Increments with every batch, gaps show batches lost on the way
+    pub sequence: u64,
+    pub signals: BTreeMap<String, Vec<TelemetrySample>>,
+}
+
+impl TelemetryBatch {
+    pub fn sample_count(&self) -> usize {
+        self.signals.values().map(Vec::len).sum()
+    }
+
+    /// `{"src": .., "seq": .., "signals": {"<name>": [[<unix ms>, <value>], ..]}}`,
+    /// the same structure is used for both formats
+    pub fn to_value(&self) -> Value {
+        let signals: serde_json::Map<String, Value> = self
+            .signals
+            .iter()
+            .map(|(name, samples)| {
+                let samples = samples
+                    .iter()
+                    .map(|sample| json!([sample.timestamp.timestamp_millis(), sample.value]))
+                    .collect();
+                (name.clone(), Value::Array(samples))
+            })
+            .collect();
+
+        json!({
+            "src": self.source,
+            "seq": self.sequence,
+            "signals": signals,
+        })
+    }
+
+    pub fn encode(&self, format: TelemetryFormat) -> Result<Vec<u8>, CanToolError> {
+        let value = self.to_value();
+        match format {
+            TelemetryFormat::JsonLines => {
+                let mut payload =
+                    serde_json::to_vec(&value).map_err(|e| CanToolError::EncodeFailed(e.to_string()))?;
+                payload.push(b'\n');
+                Ok(payload)
+            }
+            TelemetryFormat::Cbor => {
+                let mut payload = Vec::new();
+                ciborium::into_writer(&value, &mut payload).map_err(|e| CanToolError::EncodeFailed(e.to_string()))?;
+                Ok(payload)
+            }
+        }
+    }
+}
+
+/// Collects downsampled signal updates into batches
+#[derive(Debug)]
+pub struct TelemetryBatcher {
+    config: BridgeConfig,
+    signals: BTreeMap<String, Vec<TelemetrySample>>,
+    samples: usize,
+    last_sample: HashMap<String, DateTime<Utc>>,
+    sequence: u64,
+    dropped: u64,
+}
+
+impl TelemetryBatcher {
+    pub fn new(config: BridgeConfig) -> Self {
+        TelemetryBatcher {
+            config,
+            signals: BTreeMap::new(),
+            samples: 0,
+            last_sample: HashMap::new(),
+            sequence: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Adds the selected signals of an update. A sample is dropped when it follows the last
+    /// kept sample of its signal within the signal's interval, returns the number of samples kept.
+    pub fn push(&mut self, update: &SignalUpdate) -> usize {
+        let mut kept = 0;
+
+        for (name, value) in update.signals.iter() {
+            if !self.config.signals.is_empty() && !self.config.signals.contains(name) {
+                continue;
+            }
+
+            let interval = self.config.interval(name);
+            if let Some(last) = self.last_sample.get(name) {
+                // a timestamp going backwards keeps the sample
+                if (update.timestamp - *last).to_std().is_ok_and(|elapsed| elapsed < interval) {
+                    self.dropped += 1;
+                    continue;
+                }
+            }
+
+            self.last_sample.insert(name.clone(), update.timestamp);
+            self.signals
+                .entry(name.clone())
+                .or_default()
+                .push(TelemetrySample { timestamp: update.timestamp, value: *value });
+            kept += 1;
+        }
+
+        self.samples += kept;
+        kept
+    }
+
+    /// Number of samples in the current batch
+    pub fn len(&self) -> usize {
+        self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Whether the current batch reached `max_batch_samples`
+    pub fn is_full(&self) -> bool {
+        self.samples >= self.config.max_batch_samples.max(1)
+    }
+
+    /// Samples dropped by downsampling so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Takes the current batch, None if it is empty
+    pub fn take_batch(&mut self) -> Option<TelemetryBatch> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let batch = TelemetryBatch {
+            source: self.config.source.clone(),
+            sequence: self.sequence,
+            signals: std::mem::take(&mut self.signals),
+        };
+        self.sequence += 1;
+        self.samples = 0;
+        Some(batch)
+    }
+}
+
+/// Destination of encoded telemetry batches
+pub trait TelemetrySink: Send {
+    fn publish<'a>(&'a mut self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), CanToolError>>;
+}
+
+/// Appends batches to a local file, e.g. for tests or as an offline buffer
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    file: tokio::fs::File,
+}
+
+impl FileSink {
+    /// Opens `path` for appending, the file is created if necessary
+    pub async fn open(path: &Path) -> Result<Self, CanToolError> {
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        info!("Telemetry file sink writing to {}", path.display());
+        Ok(FileSink { path: path.to_path_buf(), file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TelemetrySink for FileSink {
+    fn publish<'a>(&'a mut self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), CanToolError>> {
+        async move {
+            self.file.write_all(payload).await?;
+            self.file.flush().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Broker connection of the MQTT sink
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// Topic the batches are published to, e.g. `vehicles/<vin>/telemetry`
+    pub topic: String,
+    pub qos: QoS,
+    pub keep_alive: Duration,
+    /// Requests queued while the broker is unreachable
+    pub queue_capacity: usize,
+}
+
+impl MqttConfig {
+    pub fn new(host: &str, port: u16, client_id: &str, topic: &str) -> Self {
+        MqttConfig {
+            host: host.to_string(),
+            port,
+            client_id: client_id.to_string(),
+            topic: topic.to_string(),
+            qos: QoS::AtLeastOnce,
+            keep_alive: Duration::from_secs(30),
+            queue_capacity: 100,
+        }
+    }
+}
+
+/// Publishes batches to an MQTT broker, reconnecting in the background.
+/// `publish` succeeds once the batch is queued to the client, it does not wait for the broker.
+/// Batches queued while the connection is down are lost if the sink is dropped.
+#[derive(Debug)]
+pub struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+    task: JoinHandle<()>,
+}
+
+impl MqttSink {
+    /// Starts the connection task, the broker does not have to be reachable yet
+    pub fn connect(config: &MqttConfig) -> Self {
+        let mut options = MqttOptions::new(config.client_id.clone(), config.host.clone(), config.port);
+        options.set_keep_alive(config.keep_alive);
+
+        let (client, eventloop) = AsyncClient::new(options, config.queue_capacity.max(1));
+        let task = tokio::spawn(Self::run(eventloop));
+
+        info!("MQTT sink publishing to {} on {}:{}", config.topic, config.host, config.port);
+        MqttSink { client, topic: config.topic.clone(), qos: config.qos, task }
+    }
+
+    async fn run(mut eventloop: EventLoop) {
+        loop {
+            if let Err(e) = eventloop.poll().await {
+                warn!("MQTT connection failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+impl TelemetrySink for MqttSink {
+    fn publish<'a>(&'a mut self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), CanToolError>> {
+        async move {
+            self.client
+                .try_publish(self.topic.clone(), self.qos, false, payload.to_vec())
+                .map_err(|e| CanToolError::Publish(e.to_string()))
+        }
+        .boxed()
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Counters of a running bridge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BridgeStats {
+    /// Batches accepted by the sink, for `MqttSink` queued rather than delivered
+    pub batches_published: u64,
+    pub samples_published: u64,
+    /// Samples dropped by downsampling
+    pub samples_downsampled: u64,
+    pub publish_errors: u64,
+    /// Batches dropped because `max_pending` was exceeded
+    pub batches_dropped: u64,
+    /// Batches waiting for the sink
+    pub pending: usize,
+}
+
+/// Forwards the decoded signal stream of `CanUtils` to a telemetry sink
+#[derive(Debug)]
+pub struct TelemetryBridge {
+    stats: Arc<Mutex<BridgeStats>>,
+    task: JoinHandle<()>,
+}
+
+impl TelemetryBridge {
+    /// Moves `can_utils` into a background task that batches its updates and publishes them to `sink`
+    pub fn spawn(mut can_utils: CanUtils, config: BridgeConfig, mut sink: Box<dyn TelemetrySink>) -> Self {
+        let stats = Arc::new(Mutex::new(BridgeStats::default()));
+
+        let task_stats = stats.clone();
+        let task = tokio::spawn(async move {
+            let mut publisher = Publisher {
+                format: config.format,
+                max_pending: config.max_pending.max(1),
+                pending: VecDeque::new(),
+                stats: task_stats,
+            };
+            // interval panics on a zero period
+            let mut ticker = tokio::time::interval(config.max_batch_age.max(Duration::from_millis(1)));
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut batcher = TelemetryBatcher::new(config);
+            let mut signals = can_utils.signal_stream();
+
+            loop {
+                tokio::select! {
+                    update = signals.next() => {
+                        let Some(update) = update else {
+                            break;
+                        };
+                        batcher.push(&update);
+                        publisher.lock().samples_downsampled = batcher.dropped();
+                        if batcher.is_full() {
+                            publisher.publish(batcher.take_batch(), sink.as_mut()).await;
+                        }
+                    }
+                    _ = ticker.tick() => {
+                        publisher.publish(batcher.take_batch(), sink.as_mut()).await;
+                    }
+                }
+            }
+
+            publisher.publish(batcher.take_batch(), sink.as_mut()).await;
+            warn!("Telemetry bridge stopped receiving updates.");
+        });
+
+        info!("Telemetry bridge started.");
+        TelemetryBridge { stats, task }
+    }
+
+    pub fn stats(&self) -> BridgeStats {
+        match self.stats.lock() {
+            Ok(stats) => *stats,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+
+    /// Whether the background task is still receiving
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl Drop for TelemetryBridge {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Encodes batches and keeps them until the sink accepted them
+struct Publisher {
+    format: TelemetryFormat,
+    max_pending: usize,
+    pending: VecDeque<(usize, Vec<u8>)>,
+    stats: Arc<Mutex<BridgeStats>>,
+}
+
+impl Publisher {
+    async fn publish(&mut self, batch: Option<TelemetryBatch>, sink: &mut dyn TelemetrySink) {
+        if let Some(batch) = batch {
+            match batch.encode(self.format) {
+                Ok(payload) => self.pending.push_back((batch.sample_count(), payload)),
+                Err(e) => error!("Failed to encode telemetry batch {}: {}", batch.sequence, e),
+            }
+        }
+
+        while self.pending.len() > self.max_pending {
+            self.pending.pop_front();
+            self.lock().batches_dropped += 1;
+        }
+
+        while let Some((samples, payload)) = self.pending.front() {
+            if let Err(e) = sink.publish(payload).await {
+                warn!("Failed to publish telemetry, {} batches pending: {}", self.pending.len(), e);
+                self.lock().publish_errors += 1;
+                break;
+            }
+
+            let samples = *samples as u64;
+            self.pending.pop_front();
+            let mut stats = self.lock();
+            stats.batches_published += 1;
+            stats.samples_published += samples;
+        }
+
+        let pending = self.pending.len();
+        self.lock().pending = pending;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BridgeStats> {
+        match self.stats.lock() {
+            Ok(stats) => stats,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
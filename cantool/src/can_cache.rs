@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{info, warn};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use crate::can_tool::CanUtils;
+
+/// Staleness configuration of the signal cache
+#[derive(Debug, Clone, Copy)]
+pub struct SignalCacheConfig {
+    /// Timeout used for messages without a `GenMsgCycleTime` attribute
+    pub default_timeout: Duration,
+    /// Number of missed DBC cycles after which a signal is stale
+    pub cycle_time_factor: u32,
+}
+
+impl Default for SignalCacheConfig {
+    fn default() -> Self {
+        SignalCacheConfig {
+            default_timeout: Duration::from_secs(5),
+            cycle_time_factor: 3,
+        }
+    }
+}
+
+/// Most recent value of a signal
+#[derive(Debug, Clone)]
+pub struct CachedSignal {
+    pub value: f64,
+    pub timestamp: DateTime<Utc>,
+    /// DBC identifier of the message, bit 31 set for extended frames
+    pub frame_id: u32,
+    /// Time since the last update
+    pub age: Duration,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    value: f64,
+    timestamp: DateTime<Utc>,
+    received: Instant,
+    frame_id: u32,
+    timeout: Duration,
+}
+
+impl CacheEntry {
+    fn to_cached(&self, now: Instant) -> CachedSignal {
+        let age = now.saturating_duration_since(self.received);
+        CachedSignal {
+            value: self.value,
+            timestamp: self.timestamp,
+            frame_id: self.frame_id,
+            age,
+            stale: age > self.timeout,
+        }
+    }
+}
+
+/// Latest value cache fed by a background task that owns the CAN socket
+#[derive(Debug)]
+pub struct SignalCache {
+    entries: Arc<RwLock<HashMap<String, CacheEntry>>>,
+    task: JoinHandle<()>,
+}
+
+impl SignalCache {
+    /// Moves `can_utils` into a background task that keeps the cache up to date
+    pub fn spawn(mut can_utils: CanUtils, config: SignalCacheConfig) -> Self {
+        let entries: Arc<RwLock<HashMap<String, CacheEntry>>> = Arc::new(RwLock::new(HashMap::new()));
+
+        // Per frame ID timeouts, derived from the DBC cycle times where available
+        let timeouts: HashMap<u32, Duration> = can_utils
+            .dbc_info()
+            .messages()
+            .filter_map(|message| {
+                can_utils
+                    .dbc_info()
+                    .cycle_time(message.id)
+                    .map(|cycle| (message.id, cycle * config.cycle_time_factor))
+            })
+            .collect();
+
+        let task_entries = entries.clone();
+        let task = tokio::spawn(async move {
+            let mut signals = can_utils.signal_stream();
+            while let Some(update) = signals.next().await {
+                let received = Instant::now();
+                let timeout = timeouts
+                    .get(&update.frame_id)
+                    .copied()
+                    .unwrap_or(config.default_timeout);
+
+                let mut entries = match task_entries.write() {
+                    Ok(entries) => entries,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                for (signal, value) in update.signals {
+                    entries.insert(
+                        signal,
+                        CacheEntry {
+                            value,
+                            timestamp: update.timestamp,
+                            received,
+                            frame_id: update.frame_id,
+                            timeout,
+                        },
+                    );
+                }
+            }
+            warn!("Signal cache stopped receiving updates.");
+        });
+
+        info!("Signal cache started.");
+        SignalCache { entries, task }
+    }
+
+    /// Latest value of a signal, None if it was never received
+    pub fn get(&self, signal: &str) -> Option<CachedSignal> {
+        let now = Instant::now();
+        match self.entries.read() {
+            Ok(entries) => entries.get(signal).map(|entry| entry.to_cached(now)),
+            Err(poisoned) => poisoned.into_inner().get(signal).map(|entry| entry.to_cached(now)),
+        }
+    }
+
+    /// Copy of all cached signals
+    pub fn snapshot(&self) -> HashMap<String, CachedSignal> {
+        let now = Instant::now();
+        let entries = match self.entries.read() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        entries
+            .iter()
+            .map(|(signal, entry)| (signal.clone(), entry.to_cached(now)))
+            .collect()
+    }
+
+    /// Names of all signals that exceeded their timeout
+    pub fn stale_signals(&self) -> Vec<String> {
+        self.snapshot()
+            .into_iter()
+            .filter(|(_, signal)| signal.stale)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Whether the background task is still receiving
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl Drop for SignalCache {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
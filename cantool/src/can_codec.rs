@@ -0,0 +1,165 @@
+use canparse::pgn::SpnDefinition;
+use crate::can_error::CanToolError;
+
+/// Raw integer of a signal together with its physical value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodedSignal {
+    /// Sign extended for signed signals
+    pub raw: i64,
+    pub value: f64,
+}
+
+/// Bit layout and scaling of a signal inside a CAN payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalLayout {
+    pub start_bit: usize,
+    pub bit_len: usize,
+    pub little_endian: bool,
+    pub signed: bool,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl From<&SpnDefinition> for SignalLayout {
+    fn from(spn: &SpnDefinition) -> Self {
+        SignalLayout {
+            start_bit: *spn.start_bit(),
+            bit_len: *spn.bit_len(),
+            little_endian: *spn.little_endian(),
+            signed: *spn.signed(),
+            scale: *spn.scale() as f64,
+            offset: *spn.offset() as f64,
+        }
+    }
+}
+
+impl SignalLayout {
+    /// Returns the payload bit positions of the signal, most significant bit first
+    fn bit_positions(&self) -> Vec<usize> {
+        let mut positions = Vec::with_capacity(self.bit_len);
+
+        if self.little_endian {
+            // Intel: start bit is the LSB and bits grow upwards
+            for i in (0..self.bit_len).rev() {
+                positions.push(self.start_bit + i);
+            }
+        } else {
+            // Motorola: start bit is the MSB, walking the DBC sawtooth numbering
+            let mut pos = self.start_bit;
+            for _ in 0..self.bit_len {
+                positions.push(pos);
+                if pos % 8 == 0 {
+                    pos += 15;
+                } else {
+                    pos -= 1;
+                }
+            }
+        }
+
+        positions
+    }
+
+    /// Number of payload bytes needed to hold the signal
+    pub fn required_len(&self) -> usize {
+        self.bit_positions()
+            .into_iter()
+            .max()
+            .map(|bit| bit / 8 + 1)
+            .unwrap_or(0)
+    }
+
+    /// Converts a physical value into the raw bit pattern of the signal, clamped to its range
+    pub fn to_raw(&self, value: f64) -> u64 {
+        let bits = self.bit_len.min(64) as u32;
+        if bits == 0 {
+            return 0;
+        }
+        let raw = ((value - self.offset) / self.scale).round();
+
+        if self.signed {
+            let max = ((1i128 << (bits - 1)) - 1) as f64;
+            let min = -((1i128 << (bits - 1)) as f64);
+            let raw = raw.clamp(min, max) as i64;
+            (raw as u64) & mask(bits)
+        } else {
+            let max = mask(bits) as f64;
+            raw.clamp(0.0, max) as u64
+        }
+    }
+
+    /// Payload bytes touched by the signal
+    pub fn byte_positions(&self) -> Vec<usize> {
+        let mut bytes: Vec<usize> = self.bit_positions().into_iter().map(|pos| pos / 8).collect();
+        bytes.sort_unstable();
+        bytes.dedup();
+        bytes
+    }
+
+    /// Writes a physical value into the payload
+    pub fn encode(&self, value: f64, data: &mut [u8]) -> Result<(), CanToolError> {
+        self.encode_raw(self.to_raw(value), data)
+    }
+
+    /// Writes a raw bit pattern into the payload, e.g. a counter or checksum
+    pub fn encode_raw(&self, raw: u64, data: &mut [u8]) -> Result<(), CanToolError> {
+        if self.bit_len == 0 || self.bit_len > 64 || self.required_len() > data.len() {
+            return Err(CanToolError::EncodeFailed("Signal does not fit into the message payload.".to_string()));
+        }
+
+        for (i, pos) in self.bit_positions().into_iter().enumerate() {
+            let bit = (raw >> (self.bit_len - 1 - i)) & 1;
+            let (byte, shift) = (pos / 8, pos % 8);
+            if bit == 1 {
+                data[byte] |= 1 << shift;
+            } else {
+                data[byte] &= !(1 << shift);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the raw bit pattern of the signal from a payload of any length
+    pub fn raw_value(&self, data: &[u8]) -> Option<u64> {
+        if self.bit_len == 0 || self.bit_len > 64 || self.required_len() > data.len() {
+            return None;
+        }
+
+        let mut raw = 0u64;
+        for pos in self.bit_positions() {
+            raw = (raw << 1) | ((data[pos / 8] >> (pos % 8)) & 1) as u64;
+        }
+
+        Some(raw)
+    }
+
+    /// Reads the raw integer and physical value of the signal from a payload of any length
+    pub fn decode_signal(&self, data: &[u8]) -> Option<DecodedSignal> {
+        let raw = self.raw_value(data)?;
+        let bits = self.bit_len as u32;
+
+        let raw = if self.signed && bits < 64 && (raw >> (bits - 1)) & 1 == 1 {
+            // sign extend two's complement
+            (raw | !mask(bits)) as i64
+        } else {
+            raw as i64
+        };
+
+        // unsigned 64 bit patterns above i64::MAX are scaled as unsigned
+        let unscaled = if self.signed { raw as f64 } else { raw as u64 as f64 };
+        Some(DecodedSignal { raw, value: unscaled * self.scale + self.offset })
+    }
+
+    /// Reads the physical value of the signal from a payload of any length
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        self.decode_signal(data).map(|decoded| decoded.value)
+    }
+}
+
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use log::{error, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::can_codec::{DecodedSignal, SignalLayout};
+use crate::can_error::CanToolError;
+
+/// Multiplexing role of a signal, `M` or `m<value>` in the `SG_` line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    /// Selects which multiplexed signals the payload carries
+    Multiplexor,
+    /// Present only while the multiplexor has this value
+    Multiplexed(u64),
+}
+
+/// Signal definition parsed from a DBC `SG_` line with the exact scale and offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbcSignal {
+    pub name: String,
+    pub layout: SignalLayout,
+    pub multiplex: Option<Multiplex>,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+}
+
+/// Message definition scanned from a DBC `BO_` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbcMessage {
+    pub id: u32,
+    pub name: String,
+    pub dlc: usize,
+    pub signals: Vec<String>,
+    /// Parsed definitions of `signals`, lines that fail to parse are left out
+    pub definitions: Vec<DbcSignal>,
+}
+
+impl DbcMessage {
+    /// Decodes the signals carried by a payload, multiplexed signals only when the
+    /// multiplexor selects them. Extended multiplexing (`SG_MUL_VAL_`) is not supported.
+    pub fn decode(&self, data: &[u8]) -> Result<HashMap<String, DecodedSignal>, CanToolError> {
+        let selector = match self.definitions.iter().find(|signal| signal.multiplex == Some(Multiplex::Multiplexor)) {
+            Some(multiplexor) => Some(
+                multiplexor
+                    .layout
+                    .raw_value(data)
+                    .ok_or_else(|| CanToolError::DecodeFailed(multiplexor.name.clone()))?,
+            ),
+            None => None,
+        };
+
+        self.definitions
+            .iter()
+            .filter(|signal| match signal.multiplex {
+                Some(Multiplex::Multiplexed(value)) => selector == Some(value),
+                _ => true,
+            })
+            .map(|signal| match signal.layout.decode_signal(data) {
+                Some(decoded) => Ok((signal.name.clone(), decoded)),
+                None => Err(CanToolError::DecodeFailed(signal.name.clone())),
+            })
+            .collect()
+    }
+}
+
+/// Message level information of a DBC file that `PgnLibrary` does not expose
+#[derive(Debug, Clone, Default)]
+pub struct DbcInfo {
+    messages: HashMap<u32, DbcMessage>,
+    names: HashMap<String, u32>,
+    /// Message of every signal name, the first one for names used in several messages
+    signal_ids: HashMap<String, u32>,
+    cycle_times: HashMap<u32, u64>,
+    /// `VAL_` tables by message ID and signal, names may be reused across messages
+    value_tables: HashMap<(u32, String), BTreeMap<i64, String>>,
+}
+
+impl DbcInfo {
+    /// Loads the message table from a DBC file
+    pub fn from_dbc_file(path: &Path) -> io::Result<Self> {
+        let content = fs::read(path)?;
+        Ok(Self::from_dbc_str(&String::from_utf8_lossy(&content)))
+    }
+
+    /// Parses the message table from DBC text
+    pub fn from_dbc_str(dbc: &str) -> Self {
+        let mut info = DbcInfo::default();
+        let mut current: Option<u32> = None;
+
+        for line in dbc.lines() {
+            let line = line.trim();
+
+            if let Some(rest) = line.strip_prefix("BO_ ") {
+                current = None;
+                // BO_ <id> <name>: <dlc> <transmitter>
+                let mut parts = rest.split_whitespace();
+                let id = parts.next().and_then(|s| s.parse::<u32>().ok());
+                let name = parts.next().map(|s| s.trim_end_matches(':').to_string());
+                let dlc = parts.next().and_then(|s| s.parse::<usize>().ok());
+
+                if let (Some(id), Some(name), Some(dlc)) = (id, name, dlc) {
+                    info.names.insert(name.clone(), id);
+                    info.messages.insert(
+                        id,
+                        DbcMessage { id, name, dlc, signals: Vec::new(), definitions: Vec::new() },
+                    );
+                    current = Some(id);
+                }
+            } else if let Some(rest) = line.strip_prefix("SG_ ") {
+                // SG_ <name> [mux] : <layout> ...
+                if let (Some(id), Some(name)) = (current, rest.split_whitespace().next()) {
+                    if let Some(message) = info.messages.get_mut(&id) {
+                        message.signals.push(name.to_string());
+                        match parse_signal(rest) {
+                            Some(signal) => message.definitions.push(signal),
+                            None => warn!("Cannot parse definition of signal {}", name),
+                        }
+                        info.signal_ids.entry(name.to_string()).or_insert(id);
+                    }
+                }
+            } else if let Some(rest) = line.strip_prefix("BA_ \"GenMsgCycleTime\" BO_ ") {
+                // BA_ "GenMsgCycleTime" BO_ <id> <ms>;
+                let mut parts = rest.trim_end_matches(';').split_whitespace();
+                let id = parts.next().and_then(|s| s.parse::<u32>().ok());
+                let cycle = parts.next().and_then(|s| s.parse::<u64>().ok());
+
+                if let (Some(id), Some(cycle)) = (id, cycle) {
+                    info.cycle_times.insert(id, cycle);
+                }
+            } else if let Some(rest) = line.strip_prefix("VAL_ ") {
+                // VAL_ <id> <signal> <value> "<text>" ... ;
+                if let Some((id, signal, table)) = parse_value_table(rest) {
+                    info.value_tables.insert((id, signal), table);
+                }
+            } else if line.is_empty() {
+                current = None;
+            }
+        }
+
+        info
+    }
+
+    /// Looks up a message by its DBC name
+    pub fn message(&self, name: &str) -> Option<&DbcMessage> {
+        self.names.get(name).and_then(|id| self.messages.get(id))
+    }
+
+    /// Looks up a message by its DBC identifier (bit 31 set for extended frames)
+    pub fn message_by_id(&self, id: u32) -> Option<&DbcMessage> {
+        self.messages.get(&id)
+    }
+
+    /// Definition of a signal, the first one for names used in several messages
+    pub fn signal(&self, name: &str) -> Option<&DbcSignal> {
+        let message = self.messages.get(self.signal_ids.get(name)?)?;
+        message.definitions.iter().find(|signal| signal.name == name)
+    }
+
+    /// Transmission period of a message from the `GenMsgCycleTime` attribute
+    pub fn cycle_time(&self, id: u32) -> Option<Duration> {
+        match self.cycle_times.get(&id) {
+            Some(0) | None => None,
+            Some(ms) => Some(Duration::from_millis(*ms)),
+        }
+    }
+
+    /// Iterates over all messages of the DBC
+    pub fn messages(&self) -> impl Iterator<Item = &DbcMessage> {
+        self.messages.values()
+    }
+
+    /// Raw value to text table from `VAL_` of a signal in the message with DBC identifier `id`
+    pub fn value_table(&self, id: u32, signal: &str) -> Option<&BTreeMap<i64, String>> {
+        self.value_tables.get(&(id, signal.to_string()))
+    }
+
+    /// Text of a raw signal value, e.g. "DRIVE" for a gear signal
+    pub fn value_text(&self, id: u32, signal: &str, raw: i64) -> Option<&str> {
+        self.value_table(id, signal)?.get(&raw).map(String::as_str)
+    }
+}
+
+/// Parses `<name> [M|m<value>] : <start>|<len>@<order><sign> (<scale>,<offset>) [<min>|<max>] "<unit>" ...`
+fn parse_signal(rest: &str) -> Option<DbcSignal> {
+    let (head, tail) = rest.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_string();
+    let multiplex = match head.next() {
+        None => None,
+        Some("M") => Some(Multiplex::Multiplexor),
+        // m<value>M of extended multiplexing is handled as a plain multiplexed signal
+        Some(mux) => Some(Multiplex::Multiplexed(mux.strip_prefix('m')?.trim_end_matches('M').parse().ok()?)),
+    };
+
+    let (bits, tail) = tail.trim_start().split_once(char::is_whitespace)?;
+    let (start_bit, bits) = bits.split_once('|')?;
+    let (bit_len, format) = bits.split_once('@')?;
+
+    let (factors, tail) = tail.trim_start().strip_prefix('(')?.split_once(')')?;
+    let (scale, offset) = factors.split_once(',')?;
+    let (range, tail) = tail.trim_start().strip_prefix('[')?.split_once(']')?;
+    let (min, max) = range.split_once('|')?;
+    let unit = tail
+        .trim_start()
+        .strip_prefix('"')
+        .and_then(|unit| unit.split_once('"'))
+        .map(|(unit, _)| unit.to_string())
+        .unwrap_or_default();
+
+    Some(DbcSignal {
+        name,
+        layout: SignalLayout {
+            start_bit: start_bit.trim().parse().ok()?,
+            bit_len: bit_len.trim().parse().ok()?,
+            little_endian: format.starts_with('1'),
+            signed: format.ends_with('-'),
+            scale: scale.trim().parse().ok()?,
+            offset: offset.trim().parse().ok()?,
+        },
+        multiplex,
+        min: min.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        unit,
+    })
+}
+
+fn parse_value_table(rest: &str) -> Option<(u32, String, BTreeMap<i64, String>)> {
+    let mut parts = rest.trim().splitn(3, char::is_whitespace);
+    let id = parts.next()?.parse::<u32>().ok()?;
+    let signal = parts.next()?.to_string();
+    let mut rest = parts.next()?.trim().trim_end_matches(';').trim_end();
+
+    let mut table = BTreeMap::new();
+    while !rest.is_empty() {
+        let (value, tail) = rest.split_once('"')?;
+        let (text, tail) = tail.split_once('"')?;
+        table.insert(value.trim().parse::<i64>().ok()?, text.to_string());
+        rest = tail.trim_start();
+    }
+
+    Some((id, signal, table))
+}
+
+/// Flags changes of a DBC file, the parent directory is watched so that
+/// files replaced by rename (as OTA updates do) are still noticed
+pub struct DbcWatcher {
+    _watcher: RecommendedWatcher,
+    changed: Arc<AtomicBool>,
+}
+
+impl DbcWatcher {
+    pub fn new(path: &Path) -> notify::Result<Self> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let file_name: Option<OsString> = path.file_name().map(|name| name.to_os_string());
+        let flag = changed.clone();
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+                let ours = event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name().map(|name| name.to_os_string()) == file_name);
+
+                if relevant && ours {
+                    flag.store(true, Ordering::Release);
+                }
+            }
+            Err(e) => error!("DBC watch error: {}", e),
+        })?;
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(DbcWatcher { _watcher: watcher, changed })
+    }
+
+    /// Whether the file changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl std::fmt::Debug for DbcWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DbcWatcher").finish_non_exhaustive()
+    }
+}
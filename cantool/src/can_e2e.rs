@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use log::warn;
+use crate::can_codec::SignalLayout;
+use crate::can_error::CanToolError;
+use crate::can_scheduler::{ChecksumFn, CounterSignal};
+use crate::can_tool::CanUtils;
+
+/// Checksum algorithms, the bytes of the checksum signal are left out of the calculation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Xor,
+    /// CRC-8 SAE J1850, polynomial 0x1D, init and final XOR 0xFF
+    Crc8SaeJ1850,
+    /// AUTOSAR E2E profile 1, CRC-8 SAE J1850 over both data ID bytes and the payload
+    /// with start value and final XOR 0x00
+    AutosarP1 { data_id: u16 },
+    /// AUTOSAR E2E profile 2, CRC-8H2F over the payload and the data ID selected by the counter
+    AutosarP2 { data_ids: [u8; 16] },
+}
+
+impl ChecksumAlgorithm {
+    /// Checksum of `data` without the bytes in `skip`, `counter` selects the profile 2 data ID
+    pub fn compute(&self, data: &[u8], skip: &[usize], counter: u64) -> u8 {
+        let bytes = data
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !skip.contains(i))
+            .map(|(_, byte)| *byte);
+
+        match self {
+            ChecksumAlgorithm::Xor => bytes.fold(0, |acc, byte| acc ^ byte),
+            ChecksumAlgorithm::Crc8SaeJ1850 => crc8(0x1D, 0xFF, bytes) ^ 0xFF,
+            ChecksumAlgorithm::AutosarP1 { data_id } => {
+                let id = data_id.to_le_bytes();
+                crc8(0x1D, 0x00, id.into_iter().chain(bytes))
+            }
+            ChecksumAlgorithm::AutosarP2 { data_ids } => {
+                let id = data_ids[(counter & 0x0F) as usize];
+                crc8(0x2F, 0xFF, bytes.chain(std::iter::once(id))) ^ 0xFF
+            }
+        }
+    }
+}
+
+fn crc8(poly: u8, init: u8, bytes: impl Iterator<Item = u8>) -> u8 {
+    bytes.fold(init, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ poly } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// What happens to frames that fail a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2eAction {
+    /// Deliver the signals with the failed status
+    Flag,
+    /// Do not deliver the signals
+    Drop,
+}
+
+/// Checksum signal of a protected message
+#[derive(Debug, Clone)]
+pub struct E2eChecksum {
+    pub signal: String,
+    pub algorithm: ChecksumAlgorithm,
+}
+
+/// End-to-end protection of one DBC message
+#[derive(Debug, Clone)]
+pub struct E2eConfig {
+    pub counter: Option<CounterSignal>,
+    pub checksum: Option<E2eChecksum>,
+    /// Largest accepted counter increment, 1 accepts no lost frames
+    pub max_delta: u64,
+    pub action: E2eAction,
+}
+
+impl Default for E2eConfig {
+    fn default() -> Self {
+        E2eConfig { counter: None, checksum: None, max_delta: 1, action: E2eAction::Flag }
+    }
+}
+
+/// Result of checking a received frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2eStatus {
+    Ok,
+    /// First frame, the counter cannot be checked yet
+    Initial,
+    /// Same counter as the previous frame
+    Repeated,
+    WrongSequence { expected: u64, received: u64 },
+    ChecksumError { expected: u64, received: u64 },
+}
+
+impl E2eStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, E2eStatus::Ok | E2eStatus::Initial)
+    }
+}
+
+/// Check results of a protected message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct E2eStats {
+    pub ok: u64,
+    pub repeated: u64,
+    pub wrong_sequence: u64,
+    pub checksum_errors: u64,
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedChecksum {
+    layout: SignalLayout,
+    algorithm: ChecksumAlgorithm,
+    skip: Vec<usize>,
+}
+
+impl ResolvedChecksum {
+    fn compute(&self, data: &[u8], counter: Option<&SignalLayout>) -> u64 {
+        let counter = counter.and_then(|layout| layout.raw_value(data)).unwrap_or(0);
+        self.algorithm.compute(data, &self.skip, counter) as u64
+    }
+}
+
+/// Counter and checksum state of one message for receive checks and transmit protection
+#[derive(Debug, Clone)]
+pub struct E2eProtection {
+    counter: Option<(SignalLayout, u64)>,
+    checksum: Option<ResolvedChecksum>,
+    max_delta: u64,
+    action: E2eAction,
+    last_counter: Option<u64>,
+    tx_counter: u64,
+    stats: E2eStats,
+}
+
+impl E2eProtection {
+    /// Resolves the counter and checksum signals through the DBC of `can_utils`
+    pub fn new(config: &E2eConfig, can_utils: &CanUtils) -> Result<Self, CanToolError> {
+        let layout = |signal: &str| {
+            can_utils
+                .signal_layout(signal)
+                .ok_or_else(|| CanToolError::SignalNotInDbc(signal.to_string()))
+        };
+
+        let counter = match config.counter.as_ref() {
+            Some(counter) => Some((layout(&counter.signal)?, counter.modulo.max(1))),
+            None => None,
+        };
+        let checksum = match config.checksum.as_ref() {
+            Some(checksum) => {
+                let layout = layout(&checksum.signal)?;
+                Some(ResolvedChecksum { skip: layout.byte_positions(), layout, algorithm: checksum.algorithm })
+            }
+            None => None,
+        };
+
+        Ok(E2eProtection {
+            counter,
+            checksum,
+            max_delta: config.max_delta.max(1),
+            action: config.action,
+            last_counter: None,
+            tx_counter: 0,
+            stats: E2eStats::default(),
+        })
+    }
+
+    /// Checks a received payload and updates the statistics
+    pub fn verify(&mut self, data: &[u8]) -> E2eStatus {
+        let status = self.check(data);
+        match status {
+            E2eStatus::Ok | E2eStatus::Initial => self.stats.ok += 1,
+            E2eStatus::Repeated => self.stats.repeated += 1,
+            E2eStatus::WrongSequence { .. } => self.stats.wrong_sequence += 1,
+            E2eStatus::ChecksumError { .. } => self.stats.checksum_errors += 1,
+        }
+        status
+    }
+
+    fn check(&mut self, data: &[u8]) -> E2eStatus {
+        if let Some(checksum) = self.checksum.as_ref() {
+            let received = checksum.layout.raw_value(data).unwrap_or(0);
+            let expected = checksum.compute(data, self.counter.as_ref().map(|(layout, _)| layout));
+            if received != expected {
+                return E2eStatus::ChecksumError { expected, received };
+            }
+        }
+
+        let Some((layout, modulo)) = self.counter.as_ref() else {
+            return E2eStatus::Ok;
+        };
+        let received = layout.raw_value(data).unwrap_or(0);
+        let last = self.last_counter.replace(received);
+
+        match last {
+            None => E2eStatus::Initial,
+            Some(last) => {
+                let delta = (received % modulo + modulo - last % modulo) % modulo;
+                if delta == 0 {
+                    E2eStatus::Repeated
+                } else if delta > self.max_delta {
+                    E2eStatus::WrongSequence { expected: (last + 1) % modulo, received }
+                } else {
+                    E2eStatus::Ok
+                }
+            }
+        }
+    }
+
+    /// Whether a frame with `status` is delivered
+    pub fn accepts(&mut self, status: &E2eStatus) -> bool {
+        if status.is_ok() || self.action == E2eAction::Flag {
+            return true;
+        }
+        self.stats.dropped += 1;
+        false
+    }
+
+    /// Writes the next counter value and the checksum into an encoded payload
+    pub fn protect(&mut self, data: &mut [u8]) -> Result<(), CanToolError> {
+        if let Some((layout, modulo)) = self.counter.as_ref() {
+            layout.encode_raw(self.tx_counter, data)?;
+            self.tx_counter = (self.tx_counter + 1) % modulo;
+        }
+
+        if let Some(checksum) = self.checksum.as_ref() {
+            let value = checksum.compute(data, self.counter.as_ref().map(|(layout, _)| layout));
+            checksum.layout.encode_raw(value, data)?;
+        }
+        Ok(())
+    }
+
+    /// Checksum for a `ChecksumSignal` of the transmit scheduler
+    pub fn checksum_fn(&self) -> Option<ChecksumFn> {
+        let checksum = self.checksum.clone()?;
+        let counter = self.counter;
+        Some(Arc::new(move |data: &[u8]| {
+            checksum.compute(data, counter.as_ref().map(|(layout, _)| layout))
+        }))
+    }
+
+    pub fn stats(&self) -> E2eStats {
+        self.stats
+    }
+}
+
+/// Logs a failed check of a message
+pub(crate) fn log_failure(message_id: u32, status: &E2eStatus) {
+    if !status.is_ok() {
+        warn!("{}", CanToolError::E2eFailed(message_id, *status));
+    }
+}
@@ -0,0 +1,88 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use crate::can_e2e::E2eStatus;
+
+#[derive(Debug)]
+pub enum CanToolError {
+    /// No frame arrived within the receive timeout
+    Timeout,
+    /// Frame ID without a DBC definition, the frame can be skipped
+    UnknownId(u32),
+    SignalNotInDbc(String),
+    MessageNotInDbc(String),
+    DecodeFailed(String),
+    EncodeFailed(String),
+    /// J1939 TP or ISO-TP session failure
+    Protocol(String),
+    Socket(io::Error),
+    /// The socket delivers no more frames, or the interface task has stopped
+    Closed,
+    /// CAN FD frame without an open CAN FD socket, see `CanUtils::enable_fd`
+    FdDisabled,
+    DbcLoad(String),
+    /// Invalid configuration file or definition, e.g. of virtual signals
+    InvalidConfig(String),
+    /// Telemetry sink failed to publish a batch
+    Publish(String),
+    /// Startup or restart was cancelled through the retry policy
+    Cancelled,
+    /// Frame failed its end-to-end check, it is flagged or dropped
+    E2eFailed(u32, E2eStatus),
+}
+
+impl CanToolError {
+    /// Whether the error concerns a single frame only and receiving can continue
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            CanToolError::Timeout
+                | CanToolError::UnknownId(_)
+                | CanToolError::DecodeFailed(_)
+                | CanToolError::E2eFailed(..)
+        )
+    }
+
+    /// Whether opening the socket again may succeed, e.g. once the interface is up or the
+    /// DBC is in place. Invalid configurations and unknown names fail on every attempt.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, CanToolError::Socket(_) | CanToolError::Timeout | CanToolError::DbcLoad(_))
+    }
+}
+
+impl fmt::Display for CanToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanToolError::Timeout => write!(f, "No CAN frame received within the timeout"),
+            CanToolError::UnknownId(id) => write!(f, "Frame ID {:x} is not defined in the DBC", id),
+            CanToolError::SignalNotInDbc(name) => write!(f, "Signal {} is not defined in the DBC", name),
+            CanToolError::MessageNotInDbc(name) => write!(f, "Message {} is not defined in the DBC", name),
+            CanToolError::DecodeFailed(reason) => write!(f, "Decoding failed: {}", reason),
+            CanToolError::EncodeFailed(reason) => write!(f, "Encoding failed: {}", reason),
+            CanToolError::Protocol(reason) => write!(f, "Transport protocol error: {}", reason),
+            CanToolError::Socket(e) => write!(f, "CAN socket error: {}", e),
+            CanToolError::Closed => write!(f, "No more frames available"),
+            CanToolError::FdDisabled => write!(f, "CAN FD is not enabled"),
+            CanToolError::DbcLoad(reason) => write!(f, "Failed to load the DBC: {}", reason),
+            CanToolError::InvalidConfig(reason) => write!(f, "Invalid configuration: {}", reason),
+            CanToolError::Publish(reason) => write!(f, "Failed to publish telemetry: {}", reason),
+            CanToolError::Cancelled => write!(f, "Cancelled"),
+            CanToolError::E2eFailed(id, status) => write!(f, "End-to-end check of frame {:x} failed: {:?}", id, status),
+        }
+    }
+}
+
+impl StdError for CanToolError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            CanToolError::Socket(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CanToolError {
+    fn from(err: io::Error) -> CanToolError {
+        CanToolError::Socket(err)
+    }
+}
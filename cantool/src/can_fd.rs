@@ -0,0 +1,100 @@
+use std::io;
+use std::os::fd::AsRawFd;
+use socketcan::{
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanFilter, EmbeddedFrame, ExtendedId, Frame, Id,
+    Socket, StandardId,
+};
+use tokio::io::unix::AsyncFd;
+use crate::can_filter::joins_filters;
+use crate::can_frame::RawFrame;
+use crate::can_transport::set_join_filters;
+
+/// Async CAN FD raw socket, receives both classic and FD frames
+#[derive(Debug)]
+pub struct CanFdSocket {
+    inner: AsyncFd<socketcan::CanFdSocket>,
+}
+
+impl CanFdSocket {
+    /// Opens a CAN FD socket, the interface must be configured with `mtu 72`
+    pub fn open(ifname: &str) -> io::Result<Self> {
+        let socket = socketcan::CanFdSocket::open(ifname)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(CanFdSocket {
+            inner: AsyncFd::new(socket)?,
+        })
+    }
+
+    /// Applies (id, mask) kernel filters, joined when all of them are inverted
+    pub fn set_filter(&self, filters: &[(u32, u32)]) -> io::Result<()> {
+        let can_filters: Vec<CanFilter> = filters
+            .iter()
+            .map(|(id, mask)| CanFilter::new(*id, *mask))
+            .collect();
+        self.inner.get_ref().set_filters(&can_filters)?;
+        set_join_filters(self.inner.get_ref().as_raw_fd(), joins_filters(filters))
+    }
+
+    /// Waits for the next data frame, remote and error frames are skipped
+    pub async fn read_frame(&self) -> io::Result<RawFrame> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            let frame = match guard.try_io(|inner| inner.get_ref().read_frame()) {
+                Ok(result) => result?,
+                Err(_would_block) => continue,
+            };
+
+            match frame {
+                CanAnyFrame::Normal(frame) => {
+                    return Ok(RawFrame {
+                        id: frame.raw_id(),
+                        extended: frame.is_extended(),
+                        data: frame.data().to_vec(),
+                        fd: false,
+                        brs: false,
+                    });
+                }
+                CanAnyFrame::Fd(frame) => {
+                    return Ok(RawFrame {
+                        id: frame.raw_id(),
+                        extended: frame.is_extended(),
+                        data: frame.data().to_vec(),
+                        fd: true,
+                        brs: frame.is_brs(),
+                    });
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Transmits a frame, classic frames are sent as such on the FD socket
+    pub async fn write_frame(&self, frame: &RawFrame) -> io::Result<()> {
+        let id: Id = if frame.extended {
+            ExtendedId::new(frame.id).map(Id::from)
+        } else {
+            StandardId::new(frame.id as u16).map(Id::from)
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid CAN ID"))?;
+
+        let invalid_data = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid CAN payload");
+
+        loop {
+            let mut guard = self.inner.writable().await?;
+            let result = if frame.fd {
+                let mut fd_frame = CanFdFrame::new(id, &frame.data).ok_or_else(invalid_data)?;
+                fd_frame.set_brs(frame.brs);
+                guard.try_io(|inner| inner.get_ref().write_frame(&fd_frame))
+            } else {
+                let data_frame = CanDataFrame::new(id, &frame.data).ok_or_else(invalid_data)?;
+                guard.try_io(|inner| inner.get_ref().write_frame(&data_frame))
+            };
+
+            match result {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+use crate::can_dbc::DbcInfo;
+use crate::can_error::CanToolError;
+
+/// Extended frame flag of a kernel filter ID and mask
+pub const CAN_EFF_FLAG: u32 = 0x80000000;
+/// Inverts a kernel filter, frames NOT matching it are received
+pub const CAN_INV_FILTER: u32 = 0x20000000;
+pub const CAN_SFF_MASK: u32 = 0x000007FF;
+pub const CAN_EFF_MASK: u32 = 0x1FFFFFFF;
+
+/// Error classes of `linux/can/error.h` for `FrameFilter::Errors`
+pub const CAN_ERR_TX_TIMEOUT: u32 = 0x00000001;
+pub const CAN_ERR_LOSTARB: u32 = 0x00000002;
+pub const CAN_ERR_CRTL: u32 = 0x00000004;
+pub const CAN_ERR_PROT: u32 = 0x00000008;
+pub const CAN_ERR_TRX: u32 = 0x00000010;
+pub const CAN_ERR_ACK: u32 = 0x00000020;
+pub const CAN_ERR_BUSOFF: u32 = 0x00000040;
+pub const CAN_ERR_BUSERROR: u32 = 0x00000080;
+pub const CAN_ERR_RESTARTED: u32 = 0x00000100;
+pub const CAN_ERR_MASK: u32 = 0x1FFFFFFF;
+
+/// Frame selection resolved into kernel filters when the socket is opened
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameFilter {
+    /// Exact 29 bit identifier, as the former `ids_filter`
+    Id(u32),
+    /// Raw kernel filter, `id & mask == frame_id & mask`
+    IdMask { id: u32, mask: u32 },
+    /// The frame carrying a DBC message
+    Message(String),
+    /// The frame carrying a DBC signal
+    Signal(String),
+    /// J1939 parameter group from any source address (and to any destination for PDU1)
+    Pgn(u32),
+    /// Receives everything except the frames matched by the inner filter
+    Inverted(Box<FrameFilter>),
+    /// Error frames of the given `CAN_ERR_*` classes
+    Errors(u32),
+}
+
+impl FrameFilter {
+    pub fn signals<I, S>(names: I) -> Vec<FrameFilter>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        names.into_iter().map(|name| FrameFilter::Signal(name.into())).collect()
+    }
+
+    pub fn inverted(self) -> FrameFilter {
+        FrameFilter::Inverted(Box::new(self))
+    }
+
+    /// Whether the filter refers to DBC names and must be resolved again after a reload
+    pub fn uses_dbc(&self) -> bool {
+        match self {
+            FrameFilter::Message(_) | FrameFilter::Signal(_) => true,
+            FrameFilter::Inverted(inner) => inner.uses_dbc(),
+            _ => false,
+        }
+    }
+
+    /// Kernel (id, mask) pairs of the filter, empty for error filters
+    pub fn resolve(&self, dbc: &DbcInfo) -> Result<Vec<(u32, u32)>, CanToolError> {
+        match self {
+            FrameFilter::Id(id) => Ok(vec![(*id, CAN_EFF_MASK)]),
+            FrameFilter::IdMask { id, mask } => Ok(vec![(*id, *mask)]),
+            FrameFilter::Message(name) => match dbc.message(name) {
+                Some(message) => Ok(vec![dbc_id_filter(message.id)]),
+                None => Err(CanToolError::MessageNotInDbc(name.clone())),
+            },
+            FrameFilter::Signal(name) => {
+                let filters: Vec<(u32, u32)> = dbc
+                    .messages()
+                    .filter(|message| message.signals.contains(name))
+                    .map(|message| dbc_id_filter(message.id))
+                    .collect();
+
+                if filters.is_empty() {
+                    return Err(CanToolError::SignalNotInDbc(name.clone()));
+                }
+                Ok(filters)
+            }
+            FrameFilter::Pgn(pgn) => Ok(vec![pgn_filter(*pgn)]),
+            FrameFilter::Inverted(inner) => Ok(inner
+                .resolve(dbc)?
+                .into_iter()
+                .map(|(id, mask)| (id | CAN_INV_FILTER, mask))
+                .collect()),
+            FrameFilter::Errors(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Whether a filter set consists of inverted filters only. ORed, as the kernel does by
+/// default, they would receive everything, so the socket has to join them
+/// (`CAN_RAW_JOIN_FILTERS`) to exclude the frames matching any of them.
+pub fn joins_filters(filters: &[(u32, u32)]) -> bool {
+    !filters.is_empty() && filters.iter().all(|(id, _)| id & CAN_INV_FILTER != 0)
+}
+
+/// Kernel filters resolved from a set of `FrameFilter`s
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedFilters {
+    /// Deduplicated (id, mask) pairs, empty receives all data frames
+    pub masks: Vec<(u32, u32)>,
+    /// `CAN_ERR_*` classes delivered as error frames, 0 for none
+    pub error_mask: u32,
+}
+
+impl ResolvedFilters {
+    /// Inverted filters cannot be combined with others, the kernel either ORs or joins a set
+    pub fn resolve(filters: &[FrameFilter], dbc: &DbcInfo) -> Result<Self, CanToolError> {
+        let mut masks = BTreeSet::new();
+        let mut error_mask = 0;
+
+        for filter in filters {
+            match filter {
+                FrameFilter::Errors(mask) => error_mask |= mask & CAN_ERR_MASK,
+                _ => masks.extend(filter.resolve(dbc)?),
+            }
+        }
+
+        let masks: Vec<(u32, u32)> = masks.into_iter().collect();
+        if masks.iter().any(|(id, _)| id & CAN_INV_FILTER != 0) && !joins_filters(&masks) {
+            return Err(CanToolError::InvalidConfig(
+                "Inverted filters cannot be combined with other frame filters".to_string(),
+            ));
+        }
+
+        Ok(ResolvedFilters { masks, error_mask })
+    }
+}
+
+/// Filter matching exactly one DBC identifier (bit 31 set for extended frames)
+fn dbc_id_filter(dbc_id: u32) -> (u32, u32) {
+    if dbc_id & CAN_EFF_FLAG != 0 {
+        (dbc_id, CAN_EFF_MASK | CAN_EFF_FLAG)
+    } else {
+        (dbc_id, CAN_SFF_MASK | CAN_EFF_FLAG)
+    }
+}
+
+/// Filter on the PGN bits of an extended J1939 identifier, priority and source
+/// address are ignored, as is the destination address of PDU1 groups
+fn pgn_filter(pgn: u32) -> (u32, u32) {
+    let pdu_format = (pgn >> 8) & 0xFF;
+    let pgn_mask = if pdu_format < 240 { 0x03FF00 } else { 0x03FFFF };
+    (((pgn & pgn_mask) << 8) | CAN_EFF_FLAG, (pgn_mask << 8) | CAN_EFF_FLAG)
+}
@@ -0,0 +1,70 @@
+/// Owned CAN frame independent of the socket backend, classic or FD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    /// Identifier without EFF/RTR/ERR flags
+    pub id: u32,
+    pub extended: bool,
+    pub data: Vec<u8>,
+    pub fd: bool,
+    /// Bit rate switch, only meaningful for FD frames
+    pub brs: bool,
+}
+
+impl RawFrame {
+    pub fn new(id: u32, data: &[u8]) -> Self {
+        RawFrame {
+            id,
+            extended: id > 0x7FF,
+            data: data.to_vec(),
+            fd: data.len() > 8,
+            brs: false,
+        }
+    }
+
+    pub fn new_fd(id: u32, data: &[u8], brs: bool) -> Self {
+        RawFrame {
+            id,
+            extended: id > 0x7FF,
+            data: pad_fd_payload(data),
+            fd: true,
+            brs,
+        }
+    }
+
+    /// Classic or FD frame of a DBC identifier, bit 31 selects the extended format
+    /// independent of the ID value
+    pub fn from_dbc_id(dbc_id: u32, data: &[u8]) -> Self {
+        RawFrame {
+            id: dbc_id & 0x1FFFFFFF,
+            extended: dbc_id & 0x80000000 != 0,
+            data: data.to_vec(),
+            fd: data.len() > 8,
+            brs: false,
+        }
+    }
+
+    /// Identifier as used for DBC lookups (bit 31 set for extended frames)
+    pub fn dbc_id(&self) -> u32 {
+        if self.extended {
+            self.id | 0x80000000
+        } else {
+            self.id
+        }
+    }
+}
+
+/// Valid CAN FD payload lengths
+pub const CAN_FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Zero pads a payload to the next valid CAN FD length
+pub fn pad_fd_payload(data: &[u8]) -> Vec<u8> {
+    let len = CAN_FD_LENGTHS
+        .iter()
+        .copied()
+        .find(|len| *len >= data.len())
+        .unwrap_or(64);
+
+    let mut padded = data[..data.len().min(64)].to_vec();
+    padded.resize(len, 0);
+    padded
+}
@@ -0,0 +1,429 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use log::{info, warn};
+use tokio::time::{Duration, Instant};
+use crate::can_fd::CanFdSocket;
+use crate::can_frame::RawFrame;
+use crate::can_tool::{CanUtils, SignalUpdate};
+
+const CANFD_FLAG_BRS: u8 = 0x01;
+const ASC_DATE_FORMAT: &str = "%a %b %d %I:%M:%S%.3f %P %Y";
+
+/// Supported CAN log formats, binary BLF logs are not supported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `(1436509052.249713) can0 123#DEADBEEF`
+    Candump,
+    /// Vector ASC, see `LogEntry::from_asc` for the supported subset
+    Asc,
+}
+
+impl LogFormat {
+    /// Picks the format from the file extension, `candump -L` unless `.asc`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("asc") => LogFormat::Asc,
+            _ => LogFormat::Candump,
+        }
+    }
+}
+
+/// A frame with its receive time in seconds since the UNIX epoch
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub timestamp: f64,
+    pub ifname: String,
+    pub frame: RawFrame,
+}
+
+impl LogEntry {
+    pub fn now(ifname: &str, frame: RawFrame) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        LogEntry { timestamp, ifname: ifname.to_string(), frame }
+    }
+
+    /// Formats the entry as a `candump -L` line
+    pub fn to_candump(&self) -> String {
+        let id = if self.frame.extended {
+            format!("{:08X}", self.frame.id)
+        } else {
+            format!("{:03X}", self.frame.id)
+        };
+
+        let separator = if self.frame.fd {
+            let flags = if self.frame.brs { CANFD_FLAG_BRS } else { 0 };
+            format!("##{:X}", flags)
+        } else {
+            "#".to_string()
+        };
+
+        format!("({:.6}) {} {}{}{}", self.timestamp, self.ifname, id, separator, hex(&self.frame.data, ""))
+    }
+
+    /// Parses a `candump -L` line
+    pub fn from_candump(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let timestamp = parts
+            .next()?
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .parse::<f64>()
+            .ok()?;
+        let ifname = parts.next()?.to_string();
+        let (id, rest) = parts.next()?.split_once('#')?;
+
+        let (fd, brs, data) = match rest.strip_prefix('#') {
+            Some(fd_rest) => {
+                let flags = u8::from_str_radix(fd_rest.get(..1)?, 16).ok()?;
+                (true, flags & CANFD_FLAG_BRS != 0, unhex(fd_rest.get(1..)?)?)
+            }
+            // remote frames carry no data
+            None if rest.starts_with('R') => (false, false, Vec::new()),
+            None => (false, false, unhex(rest)?),
+        };
+
+        Some(LogEntry {
+            timestamp,
+            ifname,
+            frame: RawFrame {
+                id: u32::from_str_radix(id, 16).ok()?,
+                extended: id.len() > 3,
+                data,
+                fd,
+                brs,
+            },
+        })
+    }
+
+    /// Formats the entry as an ASC line on channel 1, `base` is the measurement start
+    /// written in the header as ASC timestamps count from there
+    pub fn to_asc(&self, base: f64) -> String {
+        let timestamp = self.timestamp - base;
+        let id = if self.frame.extended {
+            format!("{:X}x", self.frame.id)
+        } else {
+            format!("{:X}", self.frame.id)
+        };
+
+        if self.frame.fd {
+            let dlc = fd_dlc(self.frame.data.len());
+            format!(
+                "{:>11.6} CANFD 1 Rx {} {} 0 {:x} {} {}",
+                timestamp,
+                id,
+                self.frame.brs as u8,
+                dlc,
+                self.frame.data.len(),
+                hex(&self.frame.data, " ")
+            )
+        } else {
+            format!(
+                "{:>11.6} 1 {:<15} Rx d {} {}",
+                timestamp,
+                id,
+                self.frame.data.len(),
+                hex(&self.frame.data, " ")
+            )
+        }
+    }
+
+    /// Parses an ASC frame line with the timestamp relative to the measurement start,
+    /// header and event lines return None.
+    ///
+    /// Supported are data frames of `base hex` logs in Vector's layout, classic
+    /// `<time> <ch> <id> <dir> d <dlc> <data..>` and
+    /// `<time> CANFD <ch> <dir> <id> [<name>] <brs> <esi> <dlc> <len> <data..>`,
+    /// trailing fields such as `Length = ..` are ignored. Remote frames, error frames,
+    /// J1939 TP lines and `base dec` IDs are skipped.
+    pub fn from_asc(line: &str, ifname: &str) -> Option<Self> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let timestamp = parts.first()?.parse::<f64>().ok()?;
+
+        let (id, brs, len, data, fd) = if parts.get(1) == Some(&"CANFD") {
+            // the symbolic message name is optional
+            let fields = if matches!(parts.get(5), Some(&"0") | Some(&"1")) { 5 } else { 6 };
+            let len = parts.get(fields + 3)?.parse::<usize>().ok()?;
+            (*parts.get(4)?, *parts.get(fields)? == "1", len, parts.get(fields + 4..fields + 4 + len)?, true)
+        } else {
+            if parts.get(4) != Some(&"d") {
+                return None;
+            }
+            let len = parts.get(5)?.parse::<usize>().ok()?;
+            (*parts.get(2)?, false, len, parts.get(6..6 + len)?, false)
+        };
+
+        let extended = id.ends_with('x');
+        let data = data
+            .iter()
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        if data.len() != len {
+            return None;
+        }
+
+        Some(LogEntry {
+            timestamp,
+            ifname: ifname.to_string(),
+            frame: RawFrame {
+                id: u32::from_str_radix(id.trim_end_matches('x'), 16).ok()?,
+                extended,
+                data,
+                fd,
+                brs,
+            },
+        })
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        datetime(self.timestamp)
+    }
+}
+
+fn datetime(timestamp: f64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros((timestamp * 1e6).round() as i64).unwrap_or_else(Utc::now)
+}
+
+/// Writes log entries in the chosen format
+pub struct CanLogWriter<W: Write> {
+    writer: W,
+    format: LogFormat,
+    /// Measurement start of an ASC log, the time of the first entry
+    base: Option<f64>,
+}
+
+impl CanLogWriter<BufWriter<File>> {
+    /// Creates a log file, the format follows the file extension
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let writer = BufWriter::new(File::create(path)?);
+        CanLogWriter::new(writer, LogFormat::from_path(path))
+    }
+}
+
+impl<W: Write> CanLogWriter<W> {
+    /// The ASC header is written with the first entry, whose time becomes the measurement start
+    pub fn new(writer: W, format: LogFormat) -> io::Result<Self> {
+        Ok(CanLogWriter { writer, format, base: None })
+    }
+
+    pub fn write_entry(&mut self, entry: &LogEntry) -> io::Result<()> {
+        match self.format {
+            LogFormat::Candump => writeln!(self.writer, "{}", entry.to_candump()),
+            LogFormat::Asc => {
+                let base = match self.base {
+                    Some(base) => base,
+                    None => self.write_asc_header(entry)?,
+                };
+                writeln!(self.writer, "{}", entry.to_asc(base))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Closes the ASC trigger block and flushes, no entries may follow
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.format == LogFormat::Asc && self.base.is_some() {
+            writeln!(self.writer, "End TriggerBlock")?;
+        }
+        self.writer.flush()
+    }
+
+    fn write_asc_header(&mut self, first: &LogEntry) -> io::Result<f64> {
+        // the header date has millisecond resolution
+        let base = (first.timestamp * 1e3).floor() / 1e3;
+        let date = datetime(base).format(ASC_DATE_FORMAT);
+        writeln!(self.writer, "date {}", date)?;
+        writeln!(self.writer, "base hex  timestamps absolute")?;
+        writeln!(self.writer, "no internal events logged")?;
+        writeln!(self.writer, "Begin Triggerblock {}", date)?;
+        writeln!(self.writer, "{:>11.6} Start of measurement", 0.0)?;
+
+        self.base = Some(base);
+        Ok(base)
+    }
+}
+
+/// Measurement start of an ASC `date` line in seconds since the UNIX epoch. The date is
+/// local time without a zone and taken as UTC, localized day and month names are not parsed.
+fn parse_asc_date(line: &str) -> Option<f64> {
+    let date = line.strip_prefix("date ")?.trim();
+    ["%a %b %d %I:%M:%S%.3f %p %Y", "%a %b %d %I:%M:%S %p %Y", "%a %b %d %H:%M:%S%.3f %Y", "%a %b %d %H:%M:%S %Y"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .map(|date| date.and_utc().timestamp_micros() as f64 / 1e6)
+}
+
+/// Reads all frames of a log, unparsable lines are skipped. ASC timestamps are made absolute
+/// with the `date` of the header, they stay relative to the measurement start without one.
+pub fn read_log<R: BufRead>(reader: R, format: LogFormat, ifname: &str) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    let mut asc_base = 0.0;
+    // `timestamps relative` logs count from the previous event
+    let mut asc_relative = false;
+    let mut asc_last = 0.0;
+
+    for line in reader.lines() {
+        let line = line?;
+        let entry = match format {
+            LogFormat::Candump => LogEntry::from_candump(&line),
+            LogFormat::Asc => {
+                if line.starts_with("date ") {
+                    asc_base = parse_asc_date(&line).unwrap_or_else(|| {
+                        warn!("Unsupported ASC date, timestamps stay relative: {}", line);
+                        0.0
+                    });
+                } else if line.starts_with("base ") {
+                    asc_relative = line.contains("timestamps relative");
+                }
+
+                LogEntry::from_asc(&line, ifname).map(|mut entry| {
+                    if asc_relative {
+                        asc_last += entry.timestamp;
+                        entry.timestamp = asc_last;
+                    }
+                    entry.timestamp += asc_base;
+                    entry
+                })
+            }
+        };
+
+        match entry {
+            Some(entry) => entries.push(entry),
+            None if format == LogFormat::Candump && !line.trim().is_empty() => {
+                warn!("Skipping invalid log line: {}", line);
+            }
+            None => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reads a log file, the format follows the file extension
+pub fn read_log_file(path: &Path, ifname: &str) -> io::Result<Vec<LogEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    read_log(reader, LogFormat::from_path(path), ifname)
+}
+
+/// Records classic and FD frames of an interface until `duration` elapsed or forever
+pub async fn record<W: Write>(
+    ifname: &str,
+    writer: &mut CanLogWriter<W>,
+    duration: Option<Duration>,
+) -> io::Result<usize> {
+    let socket = CanFdSocket::open(ifname)?;
+    let deadline = duration.map(|d| Instant::now() + d);
+    let mut count = 0;
+
+    info!("Recording CAN traffic on {}", ifname);
+    loop {
+        let frame = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, socket.read_frame()).await {
+                Ok(frame) => frame?,
+                Err(_) => break,
+            },
+            None => socket.read_frame().await?,
+        };
+
+        writer.write_entry(&LogEntry::now(ifname, frame))?;
+        count += 1;
+    }
+    writer.flush()?;
+
+    info!("Recorded {} frames on {}", count, ifname);
+    Ok(count)
+}
+
+/// Delay before replaying `entry`, `speed` 2.0 replays twice as fast and 0.0 without delay
+fn replay_delay(start: &LogEntry, entry: &LogEntry, speed: f64) -> Duration {
+    if speed <= 0.0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(((entry.timestamp - start.timestamp) / speed).max(0.0))
+}
+
+/// Replays a log onto an interface (e.g. `vcan0`) with the original timing scaled by `speed`
+pub async fn replay_to_interface(entries: &[LogEntry], ifname: &str, speed: f64) -> io::Result<usize> {
+    let socket = CanFdSocket::open(ifname)?;
+    let start = Instant::now();
+
+    if let Some(first) = entries.first() {
+        for entry in entries {
+            tokio::time::sleep_until(start + replay_delay(first, entry, speed)).await;
+            socket.write_frame(&entry.frame).await?;
+        }
+    }
+
+    info!("Replayed {} frames onto {}", entries.len(), ifname);
+    Ok(entries.len())
+}
+
+/// Replays a log through the decoder of `can_utils`, timestamps are the logged ones
+pub fn replay_signals(
+    can_utils: &CanUtils,
+    entries: Vec<LogEntry>,
+    speed: f64,
+) -> BoxStream<'_, SignalUpdate> {
+    let first = entries.first().cloned();
+    let start = Instant::now();
+
+    stream::iter(entries)
+        .filter_map(move |entry| {
+            let delay = first
+                .as_ref()
+                .map(|first| replay_delay(first, &entry, speed))
+                .unwrap_or_default();
+
+            async move {
+                tokio::time::sleep_until(start + delay).await;
+                can_utils.decode_raw_frame(&entry.frame).map(|signals| SignalUpdate {
+                    timestamp: entry.datetime(),
+                    frame_id: entry.frame.dbc_id(),
+                    signals,
+                    e2e: None,
+                })
+            }
+        })
+        .boxed()
+}
+
+fn hex(data: &[u8], separator: &str) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn fd_dlc(len: usize) -> usize {
+    match len {
+        0..=8 => len,
+        9..=12 => 9,
+        13..=16 => 10,
+        17..=20 => 11,
+        21..=24 => 12,
+        25..=32 => 13,
+        33..=48 => 14,
+        _ => 15,
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use log::{info, warn};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::can_error::CanToolError;
+use crate::can_filter::FrameFilter;
+use crate::can_retry::{HealthMonitor, RetryPolicy};
+use crate::can_tool::{CanTransmitter, CanUtils, SignalUpdate};
+use crate::can_transport::{CanBackend, SocketCanBackend};
+
+const UPDATE_QUEUE_LEN: usize = 1024;
+
+/// One bus of the manager with its own DBC and filters
+#[derive(Debug, Clone)]
+pub struct InterfaceConfig {
+    pub ifname: String,
+    pub dbc_path: Option<PathBuf>,
+    pub filters: Vec<FrameFilter>,
+    pub policy: RetryPolicy,
+    /// Opens the CAN FD socket too, needed to send messages longer than 8 bytes
+    pub fd: bool,
+}
+
+impl InterfaceConfig {
+    pub fn new(ifname: &str, dbc_path: Option<&Path>) -> Self {
+        InterfaceConfig {
+            ifname: ifname.to_string(),
+            dbc_path: dbc_path.map(Path::to_path_buf),
+            filters: Vec::new(),
+            policy: RetryPolicy::default(),
+            fd: false,
+        }
+    }
+}
+
+/// Decoded signals tagged with the interface they were received on
+#[derive(Debug, Clone)]
+pub struct TaggedSignalUpdate {
+    pub ifname: String,
+    pub update: SignalUpdate,
+}
+
+#[derive(Debug)]
+struct Interface {
+    transmitter: CanTransmitter,
+    health: HealthMonitor,
+    task: JoinHandle<()>,
+}
+
+/// Owns several `CanUtils`, one background task per interface
+#[derive(Debug)]
+pub struct CanManager {
+    interfaces: HashMap<String, Interface>,
+    /// Message name to the interface whose DBC defines it
+    routes: HashMap<String, String>,
+    updates: mpsc::Receiver<TaggedSignalUpdate>,
+    /// Updates dropped because `signal_stream` was not drained
+    dropped: Arc<AtomicU64>,
+}
+
+impl CanManager {
+    /// Opens all interfaces, fails if any of them cannot be opened
+    pub async fn open(configs: Vec<InterfaceConfig>) -> Result<Self, CanToolError> {
+        Self::open_with_backend(Arc::new(SocketCanBackend), configs).await
+    }
+
+    /// Opens all interfaces on another transport, e.g. a `SimBus` in tests
+    pub async fn open_with_backend(
+        backend: Arc<dyn CanBackend>,
+        configs: Vec<InterfaceConfig>,
+    ) -> Result<Self, CanToolError> {
+        let (update_tx, updates) = mpsc::channel(UPDATE_QUEUE_LEN);
+        let dropped = Arc::new(AtomicU64::new(0));
+        // dropping the manager on an error aborts the interfaces opened so far
+        let mut manager = CanManager {
+            interfaces: HashMap::new(),
+            routes: HashMap::new(),
+            updates,
+            dropped: dropped.clone(),
+        };
+
+        for config in configs {
+            let health = HealthMonitor::new();
+            let mut can_utils = CanUtils::new_with_backend(
+                backend.clone(),
+                &config.ifname,
+                config.dbc_path.as_deref(),
+                config.filters,
+                config.policy,
+                health.clone(),
+            )
+            .await?;
+            if config.fd {
+                can_utils.enable_fd()?;
+            }
+
+            for message in can_utils.dbc_info().messages() {
+                match manager.routes.get(&message.name) {
+                    Some(ifname) => warn!(
+                        "Message {} is defined on {} and {}, sending on {}",
+                        message.name, ifname, config.ifname, ifname
+                    ),
+                    None => {
+                        manager.routes.insert(message.name.clone(), config.ifname.clone());
+                    }
+                }
+            }
+
+            let transmitter = can_utils.transmitter();
+            let task = tokio::spawn(run_interface(config.ifname.clone(), can_utils, update_tx.clone(), dropped.clone()));
+
+            info!("CAN manager opened {}", config.ifname);
+            manager.interfaces.insert(config.ifname, Interface { transmitter, health, task });
+        }
+
+        Ok(manager)
+    }
+
+    /// Decoded signals of all interfaces merged into one stream. Updates are dropped while
+    /// the stream is not drained, sending does not depend on it.
+    pub fn signal_stream(&mut self) -> BoxStream<'_, TaggedSignalUpdate> {
+        stream::unfold(&mut self.updates, |updates| async move {
+            updates.recv().await.map(|update| (update, updates))
+        })
+        .boxed()
+    }
+
+    /// Interface a message is sent on
+    pub fn route(&self, message_name: &str) -> Option<&str> {
+        self.routes.get(message_name).map(String::as_str)
+    }
+
+    /// Encodes a DBC message and sends it on the interface whose DBC defines it
+    pub async fn send(&self, message_name: &str, values: HashMap<String, f64>) -> Result<(), CanToolError> {
+        match self.routes.get(message_name) {
+            Some(ifname) => self.send_on(ifname, message_name, values).await,
+            None => Err(CanToolError::MessageNotInDbc(message_name.to_string())),
+        }
+    }
+
+    /// Encodes a DBC message and sends it on the given interface
+    pub async fn send_on(
+        &self,
+        ifname: &str,
+        message_name: &str,
+        values: HashMap<String, f64>,
+    ) -> Result<(), CanToolError> {
+        let interface = self.interfaces.get(ifname).ok_or_else(|| {
+            CanToolError::Socket(io::Error::new(io::ErrorKind::NotFound, format!("Unknown interface {}", ifname)))
+        })?;
+        interface.transmitter.send(message_name, values).await
+    }
+
+    /// Number of updates dropped because `signal_stream` was not drained in time
+    pub fn dropped_updates(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Names of the managed interfaces
+    pub fn interfaces(&self) -> impl Iterator<Item = &str> {
+        self.interfaces.keys().map(String::as_str)
+    }
+
+    /// Connection health of an interface
+    pub fn health(&self, ifname: &str) -> Option<HealthMonitor> {
+        self.interfaces.get(ifname).map(|interface| interface.health.clone())
+    }
+
+    /// Whether the task of an interface is still receiving
+    pub fn is_running(&self, ifname: &str) -> bool {
+        self.interfaces
+            .get(ifname)
+            .is_some_and(|interface| !interface.task.is_finished())
+    }
+}
+
+impl Drop for CanManager {
+    fn drop(&mut self) {
+        for interface in self.interfaces.values() {
+            interface.task.abort();
+        }
+    }
+}
+
+/// Forwards decoded signals of one interface, transmit requests are served by its signal stream
+async fn run_interface(
+    ifname: String,
+    mut can_utils: CanUtils,
+    updates: mpsc::Sender<TaggedSignalUpdate>,
+    dropped: Arc<AtomicU64>,
+) {
+    let mut signals = can_utils.signal_stream();
+
+    while let Some(update) = signals.next().await {
+        // waiting for the receiver would stall the transmit requests too
+        match updates.try_send(TaggedSignalUpdate { ifname: ifname.clone(), update }) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                if dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("CAN manager update queue is full, dropping updates of {}", ifname);
+                }
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => return,
+        }
+    }
+
+    warn!("CAN manager stopped receiving on {}", ifname);
+}
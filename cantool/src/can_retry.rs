@@ -0,0 +1,202 @@
+use std::future::Future;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use crate::can_error::CanToolError;
+
+/// Bounds the attempts to load the DBC file and open the CAN socket
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, None retries until the deadline or cancellation
+    pub max_attempts: Option<u32>,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Factor applied to the backoff after every failed attempt
+    pub multiplier: f64,
+    /// Overall time budget for all attempts
+    pub deadline: Option<Duration>,
+    pub cancel: CancellationToken,
+}
+
+impl Default for RetryPolicy {
+    /// 10 attempts from 100 ms up to 5 s apart, at most 30 s in total
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: Some(10),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            deadline: Some(Duration::from_secs(30)),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries every second until cancelled, the behaviour before retry policies
+    pub fn forever() -> Self {
+        RetryPolicy {
+            max_attempts: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 1.0,
+            deadline: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Backoff after the failed `attempt`, starting at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        self.initial_backoff.mul_f64(factor).min(self.max_backoff)
+    }
+
+    /// Runs `operation` until it succeeds or the policy is exhausted, the last error is returned.
+    /// Errors that are not retryable are returned immediately.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        health: &HealthMonitor,
+        mut operation: F,
+    ) -> Result<T, CanToolError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CanToolError>>,
+    {
+        let deadline = self.deadline.map(|d| Instant::now() + d);
+        let mut attempt = 0;
+
+        loop {
+            if self.cancel.is_cancelled() {
+                health.set_state(ConnectionState::Cancelled);
+                return Err(CanToolError::Cancelled);
+            }
+
+            attempt += 1;
+            health.attempt(attempt);
+
+            let e = match operation().await {
+                Ok(value) => {
+                    health.set_state(ConnectionState::Connected);
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+            health.set_error(&e);
+
+            if !e.is_retryable() {
+                error!("Not retrying after attempt {}: {}", attempt, e);
+                health.set_state(ConnectionState::Failed);
+                return Err(e);
+            }
+
+            let backoff = self.backoff(attempt);
+            let attempts_left = self.max_attempts.is_none_or(|max| attempt < max);
+            let time_left = deadline.is_none_or(|deadline| Instant::now() + backoff < deadline);
+            if !attempts_left || !time_left {
+                error!("Giving up after {} attempts: {}", attempt, e);
+                health.set_state(ConnectionState::Failed);
+                return Err(e);
+            }
+
+            warn!("Attempt {} failed: {}. Retrying in {:?}...", attempt, e, backoff);
+            tokio::select! {
+                _ = self.cancel.cancelled() => {
+                    health.set_state(ConnectionState::Cancelled);
+                    return Err(CanToolError::Cancelled);
+                }
+                _ = tokio::time::sleep(backoff) => {}
+            }
+        }
+    }
+}
+
+/// Connection state of a `CanUtils` instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// The socket failed and is being reopened
+    Reconnecting,
+    /// The retry policy is exhausted
+    Failed,
+    Cancelled,
+}
+
+/// Snapshot of the connection health
+#[derive(Debug, Clone)]
+pub struct ConnectionHealth {
+    pub state: ConnectionState,
+    /// When the state last changed
+    pub since: DateTime<Utc>,
+    /// Attempt number of the current connect or reconnect
+    pub attempt: u32,
+    /// Successful socket restarts since construction
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+/// Shared connection health a supervisor can poll or watch for changes
+#[derive(Debug, Clone)]
+pub struct HealthMonitor {
+    sender: Arc<watch::Sender<ConnectionHealth>>,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(ConnectionHealth {
+            state: ConnectionState::Connecting,
+            since: Utc::now(),
+            attempt: 0,
+            restarts: 0,
+            last_error: None,
+        });
+        HealthMonitor { sender: Arc::new(sender) }
+    }
+
+    /// Current health snapshot
+    pub fn status(&self) -> ConnectionHealth {
+        self.sender.borrow().clone()
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.sender.borrow().state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+
+    /// Receiver that is notified on every health change
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionHealth> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn set_state(&self, state: ConnectionState) {
+        self.sender.send_modify(|health| {
+            if state == ConnectionState::Connected && health.state == ConnectionState::Reconnecting {
+                health.restarts += 1;
+            }
+            if health.state != state {
+                health.since = Utc::now();
+            }
+            health.state = state;
+        });
+    }
+
+    fn attempt(&self, attempt: u32) {
+        self.sender.send_modify(|health| health.attempt = attempt);
+    }
+
+    fn set_error(&self, e: &CanToolError) {
+        self.sender.send_modify(|health| health.last_error = Some(e.to_string()));
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use log::{error, info, warn};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, MissedTickBehavior};
+use crate::can_codec::SignalLayout;
+use crate::can_error::CanToolError;
+use crate::can_frame::RawFrame;
+use crate::can_tool::CanUtils;
+
+/// Signal values shared with a cyclic message, updated by the application
+pub type SharedValues = Arc<RwLock<HashMap<String, f64>>>;
+
+/// Checksum over the encoded payload, the checksum signal itself is zero while computing
+pub type ChecksumFn = Arc<dyn Fn(&[u8]) -> u64 + Send + Sync>;
+
+/// Where the signal values of every transmission come from
+pub enum ValueSource {
+    Shared(SharedValues),
+    /// Called before every transmission
+    Closure(Box<dyn FnMut() -> HashMap<String, f64> + Send>),
+}
+
+impl ValueSource {
+    fn values(&mut self) -> HashMap<String, f64> {
+        match self {
+            ValueSource::Shared(values) => match values.read() {
+                Ok(values) => values.clone(),
+                Err(poisoned) => poisoned.into_inner().clone(),
+            },
+            ValueSource::Closure(update) => update(),
+        }
+    }
+}
+
+impl fmt::Debug for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueSource::Shared(values) => f.debug_tuple("Shared").field(values).finish(),
+            ValueSource::Closure(_) => f.write_str("Closure"),
+        }
+    }
+}
+
+/// Rolling counter signal incremented with every transmission
+#[derive(Debug, Clone)]
+pub struct CounterSignal {
+    pub signal: String,
+    /// The counter wraps to 0 at this value, e.g. 16 for a 4 bit counter
+    pub modulo: u64,
+}
+
+/// Checksum signal filled in after all other signals are encoded
+#[derive(Clone)]
+pub struct ChecksumSignal {
+    pub signal: String,
+    pub checksum: ChecksumFn,
+}
+
+impl fmt::Debug for ChecksumSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChecksumSignal").field("signal", &self.signal).finish_non_exhaustive()
+    }
+}
+
+/// A DBC message transmitted with a fixed period
+#[derive(Debug)]
+pub struct CyclicMessage {
+    pub message_name: String,
+    pub period: Duration,
+    pub values: ValueSource,
+    pub counter: Option<CounterSignal>,
+    pub checksum: Option<ChecksumSignal>,
+}
+
+impl CyclicMessage {
+    pub fn new(message_name: &str, period: Duration, values: ValueSource) -> Self {
+        CyclicMessage {
+            message_name: message_name.to_string(),
+            period,
+            values,
+            counter: None,
+            checksum: None,
+        }
+    }
+}
+
+/// Transmission statistics of a cyclic message
+#[derive(Debug, Clone, Default)]
+pub struct TxStats {
+    pub sent: u64,
+    pub errors: u64,
+    pub mean_period: Option<Duration>,
+    pub min_period: Option<Duration>,
+    pub max_period: Option<Duration>,
+    /// Standard deviation of the period
+    pub jitter: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct TxCounter {
+    sent: u64,
+    errors: u64,
+    last_sent: Option<Instant>,
+    periods: u64,
+    /// Running mean and sum of squared deviations of the period in seconds (Welford)
+    mean: f64,
+    m2: f64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl TxCounter {
+    fn record_sent(&mut self, now: Instant) {
+        self.sent += 1;
+        if let Some(last) = self.last_sent {
+            let period = now.saturating_duration_since(last);
+            self.periods += 1;
+            let delta = period.as_secs_f64() - self.mean;
+            self.mean += delta / self.periods as f64;
+            self.m2 += delta * (period.as_secs_f64() - self.mean);
+            self.min = Some(self.min.map_or(period, |min| min.min(period)));
+            self.max = Some(self.max.map_or(period, |max| max.max(period)));
+        }
+        self.last_sent = Some(now);
+    }
+
+    fn to_stats(&self) -> TxStats {
+        let has_periods = self.periods > 0;
+        TxStats {
+            sent: self.sent,
+            errors: self.errors,
+            mean_period: has_periods.then(|| Duration::from_secs_f64(self.mean)),
+            min_period: self.min,
+            max_period: self.max,
+            jitter: has_periods.then(|| Duration::from_secs_f64((self.m2 / self.periods as f64).sqrt())),
+        }
+    }
+}
+
+/// Everything a job needs to encode its frame, resolved once when added
+struct TxJob {
+    message: CyclicMessage,
+    counter_value: u64,
+    counter_layout: Option<SignalLayout>,
+    checksum_layout: Option<SignalLayout>,
+}
+
+struct ScheduledMessage {
+    job: Arc<Mutex<TxJob>>,
+    period: Duration,
+    stats: Arc<Mutex<TxCounter>>,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Cyclic transmit scheduler, every running message has its own timer task
+pub struct TxScheduler {
+    can_utils: Arc<CanUtils>,
+    messages: HashMap<String, ScheduledMessage>,
+}
+
+impl TxScheduler {
+    /// Takes ownership of a `CanUtils` used for encoding and sending only
+    pub fn new(can_utils: CanUtils) -> Self {
+        TxScheduler { can_utils: Arc::new(can_utils), messages: HashMap::new() }
+    }
+
+    /// Registers a message, it is sent once `start` is called
+    pub fn add(&mut self, message: CyclicMessage) -> Result<(), CanToolError> {
+        let dbc_message = self
+            .can_utils
+            .dbc_info()
+            .message(&message.message_name)
+            .ok_or_else(|| CanToolError::MessageNotInDbc(message.message_name.clone()))?;
+
+        for signal in message.counter.iter().map(|c| &c.signal).chain(message.checksum.iter().map(|c| &c.signal)) {
+            if !dbc_message.signals.contains(signal) {
+                return Err(CanToolError::SignalNotInDbc(signal.clone()));
+            }
+        }
+
+        let layout = |signal: &String| {
+            self.can_utils
+                .signal_layout(signal)
+                .ok_or_else(|| CanToolError::SignalNotInDbc(signal.clone()))
+        };
+        let counter_layout = message.counter.as_ref().map(|counter| layout(&counter.signal)).transpose()?;
+        let checksum_layout = message.checksum.as_ref().map(|checksum| layout(&checksum.signal)).transpose()?;
+
+        if message.period.is_zero() {
+            return Err(CanToolError::EncodeFailed(format!("{}: period must not be zero", message.message_name)));
+        }
+
+        let name = message.message_name.clone();
+        self.remove(&name);
+        self.messages.insert(
+            name,
+            ScheduledMessage {
+                period: message.period,
+                job: Arc::new(Mutex::new(TxJob { message, counter_value: 0, counter_layout, checksum_layout })),
+                stats: Arc::new(Mutex::new(TxCounter::default())),
+                task: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Starts sending a registered message
+    pub fn start(&mut self, message_name: &str) -> Result<(), CanToolError> {
+        let scheduled = self
+            .messages
+            .get_mut(message_name)
+            .ok_or_else(|| CanToolError::MessageNotInDbc(message_name.to_string()))?;
+
+        if scheduled.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return Ok(());
+        }
+
+        let can_utils = self.can_utils.clone();
+        let job = scheduled.job.clone();
+        let stats = scheduled.stats.clone();
+        let period = scheduled.period;
+        let name = message_name.to_string();
+
+        scheduled.task = Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                ticker.tick().await;
+                let frame = {
+                    let mut job = match job.lock() {
+                        Ok(job) => job,
+                        Err(poisoned) => poisoned.into_inner(),
+                    };
+                    job.encode(&can_utils)
+                };
+
+                let result = match frame {
+                    Ok(frame) => can_utils.send_frame(&frame).await,
+                    Err(e) => Err(e),
+                };
+
+                let mut stats = match stats.lock() {
+                    Ok(stats) => stats,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                match result {
+                    Ok(()) => stats.record_sent(Instant::now()),
+                    Err(e) => {
+                        stats.errors += 1;
+                        warn!("Cyclic message {} not sent: {}", name, e);
+                    }
+                }
+            }
+        }));
+
+        info!("Started cyclic message {} every {:?}", message_name, period);
+        Ok(())
+    }
+
+    /// Stops sending a message, it stays registered
+    pub fn stop(&mut self, message_name: &str) {
+        if let Some(task) = self.messages.get_mut(message_name).and_then(|scheduled| scheduled.task.take()) {
+            task.abort();
+            info!("Stopped cyclic message {}", message_name);
+        }
+    }
+
+    /// Stops and unregisters a message
+    pub fn remove(&mut self, message_name: &str) {
+        self.stop(message_name);
+        self.messages.remove(message_name);
+    }
+
+    pub fn start_all(&mut self) -> Result<(), CanToolError> {
+        let names: Vec<String> = self.messages.keys().cloned().collect();
+        names.iter().try_for_each(|name| self.start(name))
+    }
+
+    pub fn stop_all(&mut self) {
+        let names: Vec<String> = self.messages.keys().cloned().collect();
+        names.iter().for_each(|name| self.stop(name));
+    }
+
+    pub fn is_running(&self, message_name: &str) -> bool {
+        self.messages
+            .get(message_name)
+            .and_then(|scheduled| scheduled.task.as_ref())
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// Transmission statistics of a registered message
+    pub fn stats(&self, message_name: &str) -> Option<TxStats> {
+        let scheduled = self.messages.get(message_name)?;
+        let stats = match scheduled.stats.lock() {
+            Ok(stats) => stats,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        Some(stats.to_stats())
+    }
+}
+
+impl Drop for TxScheduler {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+impl TxJob {
+    fn encode(&mut self, can_utils: &CanUtils) -> Result<RawFrame, CanToolError> {
+        let mut values = self.message.values.values();
+        // counter and checksum are raw bit patterns, not physical values
+        for signal in self.message.counter.iter().map(|c| &c.signal).chain(self.message.checksum.iter().map(|c| &c.signal)) {
+            values.remove(signal);
+        }
+
+        let mut frame = can_utils.encode_message(&self.message.message_name, &values).map_err(|e| {
+            error!("Failed to encode cyclic message {}: {}", self.message.message_name, e);
+            e
+        })?;
+
+        if let (Some(counter), Some(layout)) = (self.message.counter.as_ref(), self.counter_layout.as_ref()) {
+            layout.encode_raw(self.counter_value, &mut frame.data)?;
+            self.counter_value = (self.counter_value + 1) % counter.modulo.max(1);
+        }
+        if let (Some(checksum), Some(layout)) = (self.message.checksum.as_ref(), self.checksum_layout.as_ref()) {
+            layout.encode_raw(0, &mut frame.data)?;
+            layout.encode_raw((checksum.checksum)(&frame.data), &mut frame.data)?;
+        }
+
+        Ok(frame)
+    }
+}
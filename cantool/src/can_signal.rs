@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use canparse::pgn::SpnDefinition;
+use crate::can_dbc::DbcInfo;
+
+/// DBC description of a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalMeta {
+    pub name: String,
+    pub unit: String,
+    pub min: f64,
+    pub max: f64,
+    pub scale: f64,
+    pub offset: f64,
+    /// Raw value to text from `VAL_`
+    pub value_table: Option<BTreeMap<i64, String>>,
+}
+
+impl SignalMeta {
+    /// Takes the exact values of the DBC text where the signal definition was parsed,
+    /// `message_id` is the DBC identifier of the message carrying the signal
+    pub fn new(spn: &SpnDefinition, dbc: &DbcInfo, message_id: u32) -> Self {
+        let value_table = dbc.value_table(message_id, spn.name()).cloned();
+        let definition = dbc
+            .message_by_id(message_id)
+            .and_then(|message| message.definitions.iter().find(|signal| &signal.name == spn.name()))
+            .or_else(|| dbc.signal(spn.name()));
+
+        match definition {
+            Some(signal) => SignalMeta {
+                name: signal.name.clone(),
+                unit: signal.unit.clone(),
+                min: signal.min,
+                max: signal.max,
+                scale: signal.layout.scale,
+                offset: signal.layout.offset,
+                value_table,
+            },
+            None => SignalMeta {
+                name: spn.name().clone(),
+                unit: spn.units().clone(),
+                min: *spn.min_value() as f64,
+                max: *spn.max_value() as f64,
+                scale: *spn.scale() as f64,
+                offset: *spn.offset() as f64,
+                value_table,
+            },
+        }
+    }
+
+    /// A `[0|0]` range in the DBC means the range is not specified
+    pub fn has_range(&self) -> bool {
+        self.min != 0.0 || self.max != 0.0
+    }
+
+    /// Raw value the physical `value` was decoded from
+    pub fn raw_value(&self, value: f64) -> i64 {
+        let scale = if self.scale == 0.0 { 1.0 } else { self.scale };
+        ((value - self.offset) / scale).round() as i64
+    }
+
+    /// Attaches the metadata to a decoded value
+    pub fn describe(&self, value: f64) -> SignalValue {
+        let text = self
+            .value_table
+            .as_ref()
+            .and_then(|table| table.get(&self.raw_value(value)))
+            .cloned();
+
+        SignalValue {
+            value,
+            unit: self.unit.clone(),
+            min: self.min,
+            max: self.max,
+            text,
+            out_of_range: self.has_range() && (value < self.min || value > self.max),
+        }
+    }
+}
+
+/// A decoded value with its unit, range and value table text
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalValue {
+    pub value: f64,
+    pub unit: String,
+    pub min: f64,
+    pub max: f64,
+    /// Text of the value from the DBC value table, e.g. "DRIVE"
+    pub text: Option<String>,
+    /// The value lies outside the declared `[min|max]` range
+    pub out_of_range: bool,
+}
+
+impl fmt::Display for SignalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.text, self.unit.is_empty()) {
+            (Some(text), _) => write!(f, "{}", text),
+            (None, true) => write!(f, "{}", self.value),
+            (None, false) => write!(f, "{} {}", self.value, self.unit),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use futures_util::future::{self, BoxFuture};
+use futures_util::{FutureExt, Stream};
+use log::info;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tokio_socketcan::CANFrame;
+use crate::can_error::CanToolError;
+use crate::can_filter::{joins_filters, CAN_EFF_FLAG, CAN_INV_FILTER};
+use crate::can_transport::{CanBackend, CanTransport};
+
+#[derive(Debug)]
+struct SocketFilter {
+    masks: Vec<(u32, u32)>,
+    /// `CAN_RAW_JOIN_FILTERS`, every filter has to match
+    join: bool,
+    error_mask: u32,
+}
+
+impl Default for SocketFilter {
+    /// A new socket receives all frames, as the kernel installs a (0, 0) filter
+    fn default() -> Self {
+        SocketFilter { masks: vec![(0, 0)], join: false, error_mask: 0 }
+    }
+}
+
+impl SocketFilter {
+    /// Kernel filter semantics, any matching filter delivers the frame, or all of them when joined
+    fn accepts(&self, frame: &CANFrame) -> bool {
+        if frame.is_error() {
+            return frame.err() & self.error_mask != 0;
+        }
+
+        let can_id = if frame.is_extended() { frame.id() | CAN_EFF_FLAG } else { frame.id() };
+        let accepts = |(id, mask): &(u32, u32)| {
+            let matches = (can_id & mask) == (id & !CAN_INV_FILTER & mask);
+            if id & CAN_INV_FILTER != 0 { !matches } else { matches }
+        };
+        // an empty list receives nothing, it is never joined
+        if self.join {
+            self.masks.iter().all(accepts)
+        } else {
+            self.masks.iter().any(accepts)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SimEndpoint {
+    id: usize,
+    sender: mpsc::UnboundedSender<Option<io::Result<CANFrame>>>,
+    filter: Arc<Mutex<SocketFilter>>,
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    endpoints: Vec<SimEndpoint>,
+    next_id: usize,
+    transmitted: Vec<CANFrame>,
+    /// Errors returned by the next opens
+    open_errors: VecDeque<io::ErrorKind>,
+    /// Errors returned by the next writes
+    write_errors: VecDeque<io::ErrorKind>,
+}
+
+/// In-memory CAN bus, a stand-in for `vcan` in tests. Every opened socket receives
+/// the injected frames and the frames written by the other sockets. Nothing is
+/// received while no frame is injected, so timeouts are deterministic with
+/// `tokio::time::pause`.
+#[derive(Debug, Clone, Default)]
+pub struct SimBus {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl SimBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delivers a frame to all open sockets whose filters accept it
+    pub fn inject(&self, frame: CANFrame) {
+        self.deliver(None, frame);
+    }
+
+    /// Delivers a data frame built from an identifier and payload, IDs above 0x7FF are extended
+    pub fn inject_data(&self, id: u32, data: &[u8]) -> Result<(), CanToolError> {
+        let frame = CANFrame::new(id, data, false, false).map_err(|e| CanToolError::EncodeFailed(e.to_string()))?;
+        self.inject(frame);
+        Ok(())
+    }
+
+    /// Delivers an error frame of the given `CAN_ERR_*` classes
+    pub fn inject_error_frame(&self, class: u32, data: &[u8]) -> Result<(), CanToolError> {
+        let frame = CANFrame::new(class, data, false, true)
+            .map_err(|e| CanToolError::EncodeFailed(e.to_string()))?;
+        self.inject(frame);
+        Ok(())
+    }
+
+    /// The next read of every open socket fails, which makes `CanUtils` restart the socket
+    pub fn inject_read_error(&self, kind: io::ErrorKind) {
+        for endpoint in self.lock().endpoints.iter() {
+            let _ = endpoint.sender.send(Some(Err(io::Error::new(kind, "simulated read error"))));
+        }
+    }
+
+    /// Ends the receive streams of all open sockets
+    pub fn close(&self) {
+        let mut state = self.lock();
+        for endpoint in state.endpoints.drain(..) {
+            let _ = endpoint.sender.send(None);
+        }
+    }
+
+    /// Plays a scripted sequence, every frame is injected after its delay to the previous one
+    pub fn play(&self, script: Vec<(Duration, CANFrame)>) -> JoinHandle<()> {
+        let bus = self.clone();
+        tokio::spawn(async move {
+            for (delay, frame) in script {
+                tokio::time::sleep(delay).await;
+                bus.inject(frame);
+            }
+        })
+    }
+
+    /// The next `count` opens fail with `kind`
+    pub fn fail_opens(&self, count: usize, kind: io::ErrorKind) {
+        self.lock().open_errors.extend(std::iter::repeat_n(kind, count));
+    }
+
+    /// The next `count` writes fail with `kind`
+    pub fn fail_writes(&self, count: usize, kind: io::ErrorKind) {
+        self.lock().write_errors.extend(std::iter::repeat_n(kind, count));
+    }
+
+    /// Frames written by the sockets of the bus so far
+    pub fn transmitted(&self) -> Vec<CANFrame> {
+        self.lock().transmitted.clone()
+    }
+
+    /// Frames written so far, clearing the record
+    pub fn take_transmitted(&self) -> Vec<CANFrame> {
+        std::mem::take(&mut self.lock().transmitted)
+    }
+
+    /// Number of open sockets
+    pub fn sockets(&self) -> usize {
+        self.lock().endpoints.len()
+    }
+
+    fn deliver(&self, source: Option<usize>, frame: CANFrame) {
+        let mut state = self.lock();
+        state.endpoints.retain(|endpoint| !endpoint.sender.is_closed());
+
+        for endpoint in state.endpoints.iter().filter(|endpoint| Some(endpoint.id) != source) {
+            let accepted = match endpoint.filter.lock() {
+                Ok(filter) => filter.accepts(&frame),
+                Err(poisoned) => poisoned.into_inner().accepts(&frame),
+            };
+            if accepted {
+                let _ = endpoint.sender.send(Some(Ok(frame)));
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl CanBackend for SimBus {
+    fn open(&self, ifname: &str) -> Result<Box<dyn CanTransport>, CanToolError> {
+        let mut state = self.lock();
+        if let Some(kind) = state.open_errors.pop_front() {
+            return Err(CanToolError::Socket(io::Error::new(kind, "simulated open error")));
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let filter = Arc::new(Mutex::new(SocketFilter::default()));
+        let id = state.next_id;
+        state.next_id += 1;
+        state.endpoints.push(SimEndpoint { id, sender, filter: filter.clone() });
+
+        info!("Opened simulated CAN socket {} on {}", id, ifname);
+        Ok(Box::new(SimSocket { id, bus: self.clone(), receiver, filter, closed: false }))
+    }
+}
+
+/// Socket of a `SimBus`
+#[derive(Debug)]
+pub struct SimSocket {
+    id: usize,
+    bus: SimBus,
+    receiver: mpsc::UnboundedReceiver<Option<io::Result<CANFrame>>>,
+    filter: Arc<Mutex<SocketFilter>>,
+    closed: bool,
+}
+
+impl SimSocket {
+    fn with_filter(&self, f: impl FnOnce(&mut SocketFilter)) {
+        match self.filter.lock() {
+            Ok(mut filter) => f(&mut filter),
+            Err(poisoned) => f(&mut poisoned.into_inner()),
+        }
+    }
+}
+
+impl Stream for SimSocket {
+    type Item = io::Result<CANFrame>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.closed {
+            return Poll::Ready(None);
+        }
+
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(Some(frame))) => Poll::Ready(Some(frame)),
+            Poll::Ready(Some(None)) | Poll::Ready(None) => {
+                self.closed = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl CanTransport for SimSocket {
+    fn write_frame(&self, frame: CANFrame) -> BoxFuture<'_, io::Result<()>> {
+        let write_error = self.bus.lock().write_errors.pop_front();
+        if let Some(kind) = write_error {
+            return future::ready(Err(io::Error::new(kind, "simulated write error"))).boxed();
+        }
+
+        self.bus.lock().transmitted.push(frame);
+        self.bus.deliver(Some(self.id), frame);
+        future::ready(Ok(())).boxed()
+    }
+
+    fn set_filter(&self, filters: &[(u32, u32)]) -> io::Result<()> {
+        self.with_filter(|filter| {
+            filter.masks = filters.to_vec();
+            filter.join = joins_filters(filters);
+        });
+        Ok(())
+    }
+
+    fn set_error_filter(&self, mask: u32) -> io::Result<()> {
+        self.with_filter(|filter| filter.error_mask = mask);
+        Ok(())
+    }
+}
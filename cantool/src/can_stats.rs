@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use tokio::time::{Duration, Instant};
+use crate::can_filter::{CAN_EFF_FLAG, CAN_ERR_BUSOFF, CAN_ERR_CRTL, CAN_ERR_RESTARTED};
+
+/// Controller status bits in data[1] of a `CAN_ERR_CRTL` error frame
+const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+
+/// Frame bits without stuffing: SOF, arbitration, control, CRC, ACK, EOF and IFS
+const STANDARD_OVERHEAD_BITS: u64 = 47;
+const EXTENDED_OVERHEAD_BITS: u64 = 67;
+/// FD frames carry a longer CRC and the stuff count
+const FD_EXTRA_BITS: u64 = 10;
+
+/// Fault confinement state of the controller, from error frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorState {
+    Active,
+    Warning,
+    Passive,
+    BusOff,
+}
+
+/// Statistics of one CAN ID over the last interval
+#[derive(Debug, Clone, Default)]
+pub struct IdStats {
+    pub frames: u64,
+    pub frames_per_s: f64,
+    pub bytes_per_s: f64,
+    pub mean_cycle: Option<Duration>,
+    pub min_cycle: Option<Duration>,
+    pub max_cycle: Option<Duration>,
+    /// Standard deviation of the cycle time
+    pub jitter: Option<Duration>,
+}
+
+/// Bus statistics since the previous snapshot, counters marked total since enabling
+#[derive(Debug, Clone)]
+pub struct BusStatsSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub interval: Duration,
+    pub frames_per_s: f64,
+    pub bytes_per_s: f64,
+    /// Estimated bus load in percent of the configured bitrate, stuff bits not counted
+    pub bus_load: f64,
+    pub error_frames: u64,
+    /// Total frames
+    pub total_frames: u64,
+    /// Total error frames
+    pub total_error_frames: u64,
+    /// Total transitions into error passive
+    pub error_passive_count: u64,
+    /// Total transitions into bus-off
+    pub bus_off_count: u64,
+    pub error_state: ErrorState,
+    /// Keyed by DBC identifier, bit 31 set for extended frames
+    pub ids: HashMap<u32, IdStats>,
+}
+
+#[derive(Debug, Default)]
+struct IdCounter {
+    frames: u64,
+    bytes: u64,
+    last_seen: Option<Instant>,
+    cycles: u64,
+    /// Running mean and sum of squared deviations of the cycle in seconds (Welford)
+    mean: f64,
+    m2: f64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl IdCounter {
+    fn record(&mut self, len: usize, now: Instant) {
+        self.frames += 1;
+        self.bytes += len as u64;
+
+        if let Some(last) = self.last_seen {
+            let cycle = now.saturating_duration_since(last);
+            self.cycles += 1;
+            let delta = cycle.as_secs_f64() - self.mean;
+            self.mean += delta / self.cycles as f64;
+            self.m2 += delta * (cycle.as_secs_f64() - self.mean);
+            self.min = Some(self.min.map_or(cycle, |min| min.min(cycle)));
+            self.max = Some(self.max.map_or(cycle, |max| max.max(cycle)));
+        }
+        self.last_seen = Some(now);
+    }
+
+    fn to_stats(&self, secs: f64) -> IdStats {
+        let has_cycles = self.cycles > 0;
+        IdStats {
+            frames: self.frames,
+            frames_per_s: self.frames as f64 / secs,
+            bytes_per_s: self.bytes as f64 / secs,
+            mean_cycle: has_cycles.then(|| Duration::from_secs_f64(self.mean)),
+            min_cycle: self.min,
+            max_cycle: self.max,
+            jitter: has_cycles.then(|| Duration::from_secs_f64((self.m2 / self.cycles as f64).sqrt())),
+        }
+    }
+
+    /// Starts a new interval, the last timestamp is kept for the next cycle
+    fn reset(&mut self) {
+        *self = IdCounter { last_seen: self.last_seen, ..IdCounter::default() };
+    }
+}
+
+/// Accumulates frame and error counters of one interface
+#[derive(Debug)]
+pub struct BusStats {
+    bitrate: u32,
+    interval_start: Instant,
+    frames: u64,
+    bytes: u64,
+    bits: u64,
+    error_frames: u64,
+    total_frames: u64,
+    total_error_frames: u64,
+    error_passive_count: u64,
+    bus_off_count: u64,
+    error_state: ErrorState,
+    ids: HashMap<u32, IdCounter>,
+}
+
+impl BusStats {
+    /// `bitrate` is the nominal bitrate of the bus in bit/s, used for the bus load
+    pub fn new(bitrate: u32) -> Self {
+        BusStats {
+            bitrate,
+            interval_start: Instant::now(),
+            frames: 0,
+            bytes: 0,
+            bits: 0,
+            error_frames: 0,
+            total_frames: 0,
+            total_error_frames: 0,
+            error_passive_count: 0,
+            bus_off_count: 0,
+            error_state: ErrorState::Active,
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Counts a received data frame
+    pub fn record_frame(&mut self, id: u32, extended: bool, len: usize, fd: bool, now: Instant) {
+        self.frames += 1;
+        self.total_frames += 1;
+        self.bytes += len as u64;
+        self.bits += frame_bits(extended, len, fd);
+        // a standard and an extended frame with the same ID are different messages
+        let dbc_id = if extended { id | CAN_EFF_FLAG } else { id };
+        self.ids.entry(dbc_id).or_default().record(len, now);
+    }
+
+    /// Counts an error frame, `class` are the `CAN_ERR_*` bits of its ID
+    pub fn record_error(&mut self, class: u32, data: &[u8]) {
+        self.error_frames += 1;
+        self.total_error_frames += 1;
+
+        let state = if class & CAN_ERR_BUSOFF != 0 {
+            Some(ErrorState::BusOff)
+        } else if class & CAN_ERR_RESTARTED != 0 {
+            Some(ErrorState::Active)
+        } else if class & CAN_ERR_CRTL != 0 {
+            let status = data.get(1).copied().unwrap_or(0);
+            if status & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+                Some(ErrorState::Passive)
+            } else if status & (CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING) != 0 {
+                Some(ErrorState::Warning)
+            } else if status & CAN_ERR_CRTL_ACTIVE != 0 {
+                Some(ErrorState::Active)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        if let Some(state) = state {
+            if state != self.error_state {
+                match state {
+                    ErrorState::Passive => self.error_passive_count += 1,
+                    ErrorState::BusOff => self.bus_off_count += 1,
+                    _ => {}
+                }
+                self.error_state = state;
+            }
+        }
+    }
+
+    /// Statistics of the interval since the previous snapshot, starts a new interval
+    pub fn snapshot(&mut self, now: Instant) -> BusStatsSnapshot {
+        let interval = now.saturating_duration_since(self.interval_start);
+        // avoid division by zero for back to back snapshots
+        let secs = interval.as_secs_f64().max(1e-6);
+
+        let snapshot = BusStatsSnapshot {
+            timestamp: Utc::now(),
+            interval,
+            frames_per_s: self.frames as f64 / secs,
+            bytes_per_s: self.bytes as f64 / secs,
+            bus_load: match self.bitrate {
+                0 => 0.0,
+                bitrate => (self.bits as f64 / secs / bitrate as f64 * 100.0).min(100.0),
+            },
+            error_frames: self.error_frames,
+            total_frames: self.total_frames,
+            total_error_frames: self.total_error_frames,
+            error_passive_count: self.error_passive_count,
+            bus_off_count: self.bus_off_count,
+            error_state: self.error_state,
+            ids: self
+                .ids
+                .iter()
+                .filter(|(_, counter)| counter.frames > 0)
+                .map(|(id, counter)| (*id, counter.to_stats(secs)))
+                .collect(),
+        };
+
+        self.interval_start = now;
+        self.frames = 0;
+        self.bytes = 0;
+        self.bits = 0;
+        self.error_frames = 0;
+        self.ids.values_mut().for_each(IdCounter::reset);
+
+        snapshot
+    }
+}
+
+/// Bits on the wire of a frame, FD data phase bits are counted at the nominal rate
+fn frame_bits(extended: bool, len: usize, fd: bool) -> u64 {
+    let overhead = if extended { EXTENDED_OVERHEAD_BITS } else { STANDARD_OVERHEAD_BITS };
+    let extra = if fd { FD_EXTRA_BITS } else { 0 };
+    overhead + extra + 8 * len as u64
+}
+
+/// Shared statistics of a `CanUtils` instance that can be polled while receiving
+#[derive(Debug, Clone)]
+pub struct BusMonitor {
+    stats: Arc<Mutex<BusStats>>,
+}
+
+impl BusMonitor {
+    pub fn new(bitrate: u32) -> Self {
+        BusMonitor { stats: Arc::new(Mutex::new(BusStats::new(bitrate))) }
+    }
+
+    /// Statistics since the previous call
+    pub fn snapshot(&self) -> BusStatsSnapshot {
+        self.with_stats(|stats| stats.snapshot(Instant::now()))
+    }
+
+    pub(crate) fn record_frame(&self, id: u32, extended: bool, len: usize, fd: bool) {
+        let now = Instant::now();
+        self.with_stats(|stats| stats.record_frame(id, extended, len, fd, now));
+    }
+
+    pub(crate) fn record_error(&self, class: u32, data: &[u8]) {
+        self.with_stats(|stats| stats.record_error(class, data));
+    }
+
+    fn with_stats<T>(&self, f: impl FnOnce(&mut BusStats) -> T) -> T {
+        let mut stats = match self.stats.lock() {
+            Ok(stats) => stats,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut stats)
+    }
+}
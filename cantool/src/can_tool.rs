@@ -1,146 +1,584 @@
 extern crate chrono;
 use log::{error, info, warn};
 use std::collections::HashMap;
-use std::path::Path;
-use canparse::pgn::{ParseMessage, PgnLibrary};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use canparse::pgn::PgnLibrary;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::time::Duration;
-use tokio_socketcan::{CANFilter, CANSocket, CANFrame, Error};
-use futures_util::{stream::StreamExt, TryStreamExt};
+use tokio_socketcan::{CANFrame, Error};
+use futures_util::{stream::{self, BoxStream, StreamExt}, TryStreamExt};
+use chrono::{DateTime, Utc};
+use crate::can_codec::{DecodedSignal, SignalLayout};
+use crate::can_dbc::{DbcInfo, DbcWatcher};
+use crate::can_e2e::{self, E2eConfig, E2eProtection, E2eStats, E2eStatus};
+use crate::can_error::CanToolError;
+use crate::can_fd::CanFdSocket;
+use crate::can_filter::{FrameFilter, ResolvedFilters, CAN_EFF_FLAG, CAN_ERR_MASK};
+use crate::can_retry::{ConnectionState, HealthMonitor, RetryPolicy};
+use crate::can_signal::{SignalMeta, SignalValue};
+use crate::can_stats::BusMonitor;
+use crate::can_transport::{CanBackend, CanTransport, SocketCanBackend};
+use crate::can_trigger::{Trigger, TriggerEvent, TriggerSet};
+use crate::can_virtual::VirtualSignals;
+use crate::can_frame::RawFrame;
+use crate::j1939_dm;
+use crate::j1939_tp::{self, J1939Id, J1939Message, TpEvent, TpReassembler, TpSendState, TpSender};
 
 const CAN_RECV_TIMEOUT_S: u64 = 10;
+const TX_QUEUE_LEN: usize = 64;
+
+/// Filters to install on a socket, no filters receive everything as a socket without
+/// filters does, while the kernel delivers nothing for an empty list
+fn socket_filters(filters: &[(u32, u32)]) -> &[(u32, u32)] {
+    if filters.is_empty() { &[(0, 0)] } else { filters }
+}
+
+fn apply_filters(socket: &dyn CanTransport, filters: &[(u32, u32)], error_mask: u32) -> Result<(), CanToolError> {
+    if let Err(e) = socket.set_filter(socket_filters(filters)) {
+        error!("Failed to set CAN filters: {}", e);
+        return Err(CanToolError::Socket(e));
+    }
+
+    if error_mask != 0 {
+        if let Err(e) = socket.set_error_filter(error_mask) {
+            error!("Failed to set CAN error filter: {}", e);
+            return Err(CanToolError::Socket(e));
+        }
+    }
+    Ok(())
+}
+
+/// Identifier of a received frame as used for DBC lookups, see `RawFrame::dbc_id`
+fn dbc_id(frame: &CANFrame) -> u32 {
+    if frame.is_extended() {
+        frame.id() | CAN_EFF_FLAG
+    } else {
+        frame.id()
+    }
+}
+
+/// Classic frame with the format of `frame`, `CANFrame::new` makes IDs up to 0x7FF standard
+fn classic_frame(frame: &RawFrame) -> Result<CANFrame, CanToolError> {
+    let mut can_frame =
+        CANFrame::new(frame.id, &frame.data, false, false).map_err(|e| CanToolError::EncodeFailed(e.to_string()))?;
+    if frame.extended && !can_frame.is_extended() {
+        // SAFETY: CANFrame is the repr(C) `struct can_frame`, whose first field is the u32 can_id
+        unsafe { *(&mut can_frame as *mut CANFrame as *mut u32) |= CAN_EFF_FLAG };
+    }
+    Ok(can_frame)
+}
+
+fn physical_values(signals: HashMap<String, DecodedSignal>) -> HashMap<String, f64> {
+    signals.into_iter().map(|(name, decoded)| (name, decoded.value)).collect()
+}
+
+/// Decoded signals of a single received frame
+#[derive(Debug, Clone)]
+pub struct SignalUpdate {
+    pub timestamp: DateTime<Utc>,
+    /// DBC identifier of the frame, bit 31 set for extended frames
+    pub frame_id: u32,
+    pub signals: HashMap<String, f64>,
+    /// Result of the end-to-end check for protected messages
+    pub e2e: Option<E2eStatus>,
+}
+
+struct TxRequest {
+    message_name: String,
+    values: HashMap<String, f64>,
+    reply: oneshot::Sender<Result<(), CanToolError>>,
+}
+
+/// Sends DBC messages through a `CanUtils` whose `signal_stream` runs in another task,
+/// the requests are served between received frames
+#[derive(Debug, Clone)]
+pub struct CanTransmitter {
+    requests: mpsc::Sender<TxRequest>,
+}
+
+impl CanTransmitter {
+    /// Encodes and sends a message like `encode_and_send`, messages longer than 8 bytes
+    /// are sent as CAN FD frames with bit rate switch
+    pub async fn send(&self, message_name: &str, values: HashMap<String, f64>) -> Result<(), CanToolError> {
+        let (reply, response) = oneshot::channel();
+        let request = TxRequest { message_name: message_name.to_string(), values, reply };
+        self.requests.send(request).await.map_err(|_| CanToolError::Closed)?;
+        response.await.map_err(|_| CanToolError::Closed)?
+    }
+}
 
 #[derive(Debug)]
 pub struct CanUtils {
     canport: String,
-    filters: Vec<CANFilter>,
+    filter_specs: Vec<FrameFilter>,
+    filter_masks: Vec<(u32, u32)>,
+    error_mask: u32,
     can_info: PgnLibrary,
+    dbc_info: DbcInfo,
     id_and_signal: HashMap<u32, Vec<String>>,
-    can_socket: CANSocket,
+    backend: Arc<dyn CanBackend>,
+    can_socket: Box<dyn CanTransport>,
+    fd_socket: Option<CanFdSocket>,
+    tp_reassembler: TpReassembler,
+    retry_policy: RetryPolicy,
+    health: HealthMonitor,
+    dbc_path: PathBuf,
+    dbc_watcher: Option<DbcWatcher>,
+    stats: Option<BusMonitor>,
+    e2e: HashMap<u32, E2eProtection>,
+    virtual_signals: Option<VirtualSignals>,
+    triggers: TriggerSet,
+    tx_sender: mpsc::Sender<TxRequest>,
+    tx_requests: mpsc::Receiver<TxRequest>,
 }
 
 impl CanUtils {
-    const DEFAULT_DBC_PATH: &'static str = "/usr/share/can-dbcs/consolidated.dbc";
+    pub const DEFAULT_DBC_PATH: &'static str = "/usr/share/can-dbcs/consolidated.dbc";
 
-    /// Creates a new CanUtils instance asynchronously
+    /// Creates a new CanUtils instance asynchronously with the default retry policy
     pub async fn new(
         ifname: &str, 
         dbc_path: Option<&Path>, 
         ids_filter: Vec<u32>
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {  // Add Send + Sync
+    ) -> Result<Self, CanToolError> {  // Add Send + Sync
+        let filters = ids_filter.into_iter().map(FrameFilter::Id).collect();
+        Self::new_with_policy(ifname, dbc_path, filters, RetryPolicy::default(), HealthMonitor::new()).await
+    }
+
+    /// Creates a new CanUtils instance receiving only the frames selected by `filters`,
+    /// e.g. `FrameFilter::signals(["BatterySOC", "MotorRPM"])`
+    pub async fn new_with_filters(
+        ifname: &str,
+        dbc_path: Option<&Path>,
+        filters: Vec<FrameFilter>,
+    ) -> Result<Self, CanToolError> {
+        Self::new_with_policy(ifname, dbc_path, filters, RetryPolicy::default(), HealthMonitor::new()).await
+    }
+
+    /// Creates a new CanUtils instance, loading the DBC file and opening the socket
+    /// are retried according to `policy` and reported through `health`
+    pub async fn new_with_policy(
+        ifname: &str,
+        dbc_path: Option<&Path>,
+        filter_specs: Vec<FrameFilter>,
+        policy: RetryPolicy,
+        health: HealthMonitor,
+    ) -> Result<Self, CanToolError> {
+        Self::new_with_backend(Arc::new(SocketCanBackend), ifname, dbc_path, filter_specs, policy, health).await
+    }
+
+    /// Creates a new CanUtils instance on another transport, e.g. a `SimBus` in tests
+    pub async fn new_with_backend(
+        backend: Arc<dyn CanBackend>,
+        ifname: &str,
+        dbc_path: Option<&Path>,
+        filter_specs: Vec<FrameFilter>,
+        policy: RetryPolicy,
+        health: HealthMonitor,
+    ) -> Result<Self, CanToolError> {
         let dbc_path = dbc_path.unwrap_or_else(|| Path::new(Self::DEFAULT_DBC_PATH));
 
-        loop {
-            if !dbc_path.exists() {
-                warn!("DBC file not found, retrying in 1 second...");
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                continue;
+        health.set_state(ConnectionState::Connecting);
+        let (can_info, dbc_info, socket_can, resolved) = policy
+            .run(&health, || async {
+                if !dbc_path.exists() {
+                    return Err(CanToolError::DbcLoad(format!("{} not found", dbc_path.display())));
+                }
+
+                let can_info = PgnLibrary::from_dbc_file(dbc_path)
+                    .map_err(|e| CanToolError::DbcLoad(format!("{}: {}", dbc_path.display(), e)))?;
+                let dbc_info = DbcInfo::from_dbc_file(dbc_path)
+                    .map_err(|e| CanToolError::DbcLoad(format!("{}: {}", dbc_path.display(), e)))?;
+
+                // Signal and message names are resolved through the DBC
+                let resolved = ResolvedFilters::resolve(&filter_specs, &dbc_info)?;
+
+                let socket_can = backend.open(ifname)?;
+                apply_filters(socket_can.as_ref(), &resolved.masks, resolved.error_mask)?;
+
+                Ok((can_info, dbc_info, socket_can, resolved))
+            })
+            .await?;
+
+        let id_and_signal = Self::signals_by_id(&can_info);
+        let (tx_sender, tx_requests) = mpsc::channel(TX_QUEUE_LEN);
+
+        Ok(CanUtils {
+            canport: ifname.to_string(),
+            filter_specs,
+            filter_masks: resolved.masks,
+            error_mask: resolved.error_mask,
+            can_info,
+            dbc_info,
+            id_and_signal,
+            backend,
+            can_socket: socket_can,
+            fd_socket: None,
+            tp_reassembler: TpReassembler::new(None),
+            retry_policy: policy,
+            health,
+            dbc_path: dbc_path.to_path_buf(),
+            dbc_watcher: None,
+            stats: None,
+            e2e: HashMap::new(),
+            virtual_signals: None,
+            triggers: TriggerSet::new(),
+            tx_sender,
+            tx_requests,
+        })
+    }
+
+    fn signals_by_id(can_info: &PgnLibrary) -> HashMap<u32, Vec<String>> {
+        can_info
+            .hash_of_canid_signals()
+            .into_iter()
+            .map(|(k, v)| (k, v.into_iter().map(String::from).collect()))
+            .collect()
+    }
+
+    /// Loads a new DBC file and swaps the definitions, the socket and filters stay open.
+    /// On parse errors, or when a watched file cannot be watched at its new path, the
+    /// previous definitions are kept.
+    pub fn reload_dbc(&mut self, path: &Path) -> Result<(), CanToolError> {
+        let loaded = PgnLibrary::from_dbc_file(path)
+            .map_err(|e| CanToolError::DbcLoad(format!("{}: {}", path.display(), e)))
+            .and_then(|can_info| {
+                let dbc_info = DbcInfo::from_dbc_file(path)
+                    .map_err(|e| CanToolError::DbcLoad(format!("{}: {}", path.display(), e)))?;
+                Ok((can_info, dbc_info))
+            });
+
+        let (can_info, dbc_info) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Failed to reload DBC, keeping previous definitions: {}", e);
+                return Err(e);
             }
+        };
 
-            match PgnLibrary::from_dbc_file(dbc_path) {
-                Ok(can_info) => {
-                    let socket_can = match CANSocket::open(ifname) {
-                        Ok(s) => s,
-                        Err(e) => {
-                            error!("Failed to open CAN socket on {}: {}. Retrying...", ifname, e);
-                            tokio::time::sleep(Duration::from_secs(1)).await;
-                            continue;
-                        }
-                    };
-
-                    let filters: Vec<CANFilter> = ids_filter
-                        .into_iter()
-                        .map(|id| CANFilter::new(id, 0x1FFFFFFF)) // 0x1FFFFFFF for full mask
-                        .collect::<Result<Vec<CANFilter>, _>>()?;
-
-                    // Set filters if available
-                    if !filters.is_empty() {
-                        if let Err(e) = socket_can.set_filter(&filters) {
-                            error!("Failed to set CAN filters: {}", e);
-                            return Err(Box::new(e));
-                        }
-                    }
+        // move the watcher to the new file first, a failure keeps the previous definitions
+        if self.dbc_path != path && self.dbc_watcher.is_some() {
+            let watcher = DbcWatcher::new(path).map_err(|e| {
+                let e = CanToolError::DbcLoad(format!("watching {}: {}", path.display(), e));
+                error!("Failed to reload DBC, keeping previous definitions: {}", e);
+                e
+            })?;
+            self.dbc_watcher = Some(watcher);
+        }
+        self.dbc_path = path.to_path_buf();
 
-                    let id_and_signal = can_info
-                        .hash_of_canid_signals()
-                        .into_iter()
-                        .map(|(k, v)| (k, v.into_iter().map(String::from).collect()))
-                        .collect::<HashMap<u32, Vec<String>>>();
+        self.id_and_signal = Self::signals_by_id(&can_info);
+        self.can_info = can_info;
+        self.dbc_info = dbc_info;
 
-                    return Ok(CanUtils {
-                        canport: ifname.to_string(),
-                        filters,
-                        can_info,
-                        id_and_signal,
-                        can_socket: socket_can,
-                    });
-                }
-                Err(e) => {
-                    error!("Failed to load DBC file {}: {}. Retrying...", dbc_path.display(), e);
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    continue;
-                }
+        if self.filter_specs.iter().any(FrameFilter::uses_dbc) {
+            // a failing name keeps the previous kernel filters
+            let _ = self.set_filters(self.filter_specs.clone());
+        }
+
+        info!("Reloaded DBC {}", path.display());
+        Ok(())
+    }
+
+    /// Watches the DBC file and reloads it before the next frame is decoded after a change
+    pub fn watch_dbc(&mut self) -> Result<(), CanToolError> {
+        let watcher = DbcWatcher::new(&self.dbc_path)
+            .map_err(|e| CanToolError::DbcLoad(format!("watching {}: {}", self.dbc_path.display(), e)))?;
+        self.dbc_watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// Stops watching the DBC file
+    pub fn unwatch_dbc(&mut self) {
+        self.dbc_watcher = None;
+    }
+
+    /// Replaces the frame filters of the open sockets
+    pub fn set_filters(&mut self, filter_specs: Vec<FrameFilter>) -> Result<(), CanToolError> {
+        let resolved = ResolvedFilters::resolve(&filter_specs, &self.dbc_info).map_err(|e| {
+            error!("Failed to resolve CAN filters: {}", e);
+            e
+        })?;
+        // statistics need all error frames
+        let error_mask = if self.stats.is_some() { CAN_ERR_MASK } else { resolved.error_mask };
+
+        apply_filters(self.can_socket.as_ref(), &resolved.masks, error_mask)?;
+        if let Some(socket) = self.fd_socket.as_ref() {
+            if let Err(e) = socket.set_filter(socket_filters(&resolved.masks)) {
+                error!("Failed to set CAN FD filters: {}", e);
+                return Err(CanToolError::Socket(e));
             }
         }
+
+        self.filter_specs = filter_specs;
+        self.filter_masks = resolved.masks;
+        self.error_mask = error_mask;
+        Ok(())
     }
 
-    /// Restarts the CAN socket
-    async fn restart_socket(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        loop {
-            match CANSocket::open(&self.canport) {
-                Ok(socket) => {
-                    self.can_socket = socket;
-                    info!("Successfully restarted CAN socket.");
-                    
-                    // Reapply filters if necessary
-                    if !self.filters.is_empty() {
-                        if let Err(e) = self.can_socket.set_filter(&self.filters) {
-                            error!("Failed to reapply CAN filters: {}", e);
-                            return Err(Box::new(e));
-                        }
-                    }
-                    return Ok(());
-                }
-                Err(e) => {
-                    error!("Failed to restart CAN socket: {}. Retrying in 1 second...", e);
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+    /// Starts counting received frames and error frames, `bitrate` is the nominal
+    /// bitrate of the bus in bit/s for the bus load estimate
+    pub fn enable_stats(&mut self, bitrate: u32) -> Result<BusMonitor, CanToolError> {
+        if let Some(stats) = self.stats.as_ref() {
+            return Ok(stats.clone());
+        }
+
+        apply_filters(self.can_socket.as_ref(), &self.filter_masks, CAN_ERR_MASK)?;
+        self.error_mask = CAN_ERR_MASK;
+
+        let stats = BusMonitor::new(bitrate);
+        self.stats = Some(stats.clone());
+        Ok(stats)
+    }
+
+    /// Statistics of the interface if enabled
+    pub fn stats(&self) -> Option<BusMonitor> {
+        self.stats.clone()
+    }
+
+    fn observe(&self, frame: &CANFrame) {
+        if let Some(stats) = self.stats.as_ref() {
+            if frame.is_error() {
+                stats.record_error(frame.err(), frame.data());
+            } else {
+                stats.record_frame(frame.id(), frame.is_extended(), frame.data().len(), false);
+            }
+        }
+    }
+
+    fn observe_raw(&self, frame: &RawFrame) {
+        if let Some(stats) = self.stats.as_ref() {
+            stats.record_frame(frame.id, frame.extended, frame.data.len(), frame.fd);
+        }
+    }
+
+    /// Bit layout of a signal, e.g. to patch a counter or checksum into an encoded payload
+    pub fn signal_layout(&self, signal: &str) -> Option<SignalLayout> {
+        match self.dbc_info.signal(signal) {
+            Some(definition) => Some(definition.layout),
+            None => self.can_info.get_spn(signal).map(SignalLayout::from),
+        }
+    }
+
+    /// Enables counter and checksum checks of a received message, transmissions through
+    /// `encode_and_send` get the counter and checksum filled in
+    pub fn set_e2e(&mut self, message_name: &str, config: &E2eConfig) -> Result<(), CanToolError> {
+        let id = self
+            .dbc_info
+            .message(message_name)
+            .map(|message| message.id)
+            .ok_or_else(|| CanToolError::MessageNotInDbc(message_name.to_string()))?;
+
+        let protection = E2eProtection::new(config, self)?;
+        self.e2e.insert(id, protection);
+        Ok(())
+    }
+
+    pub fn clear_e2e(&mut self, message_name: &str) {
+        if let Some(message) = self.dbc_info.message(message_name) {
+            self.e2e.remove(&message.id);
+        }
+    }
+
+    /// Check results of a protected message
+    pub fn e2e_stats(&self, message_name: &str) -> Option<E2eStats> {
+        let message = self.dbc_info.message(message_name)?;
+        self.e2e.get(&message.id).map(E2eProtection::stats)
+    }
+
+    /// Checks a protected message, returns the status (None if unprotected) and
+    /// whether its signals are delivered
+    fn check_e2e(&mut self, dbc_id: u32, data: &[u8]) -> (Option<E2eStatus>, bool) {
+        match self.e2e.get_mut(&dbc_id) {
+            Some(protection) => {
+                let status = protection.verify(data);
+                can_e2e::log_failure(dbc_id, &status);
+                let accepted = protection.accepts(&status);
+                (Some(status), accepted)
+            }
+            None => (None, true),
+        }
+    }
+
+    fn protect_e2e(&mut self, message_name: &str, data: &mut [u8]) -> Result<(), CanToolError> {
+        let id = match self.dbc_info.message(message_name) {
+            Some(message) => message.id,
+            None => return Ok(()),
+        };
+
+        match self.e2e.get_mut(&id) {
+            Some(protection) => protection.protect(data),
+            None => Ok(()),
+        }
+    }
+
+    /// Unit, range and value table of a signal in the message with DBC identifier `frame_id`
+    pub fn signal_meta(&self, frame_id: u32, signal: &str) -> Option<SignalMeta> {
+        self.can_info
+            .get_spn(signal)
+            .map(|spn| SignalMeta::new(spn, &self.dbc_info, frame_id))
+    }
+
+    /// Attaches the DBC metadata to a decoded value, None for signals unknown to the DBC
+    pub fn describe(&self, frame_id: u32, signal: &str, value: f64) -> Option<SignalValue> {
+        self.signal_meta(frame_id, signal).map(|meta| meta.describe(value))
+    }
+
+    /// Attaches the DBC metadata to all decoded values of a frame, e.g. of a `SignalUpdate`
+    pub fn describe_all(&self, frame_id: u32, signals: &HashMap<String, f64>) -> HashMap<String, SignalValue> {
+        signals
+            .iter()
+            .filter_map(|(signal, value)| {
+                let described = self.describe(frame_id, signal, *value)?;
+                if described.out_of_range {
+                    warn!("Signal {} value {} outside of [{}|{}]", signal, value, described.min, described.max);
                 }
+                Some((signal.clone(), described))
+            })
+            .collect()
+    }
+
+    /// Computes virtual signals on every decoded update, their values are delivered
+    /// alongside the received signals
+    pub fn set_virtual_signals(&mut self, virtual_signals: VirtualSignals) {
+        for input in virtual_signals.inputs() {
+            if self.dbc_info.signal(&input).is_none() && self.can_info.get_spn(&input).is_none() {
+                warn!("Virtual signals use {} which is not in the DBC", input);
             }
         }
+        self.virtual_signals = Some(virtual_signals);
+    }
+
+    pub fn clear_virtual_signals(&mut self) {
+        self.virtual_signals = None;
+    }
+
+    /// Virtual signals in use, e.g. to reset a trip integral
+    pub fn virtual_signals_mut(&mut self) -> Option<&mut VirtualSignals> {
+        self.virtual_signals.as_mut()
+    }
+
+    /// Registers a trigger on a decoded or virtual signal, events are received through
+    /// `subscribe_triggers`. A trigger with the same name is replaced.
+    pub fn add_trigger(&mut self, trigger: Trigger) -> Result<(), CanToolError> {
+        let is_virtual = self
+            .virtual_signals
+            .as_ref()
+            .is_some_and(|virtual_signals| virtual_signals.names().any(|name| name == trigger.signal));
+        if !is_virtual && self.dbc_info.signal(&trigger.signal).is_none() && self.can_info.get_spn(&trigger.signal).is_none() {
+            error!("Trigger {} uses unknown signal {}", trigger.name, trigger.signal);
+            return Err(CanToolError::SignalNotInDbc(trigger.signal));
+        }
+        self.triggers.add(trigger)
+    }
+
+    /// Removes a trigger, returns whether it existed
+    pub fn remove_trigger(&mut self, name: &str) -> bool {
+        self.triggers.remove(name)
+    }
+
+    /// Events of all triggers fired from now on
+    pub fn subscribe_triggers(&self) -> broadcast::Receiver<TriggerEvent> {
+        self.triggers.subscribe()
+    }
+
+    /// Adds the virtual signals to a decoded update and checks the triggers
+    fn process_update(&mut self, timestamp: DateTime<Utc>, signals: &mut HashMap<String, f64>) {
+        if let Some(virtual_signals) = self.virtual_signals.as_mut() {
+            virtual_signals.update(timestamp, signals);
+        }
+        self.triggers.evaluate(timestamp, signals);
+    }
+
+    /// Path of the DBC file currently loaded
+    pub fn dbc_path(&self) -> &Path {
+        &self.dbc_path
+    }
+
+    fn reload_if_changed(&mut self) {
+        let changed = self.dbc_watcher.as_ref().is_some_and(|watcher| watcher.take_changed());
+        if changed {
+            let path = self.dbc_path.clone();
+            // errors are logged and the previous definitions stay active
+            let _ = self.reload_dbc(&path);
+        }
+    }
+
+    /// Connection health shared with the instance, e.g. for a supervisor
+    pub fn health(&self) -> HealthMonitor {
+        self.health.clone()
+    }
+
+    /// Replaces the policy used when the socket has to be restarted
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Restarts the CAN socket
+    async fn restart_socket(&mut self) -> Result<(), CanToolError> {
+        self.health.set_state(ConnectionState::Reconnecting);
+        let canport = &self.canport;
+        let backend = &self.backend;
+        let filters = &self.filter_masks;
+        let error_mask = self.error_mask;
+
+        let socket = self
+            .retry_policy
+            .run(&self.health, || async move {
+                let socket = backend.open(canport)?;
+
+                // Reapply filters if necessary
+                apply_filters(socket.as_ref(), filters, error_mask)?;
+                Ok(socket)
+            })
+            .await?;
+
+        self.can_socket = socket;
+        info!("Successfully restarted CAN socket.");
+
+        if self.fd_socket.is_some() {
+            self.enable_fd()?;
+        }
+        Ok(())
     }
 
     /// Asynchronously fetches signals from CAN frames with socket restart logic and timeout
     pub async fn get_signals(
         &mut self,
-    ) -> Result<HashMap<String, f32>, Box<dyn std::error::Error + Send + Sync>> {
-        let mut result: HashMap<String, f32> = HashMap::new();
+    ) -> Result<HashMap<String, f64>, CanToolError> {
+        let mut result: HashMap<String, f64>;
         loop {
             // Use the `timeout` function with the resolved duration
             let frame_result = tokio::time::timeout(tokio::time::Duration::from_secs(CAN_RECV_TIMEOUT_S), self.can_socket.next()).await;
+            self.reload_if_changed();
+            if let Ok(Some(Ok(frame))) = &frame_result {
+                self.observe(frame);
+            }
 
             match frame_result {
+                Ok(Some(Ok(frame))) if frame.is_error() => {
+                    warn!("CAN error frame, class {:x}", frame.err());
+                    continue;
+                }
                 Ok(Some(Ok(frame))) => {
-                    let frame_id = frame.id() | 0x80000000;
-
-                    if let Some(signals) = self.id_and_signal.get(&frame_id) {
-                        for signal in signals {
-                            if let Some(signal_info) = self.can_info.get_spn(signal) {
-                                let mut can_padded_msg = [0u8; 8];
-                                can_padded_msg[..frame.data().len()].copy_from_slice(&frame.data());
-
-                                if let Some(value) = signal_info.parse_message(&can_padded_msg) {
-                                    result.insert(signal.clone(), value);
-                                } else {
-                                    error!("Failed to parse message for signal: {}", signal);
-                                    return Err("Failed to parse message, please check the DBC file.".into());
-                                }
-                            } else {
-                                error!("Signal not found in DBC: {}", signal);
-                                return Err("Signal not found in DBC.".into());
+                    let frame_id = dbc_id(&frame);
+                    if !self.check_e2e(frame_id, frame.data()).1 {
+                        continue;
+                    }
+
+                    if let Some(signals) = self.decode_signals(frame_id, frame.data()) {
+                        match signals {
+                            Ok(signals) => result = physical_values(signals),
+                            Err(e) => {
+                                error!("Failed to parse message {:x}: {}", frame_id, e);
+                                return Err(e);
                             }
                         }
                     } else {
-                        error!("Message ID {:x} not found in DBC", frame.id());
-                        return Err("Message ID not found in DBC.".into());
+                        // Unknown IDs are not fatal, keep waiting for a known frame
+                        warn!("{}", CanToolError::UnknownId(frame_id));
+                        continue;
                     }
 
                     break; // Exit loop on successful frame reception
@@ -152,59 +590,516 @@ impl CanUtils {
                 }
                 Ok(None) => {
                     error!("No more frames available from the CAN socket.");
-                    return Err("No more frames available.".into());
+                    return Err(CanToolError::Closed);
                 }
                 Err(_) => {
                     warn!("CAN Underun!!!");
-                    return Err("No more frames available.".into());
+                    return Err(CanToolError::Timeout);
                 }
             }
         }
 
+        self.process_update(Utc::now(), &mut result);
         Ok(result)
     }
 
     /// Asynchronously fetches signals from CAN frames with socket restart logic and timeout
     pub async fn try_get_signals(
         &mut self,
-    ) -> Result<HashMap<String, f32>, Box<dyn std::error::Error + Send + Sync>> {
-        let mut result: HashMap<String, f32> = HashMap::new();
+    ) -> Result<HashMap<String, f64>, CanToolError> {
         loop {
             // Use the `timeout` function with the resolved duration
             let frame_result = self.can_socket.try_next().await;
+            self.reload_if_changed();
+            if let Ok(Some(frame)) = &frame_result {
+                self.observe(frame);
+            }
             match frame_result {
+                Ok(Some(_frame)) if _frame.is_error() => {
+                    warn!("CAN error frame, class {:x}", _frame.err());
+                    continue;
+                }
                 Ok(Some(_frame)) => {
-                    let frame_id = _frame.id() | 0x80000000;
-                    if let Some(signals) = self.id_and_signal.get(&frame_id) {
-                        for signal in signals {
-                            if let Some(signal_info) = self.can_info.get_spn(signal) {
-                                let mut can_padded_msg = [0u8; 8];
-                                can_padded_msg[.._frame.data().len()].copy_from_slice(&_frame.data());
-
-                                if let Some(value) = signal_info.parse_message(&can_padded_msg) {
-                                    result.insert(signal.clone(), value);
-                                } else {
-                                    error!("Failed to parse message for signal: {}", signal);
-                                    return Err("Failed to parse message, please check the DBC file.".into());
-                                }
-                            } else {
-                                error!("Signal not found in DBC: {}", signal);
-                                return Err("Signal not found in DBC.".into());
+                    let frame_id = dbc_id(&_frame);
+                    if !self.check_e2e(frame_id, _frame.data()).1 {
+                        continue;
+                    }
+                    if let Some(signals) = self.decode_signals(frame_id, _frame.data()) {
+                        return match signals {
+                            Ok(signals) => {
+                                let mut result = physical_values(signals);
+                                self.process_update(Utc::now(), &mut result);
+                                Ok(result)
                             }
-                        }
-                        return Ok(result);
+                            Err(e) => {
+                                error!("Failed to parse message {:x}: {}", frame_id, e);
+                                Err(e)
+                            }
+                        };
                     } else {
-                        error!("Message ID {:x} not found in DBC", _frame.id());
-                        return Err("Message ID not found in DBC.".into());
+                        // Unknown IDs are not fatal, keep waiting for a known frame
+                        warn!("{}", CanToolError::UnknownId(frame_id));
+                        continue;
                     }
                 }
                 Ok(None) => {
-                    return Err("No more frames available.".into());
+                    return Err(CanToolError::Closed);
                 }
                 Err(_e) => {
                     error!("Failed to receive CAN frame: {}, sleep a bit", _e);
                     tokio::time::sleep(Duration::from_secs(1)).await;
-                    return Err("Failed to receive CAN frame".into());
+                    return Err(CanToolError::Socket(_e));
+                }
+            }
+        }
+    }
+
+    /// Packs signal values into the payload of a DBC message, the frame is extended when
+    /// the DBC marks the message as such
+    pub fn encode_message(
+        &self,
+        message_name: &str,
+        values: &HashMap<String, f64>,
+    ) -> Result<RawFrame, CanToolError> {
+        let message = match self.dbc_info.message(message_name) {
+            Some(message) => message,
+            None => {
+                error!("Message not found in DBC: {}", message_name);
+                return Err(CanToolError::MessageNotInDbc(message_name.to_string()));
+            }
+        };
+
+        let mut data = vec![0u8; message.dlc];
+        for (signal, value) in values {
+            if !message.signals.contains(signal) {
+                error!("Signal {} is not part of message {}", signal, message_name);
+                return Err(CanToolError::SignalNotInDbc(signal.clone()));
+            }
+
+            match self.signal_layout(signal) {
+                Some(layout) => layout.encode(*value, &mut data)?,
+                None => {
+                    error!("Signal not found in DBC: {}", signal);
+                    return Err(CanToolError::SignalNotInDbc(signal.clone()));
+                }
+            }
+        }
+
+        Ok(RawFrame::from_dbc_id(message.id, &data))
+    }
+
+    /// Encodes signal values with the DBC definition of a message and transmits the frame
+    pub async fn encode_and_send(
+        &mut self,
+        message_name: &str,
+        values: HashMap<String, f64>,
+    ) -> Result<(), CanToolError> {
+        let mut frame = self.encode_message(message_name, &values)?;
+        self.protect_e2e(message_name, &mut frame.data)?;
+        self.send_frame(&frame).await
+    }
+
+    /// Handle for sending while `signal_stream` is polled by another task
+    pub fn transmitter(&self) -> CanTransmitter {
+        CanTransmitter { requests: self.tx_sender.clone() }
+    }
+
+    async fn serve_tx(&mut self, request: TxRequest) {
+        let is_fd = self
+            .dbc_info
+            .message(&request.message_name)
+            .is_some_and(|message| message.dlc > 8);
+        let result = if is_fd {
+            self.encode_and_send_fd(&request.message_name, request.values, true).await
+        } else {
+            self.encode_and_send(&request.message_name, request.values).await
+        };
+        let _ = request.reply.send(result);
+    }
+
+    /// Message level information of the loaded DBC
+    pub fn dbc_info(&self) -> &DbcInfo {
+        &self.dbc_info
+    }
+
+    /// Decodes all DBC signals of a frame, returns None for IDs unknown to the DBC
+    fn decode_frame(&self, frame: &CANFrame) -> Option<HashMap<String, f64>> {
+        if frame.is_error() {
+            warn!("CAN error frame, class {:x}", frame.err());
+            return None;
+        }
+        self.decode_payload(dbc_id(frame), frame.data())
+    }
+
+    /// Decodes a payload with the DBC definitions of its message, returns None for IDs
+    /// unknown to the DBC. Payloads shorter than 8 bytes are zero padded.
+    fn decode_signals(&self, dbc_id: u32, data: &[u8]) -> Option<Result<HashMap<String, DecodedSignal>, CanToolError>> {
+        let message = self.dbc_info.message_by_id(dbc_id)?;
+
+        if data.len() < 8 {
+            let mut can_padded_msg = [0u8; 8];
+            can_padded_msg[..data.len()].copy_from_slice(data);
+            return Some(message.decode(&can_padded_msg));
+        }
+        Some(message.decode(data))
+    }
+
+    /// Continuous stream of decoded frames, frames with unknown IDs are skipped
+    ///
+    /// The socket is restarted on receive errors, the stream ends when the socket
+    /// is closed or cannot be restarted. Requests of `transmitter` handles are sent
+    /// while the stream waits for frames.
+    pub fn signal_stream(&mut self) -> BoxStream<'_, SignalUpdate> {
+        stream::unfold(self, |can_utils| async move {
+            loop {
+                let next = tokio::select! {
+                    next = can_utils.can_socket.next() => next,
+                    Some(request) = can_utils.tx_requests.recv() => {
+                        can_utils.serve_tx(request).await;
+                        continue;
+                    }
+                };
+                can_utils.reload_if_changed();
+                if let Some(Ok(frame)) = &next {
+                    can_utils.observe(frame);
+                }
+
+                match next {
+                    Some(Ok(frame)) if frame.is_extended() && TpReassembler::is_tp_frame(frame.id()) => {
+                        if let Some(message) = can_utils.handle_tp_frame(&frame).await {
+                            if let Some(signals) = can_utils.decode_j1939(&message) {
+                                let mut update = SignalUpdate {
+                                    timestamp: Utc::now(),
+                                    frame_id: message.can_id() | CAN_EFF_FLAG,
+                                    signals,
+                                    e2e: None,
+                                };
+                                can_utils.process_update(update.timestamp, &mut update.signals);
+                                return Some((update, can_utils));
+                            }
+                        }
+                    }
+                    Some(Ok(frame)) => {
+                        let (e2e, accepted) = can_utils.check_e2e(dbc_id(&frame), frame.data());
+                        if !accepted {
+                            continue;
+                        }
+
+                        if let Some(signals) = can_utils.decode_frame(&frame) {
+                            let mut update = SignalUpdate {
+                                timestamp: Utc::now(),
+                                frame_id: dbc_id(&frame),
+                                signals,
+                                e2e,
+                            };
+                            can_utils.process_update(update.timestamp, &mut update.signals);
+                            return Some((update, can_utils));
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("Failed to receive CAN frame: {}. Attempting socket restart...", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        if let Err(e) = can_utils.restart_socket().await {
+                            error!("Signal stream stopped: {}", e);
+                            return None;
+                        }
+                    }
+                    None => {
+                        error!("No more frames available from the CAN socket.");
+                        return None;
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Opens a CAN FD socket on the interface, required for FD receive and transmit
+    pub fn enable_fd(&mut self) -> Result<(), CanToolError> {
+        let socket = CanFdSocket::open(&self.canport).map_err(|e| {
+            error!("Failed to open CAN FD socket on {}: {}", self.canport, e);
+            e
+        })?;
+
+        if let Err(e) = socket.set_filter(socket_filters(&self.filter_masks)) {
+            error!("Failed to set CAN FD filters: {}", e);
+            return Err(CanToolError::Socket(e));
+        }
+
+        self.fd_socket = Some(socket);
+        Ok(())
+    }
+
+    /// Physical values of all DBC signals of a payload, returns None for IDs unknown to the DBC
+    /// and payloads that cannot be decoded
+    fn decode_payload(&self, dbc_id: u32, data: &[u8]) -> Option<HashMap<String, f64>> {
+        match self.decode_signals(dbc_id, data)? {
+            Ok(signals) => Some(physical_values(signals)),
+            Err(e) => {
+                error!("Failed to parse message {:x}: {}", dbc_id, e);
+                None
+            }
+        }
+    }
+
+    /// Decodes a frame captured outside of this instance, e.g. replayed from a log
+    pub fn decode_raw_frame(&self, frame: &RawFrame) -> Option<HashMap<String, f64>> {
+        self.decode_payload(frame.dbc_id(), &frame.data)
+    }
+
+    /// Decodes a frame into raw integers and physical values, e.g. for 64 bit counters
+    /// that lose precision as floating point values
+    pub fn decode_with_raw(&self, frame: &RawFrame) -> Option<HashMap<String, DecodedSignal>> {
+        match self.decode_signals(frame.dbc_id(), &frame.data)? {
+            Ok(signals) => Some(signals),
+            Err(e) => {
+                error!("Failed to parse message {:x}: {}", frame.id, e);
+                None
+            }
+        }
+    }
+
+    /// Asynchronously fetches signals from classic or FD frames of the CAN FD socket,
+    /// skipping unknown IDs and frames failing their end-to-end check like `get_signals`
+    pub async fn get_fd_signals(
+        &mut self,
+    ) -> Result<HashMap<String, f64>, CanToolError> {
+        loop {
+            let socket = match self.fd_socket.as_ref() {
+                Some(socket) => socket,
+                None => return Err(CanToolError::FdDisabled),
+            };
+
+            let frame_result = tokio::time::timeout(Duration::from_secs(CAN_RECV_TIMEOUT_S), socket.read_frame()).await;
+            self.reload_if_changed();
+            if let Ok(Ok(frame)) = &frame_result {
+                self.observe_raw(frame);
+            }
+
+            match frame_result {
+                Ok(Ok(frame)) => {
+                    if !self.check_e2e(frame.dbc_id(), &frame.data).1 {
+                        continue;
+                    }
+
+                    match self.decode_raw_frame(&frame) {
+                        Some(mut result) => {
+                            self.process_update(Utc::now(), &mut result);
+                            return Ok(result);
+                        }
+                        None => {
+                            // Unknown IDs are not fatal, keep waiting for a known frame
+                            warn!("{}", CanToolError::UnknownId(frame.dbc_id()));
+                            continue;
+                        }
+                    }
+                }
+                Ok(Err(e)) => {
+                    error!("Failed to receive CAN FD frame: {}. Attempting socket restart...", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    self.restart_socket().await?;
+                    return Err(CanToolError::Socket(e));
+                }
+                Err(_) => {
+                    warn!("CAN Underun!!!");
+                    return Err(CanToolError::Timeout);
+                }
+            }
+        }
+    }
+
+    /// Encodes signal values and transmits them as a CAN FD frame, optionally with bit rate switch
+    pub async fn encode_and_send_fd(
+        &mut self,
+        message_name: &str,
+        values: HashMap<String, f64>,
+        brs: bool,
+    ) -> Result<(), CanToolError> {
+        let mut frame = self.encode_message(message_name, &values)?;
+        self.protect_e2e(message_name, &mut frame.data)?;
+
+        match self.fd_socket.as_ref() {
+            Some(socket) => {
+                let frame = RawFrame { extended: frame.extended, ..RawFrame::new_fd(frame.id, &frame.data, brs) };
+                socket.write_frame(&frame).await?;
+                Ok(())
+            }
+            None => Err(CanToolError::FdDisabled),
+        }
+    }
+
+    /// Transmits a raw frame, FD frames require `enable_fd`
+    pub async fn send_frame(&self, frame: &RawFrame) -> Result<(), CanToolError> {
+        if !frame.extended && frame.id > 0x7FF {
+            return Err(CanToolError::EncodeFailed(format!("Standard frame with ID {:x} above 0x7FF", frame.id)));
+        }
+
+        if frame.fd {
+            match self.fd_socket.as_ref() {
+                Some(socket) => socket.write_frame(frame).await?,
+                None => return Err(CanToolError::FdDisabled),
+            }
+        } else {
+            self.can_socket.write_frame(classic_frame(frame)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Receives the next raw frame from the classic socket
+    pub async fn recv_frame(&mut self, timeout: Duration) -> Result<RawFrame, CanToolError> {
+        let frame_result = tokio::time::timeout(timeout, self.can_socket.next()).await;
+        if let Ok(Some(Ok(frame))) = &frame_result {
+            self.observe(frame);
+        }
+
+        match frame_result {
+            Ok(Some(Ok(frame))) => Ok(RawFrame {
+                id: frame.id(),
+                extended: frame.is_extended(),
+                data: frame.data().to_vec(),
+                fd: false,
+                brs: false,
+            }),
+            Ok(Some(Err(e))) => {
+                error!("Failed to receive CAN frame: {}. Attempting socket restart...", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                self.restart_socket().await?;
+                Err(CanToolError::Socket(e))
+            }
+            Ok(None) => Err(CanToolError::Closed),
+            Err(_) => Err(CanToolError::Timeout),
+        }
+    }
+
+    /// Sets our J1939 source address, RTS/CTS transfers addressed to it are accepted
+    pub fn set_j1939_address(&mut self, address: Option<u8>) {
+        self.tp_reassembler.set_address(address);
+    }
+
+    /// Feeds a TP.CM/TP.DT frame into the reassembler, returns the message once complete
+    async fn handle_tp_frame(&mut self, frame: &CANFrame) -> Option<J1939Message> {
+        let events = self.tp_reassembler.handle_frame(frame.id(), frame.data(), tokio::time::Instant::now());
+
+        let mut complete = None;
+        for event in events {
+            match event {
+                TpEvent::Send(response) => {
+                    if let Err(e) = self.send_frame(&response).await {
+                        error!("Failed to send J1939 TP response: {}", e);
+                    }
+                }
+                TpEvent::Complete(message) => complete = Some(message),
+                TpEvent::Aborted { pgn, source } => {
+                    warn!("J1939 transfer of PGN {:x} from {:02x} aborted", pgn, source);
+                }
+            }
+        }
+
+        complete
+    }
+
+    /// Continuous stream of J1939 messages, single frame and reassembled multi-packet ones
+    pub fn j1939_stream(&mut self) -> BoxStream<'_, J1939Message> {
+        stream::unfold(self, |can_utils| async move {
+            loop {
+                let next = can_utils.can_socket.next().await;
+                can_utils.reload_if_changed();
+                if let Some(Ok(frame)) = &next {
+                    can_utils.observe(frame);
+                }
+
+                match next {
+                    Some(Ok(frame)) if frame.is_extended() && TpReassembler::is_tp_frame(frame.id()) => {
+                        if let Some(message) = can_utils.handle_tp_frame(&frame).await {
+                            return Some((message, can_utils));
+                        }
+                    }
+                    Some(Ok(frame)) if frame.is_extended() => {
+                        let id = J1939Id::from_can_id(frame.id());
+                        let message = J1939Message {
+                            pgn: id.pgn,
+                            source: id.source,
+                            destination: id.destination,
+                            priority: id.priority,
+                            data: frame.data().to_vec(),
+                        };
+                        return Some((message, can_utils));
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        error!("Failed to receive CAN frame: {}. Attempting socket restart...", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        if let Err(e) = can_utils.restart_socket().await {
+                            error!("J1939 stream stopped: {}", e);
+                            return None;
+                        }
+                    }
+                    None => {
+                        error!("No more frames available from the CAN socket.");
+                        return None;
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Requests all nodes (or `destination`) to clear their active DTCs (DM11)
+    pub async fn send_dm11_clear(&mut self, source: u8, destination: u8) -> Result<(), CanToolError> {
+        self.send_j1939(j1939_dm::dm11_clear_request(source, destination)).await
+    }
+
+    /// Decodes a J1939 message by PGN, the source address of the DBC entry is ignored
+    pub fn decode_j1939(&self, message: &J1939Message) -> Option<HashMap<String, f64>> {
+        let dbc_id = message.can_id() | CAN_EFF_FLAG;
+        let dbc_id = if self.id_and_signal.contains_key(&dbc_id) {
+            dbc_id
+        } else {
+            *self
+                .id_and_signal
+                .keys()
+                .find(|id| J1939Id::from_can_id(**id).pgn == message.pgn)?
+        };
+
+        self.decode_payload(dbc_id, &message.data)
+    }
+
+    /// Sends a J1939 message, using BAM or RTS/CTS when it exceeds 8 bytes
+    pub async fn send_j1939(&mut self, message: J1939Message) -> Result<(), CanToolError> {
+        if message.data.len() <= 8 {
+            return self.send_frame(&RawFrame::new(message.can_id(), &message.data)).await;
+        }
+
+        if message.destination == j1939_tp::GLOBAL_ADDRESS {
+            for frame in j1939_tp::segment_bam(&message)? {
+                self.send_frame(&frame).await?;
+                tokio::time::sleep(j1939_tp::TP_BAM_GAP).await;
+            }
+            return Ok(());
+        }
+
+        let mut sender = TpSender::new(message)?;
+        self.send_frame(&sender.request_to_send()).await?;
+
+        loop {
+            let frame = match tokio::time::timeout(j1939_tp::TP_T3, self.can_socket.next()).await {
+                Ok(Some(Ok(frame))) => frame,
+                Ok(Some(Err(e))) => return Err(CanToolError::Socket(e)),
+                Ok(None) => return Err(CanToolError::Closed),
+                Err(_) => return Err(CanToolError::Timeout),
+            };
+
+            match sender.handle_frame(frame.id(), frame.data()) {
+                TpSendState::Waiting => {}
+                TpSendState::SendData(frames) => {
+                    for frame in frames {
+                        self.send_frame(&frame).await?;
+                    }
+                }
+                TpSendState::Done => return Ok(()),
+                TpSendState::Aborted(reason) => {
+                    error!("J1939 transfer aborted by peer, reason {}", reason);
+                    return Err(CanToolError::Protocol(format!("J1939 transfer aborted, reason {}", reason)));
                 }
             }
         }
@@ -0,0 +1,83 @@
+use std::fmt::Debug;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, Stream};
+use log::error;
+use tokio_socketcan::{CANFilter, CANFrame, CANSocket};
+use crate::can_error::CanToolError;
+use crate::can_filter::joins_filters;
+
+/// Classic CAN socket used by `CanUtils`, frames are received through the `Stream`
+pub trait CanTransport: Stream<Item = io::Result<CANFrame>> + Debug + Send + Sync + Unpin {
+    fn write_frame(&self, frame: CANFrame) -> BoxFuture<'_, io::Result<()>>;
+
+    /// Kernel style (id, mask) filters, an empty list receives no frames. A set of
+    /// inverted filters only is joined, see `joins_filters`.
+    fn set_filter(&self, filters: &[(u32, u32)]) -> io::Result<()>;
+
+    /// `CAN_ERR_*` classes delivered as error frames
+    fn set_error_filter(&self, mask: u32) -> io::Result<()>;
+}
+
+/// Opens transports, called again when a socket has to be restarted
+pub trait CanBackend: Debug + Send + Sync {
+    fn open(&self, ifname: &str) -> Result<Box<dyn CanTransport>, CanToolError>;
+}
+
+/// Kernel SocketCAN interfaces such as `can0` or `vcan0`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketCanBackend;
+
+impl CanBackend for SocketCanBackend {
+    fn open(&self, ifname: &str) -> Result<Box<dyn CanTransport>, CanToolError> {
+        let socket = CANSocket::open(ifname).map_err(|e| {
+            error!("Failed to open CAN socket on {}: {}", ifname, e);
+            CanToolError::Socket(io::Error::new(io::ErrorKind::NotConnected, e.to_string()))
+        })?;
+        Ok(Box::new(socket))
+    }
+}
+
+impl CanTransport for CANSocket {
+    fn write_frame(&self, frame: CANFrame) -> BoxFuture<'_, io::Result<()>> {
+        match CANSocket::write_frame(self, frame) {
+            Ok(future) => future.boxed(),
+            Err(e) => futures_util::future::ready(Err(e)).boxed(),
+        }
+    }
+
+    fn set_filter(&self, filters: &[(u32, u32)]) -> io::Result<()> {
+        let can_filters = filters
+            .iter()
+            .map(|(id, mask)| CANFilter::new(*id, *mask))
+            .collect::<Result<Vec<CANFilter>, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        CANSocket::set_filter(self, &can_filters)?;
+        set_join_filters(self.as_raw_fd(), joins_filters(filters))
+    }
+
+    fn set_error_filter(&self, mask: u32) -> io::Result<()> {
+        CANSocket::set_error_filter(self, mask)
+    }
+}
+
+/// Sets `CAN_RAW_JOIN_FILTERS`, a frame then has to match every filter instead of any
+pub(crate) fn set_join_filters(fd: RawFd, join: bool) -> io::Result<()> {
+    let join = join as libc::c_int;
+    // SAFETY: the option value points to a c_int that outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_CAN_RAW,
+            libc::CAN_RAW_JOIN_FILTERS,
+            &join as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use log::info;
+use tokio::sync::broadcast;
+use crate::can_error::CanToolError;
+
+const TRIGGER_CHANNEL_CAPACITY: usize = 256;
+
+/// When a trigger fires. The first value of a signal only sets the baseline,
+/// thresholds fire on crossings and not for a value that starts beyond them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerCondition {
+    /// The value moved more than `deadband` from the value of the last event, 0 fires on any change
+    OnChange { deadband: f64 },
+    /// The value rose above `threshold`, re-armed once it fell below `threshold - hysteresis`
+    Rising { threshold: f64, hysteresis: f64 },
+    /// The value fell below `threshold`, re-armed once it rose above `threshold + hysteresis`
+    Falling { threshold: f64, hysteresis: f64 },
+    /// The value changes faster than `limit` per second in either direction, re-armed
+    /// once the rate is within `limit` again
+    RateOfChange { limit: f64 },
+}
+
+/// A named condition on one decoded or virtual signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    pub signal: String,
+    pub condition: TriggerCondition,
+}
+
+impl Trigger {
+    pub fn new(name: &str, signal: &str, condition: TriggerCondition) -> Self {
+        Trigger { name: name.to_string(), signal: signal.to_string(), condition }
+    }
+
+    pub fn on_change(name: &str, signal: &str, deadband: f64) -> Self {
+        Self::new(name, signal, TriggerCondition::OnChange { deadband })
+    }
+
+    /// e.g. `Trigger::falling("SocLow", "BMS_SOC", 10.0, 1.0)`
+    pub fn falling(name: &str, signal: &str, threshold: f64, hysteresis: f64) -> Self {
+        Self::new(name, signal, TriggerCondition::Falling { threshold, hysteresis })
+    }
+
+    pub fn rising(name: &str, signal: &str, threshold: f64, hysteresis: f64) -> Self {
+        Self::new(name, signal, TriggerCondition::Rising { threshold, hysteresis })
+    }
+
+    pub fn rate_of_change(name: &str, signal: &str, limit: f64) -> Self {
+        Self::new(name, signal, TriggerCondition::RateOfChange { limit })
+    }
+
+    fn validate(&self) -> Result<(), CanToolError> {
+        let valid = match self.condition {
+            TriggerCondition::OnChange { deadband } => deadband >= 0.0,
+            TriggerCondition::Rising { threshold, hysteresis }
+            | TriggerCondition::Falling { threshold, hysteresis } => threshold.is_finite() && hysteresis >= 0.0,
+            TriggerCondition::RateOfChange { limit } => limit >= 0.0,
+        };
+
+        if !valid {
+            return Err(CanToolError::InvalidConfig(format!("trigger {}: {:?}", self.name, self.condition)));
+        }
+        Ok(())
+    }
+}
+
+/// What a trigger detected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerKind {
+    Changed,
+    Rose,
+    Fell,
+    /// Rate of change in units per second
+    RateExceeded { rate: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerEvent {
+    pub trigger: String,
+    pub signal: String,
+    pub kind: TriggerKind,
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+    /// Previous value of the signal
+    pub previous: f64,
+}
+
+#[derive(Debug, Clone)]
+struct TriggerState {
+    trigger: Trigger,
+    last: Option<(DateTime<Utc>, f64)>,
+    /// Value of the last on-change event
+    reference: f64,
+    /// Threshold triggers fire only while armed
+    armed: bool,
+}
+
+impl TriggerState {
+    fn evaluate(&mut self, timestamp: DateTime<Utc>, value: f64) -> Option<TriggerKind> {
+        let Some((last_time, previous)) = self.last.replace((timestamp, value)) else {
+            self.reference = value;
+            self.armed = match self.trigger.condition {
+                TriggerCondition::Rising { threshold, .. } => value <= threshold,
+                TriggerCondition::Falling { threshold, .. } => value >= threshold,
+                _ => true,
+            };
+            return None;
+        };
+
+        match self.trigger.condition {
+            TriggerCondition::OnChange { deadband } => {
+                let changed = if deadband == 0.0 {
+                    value != self.reference
+                } else {
+                    (value - self.reference).abs() > deadband
+                };
+                if changed {
+                    self.reference = value;
+                }
+                changed.then_some(TriggerKind::Changed)
+            }
+            TriggerCondition::Rising { threshold, hysteresis } => {
+                if self.armed && value > threshold {
+                    self.armed = false;
+                    return Some(TriggerKind::Rose);
+                }
+                if value < threshold - hysteresis {
+                    self.armed = true;
+                }
+                None
+            }
+            TriggerCondition::Falling { threshold, hysteresis } => {
+                if self.armed && value < threshold {
+                    self.armed = false;
+                    return Some(TriggerKind::Fell);
+                }
+                if value > threshold + hysteresis {
+                    self.armed = true;
+                }
+                None
+            }
+            TriggerCondition::RateOfChange { limit } => {
+                let dt = (timestamp - last_time).num_microseconds().unwrap_or(0) as f64 / 1e6;
+                if dt <= 0.0 {
+                    return None;
+                }
+                let rate = (value - previous) / dt;
+                if rate.abs() <= limit {
+                    self.armed = true;
+                    return None;
+                }
+                if self.armed {
+                    self.armed = false;
+                    return Some(TriggerKind::RateExceeded { rate });
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Triggers evaluated on every decoded update, events are broadcast to all subscribers.
+/// Slow subscribers miss events (`RecvError::Lagged`) instead of delaying decoding.
+#[derive(Debug)]
+pub struct TriggerSet {
+    triggers: Vec<TriggerState>,
+    events: broadcast::Sender<TriggerEvent>,
+}
+
+impl Default for TriggerSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TriggerSet {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(TRIGGER_CHANNEL_CAPACITY);
+        TriggerSet { triggers: Vec::new(), events }
+    }
+
+    /// Adds a trigger, a trigger with the same name is replaced
+    pub fn add(&mut self, trigger: Trigger) -> Result<(), CanToolError> {
+        trigger.validate()?;
+        self.remove(&trigger.name);
+
+        info!("Added trigger {} on {}: {:?}", trigger.name, trigger.signal, trigger.condition);
+        self.triggers.push(TriggerState { trigger, last: None, reference: 0.0, armed: false });
+        Ok(())
+    }
+
+    /// Removes a trigger, returns whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.triggers.len();
+        self.triggers.retain(|state| state.trigger.name != name);
+        self.triggers.len() != count
+    }
+
+    pub fn triggers(&self) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter().map(|state| &state.trigger)
+    }
+
+    /// Receiver of the events of all triggers fired from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<TriggerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Checks the triggers of the signals in an update, returns the number of events
+    pub fn evaluate(&mut self, timestamp: DateTime<Utc>, signals: &HashMap<String, f64>) -> usize {
+        let mut fired = 0;
+
+        for state in self.triggers.iter_mut() {
+            let Some(value) = signals.get(&state.trigger.signal).copied() else {
+                continue;
+            };
+            let previous = state.last.map(|(_, previous)| previous).unwrap_or(value);
+
+            if let Some(kind) = state.evaluate(timestamp, value) {
+                fired += 1;
+                let event = TriggerEvent {
+                    trigger: state.trigger.name.clone(),
+                    signal: state.trigger.signal.clone(),
+                    kind,
+                    timestamp,
+                    value,
+                    previous,
+                };
+                info!("Trigger {} fired: {} = {}", event.trigger, event.signal, event.value);
+                // sending fails only without subscribers
+                let _ = self.events.send(event);
+            }
+        }
+
+        fired
+    }
+}
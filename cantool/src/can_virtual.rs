@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use log::info;
+use tokio::time::Duration;
+use crate::can_error::CanToolError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Duration(Duration),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Open,
+    Close,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Token(Token),
+    Ident(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Lexeme>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            ' ' | '\t' => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // exponent, e.g. 1e-3
+                if i + 1 < chars.len() && matches!(chars[i], 'e' | 'E') {
+                    let sign = usize::from(matches!(chars[i + 1], '+' | '-'));
+                    if chars.get(i + 1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+                        i += 1 + sign;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let number: String = chars[start..i].iter().collect();
+                let value = number.parse::<f64>().map_err(|_| format!("invalid number {}", number))?;
+
+                // a unit suffix makes it a duration, e.g. 10s
+                let unit_start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let unit: String = chars[unit_start..i].iter().collect();
+                let seconds = match unit.as_str() {
+                    "" => None,
+                    "ms" => Some(value / 1000.0),
+                    "s" => Some(value),
+                    "m" | "min" => Some(value * 60.0),
+                    "h" => Some(value * 3600.0),
+                    _ => return Err(format!("unknown unit {}", unit)),
+                };
+                let token = match seconds {
+                    None => Token::Number(value),
+                    Some(seconds) => Token::Duration(
+                        Duration::try_from_secs_f64(seconds).map_err(|_| format!("invalid duration {}{}", number, unit))?,
+                    ),
+                };
+                lexemes.push(Lexeme::Token(token));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    i += 1;
+                }
+                lexemes.push(Lexeme::Ident(chars[start..i].iter().collect()));
+                continue;
+            }
+            c => return Err(format!("unexpected character '{}'", c)),
+        };
+
+        lexemes.push(Lexeme::Token(token));
+        i += 1;
+    }
+
+    Ok(lexemes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Signal(String),
+    Neg(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    /// Trapezoidal integral over time in seconds, the state lives in slot `usize`
+    Integrate(Box<Expr>, usize),
+    Window(Window, Box<Expr>, Duration, usize),
+}
+
+/// Recursive descent parser, stateful functions get consecutive state slots
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+    slots: usize,
+}
+
+impl Parser {
+    fn parse(text: &str) -> Result<(Expr, usize), String> {
+        let mut parser = Parser { lexemes: tokenize(text)?, pos: 0, slots: 0 };
+        let expr = parser.expression()?;
+        if parser.pos < parser.lexemes.len() {
+            return Err(format!("unexpected {:?}", parser.lexemes[parser.pos]));
+        }
+        Ok((expr, parser.slots))
+    }
+
+    fn peek(&self) -> Option<&Lexeme> {
+        self.lexemes.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Lexeme> {
+        let lexeme = self.lexemes.get(self.pos).cloned();
+        self.pos += 1;
+        lexeme
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(Lexeme::Token(next)) if next == token => Ok(()),
+            other => Err(format!("expected {:?}, found {:?}", token, other)),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Lexeme::Token(Token::Plus)) => Operator::Add,
+                Some(Lexeme::Token(Token::Minus)) => Operator::Sub,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Lexeme::Token(Token::Star)) => Operator::Mul,
+                Some(Lexeme::Token(Token::Slash)) => Operator::Div,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.factor()?));
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Lexeme::Token(Token::Number(value))) => Ok(Expr::Number(value)),
+            Some(Lexeme::Token(Token::Minus)) => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Some(Lexeme::Token(Token::Open)) => {
+                let expr = self.expression()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Lexeme::Ident(name)) if self.peek() == Some(&Lexeme::Token(Token::Open)) => {
+                self.pos += 1;
+                self.call(&name)
+            }
+            Some(Lexeme::Ident(name)) => Ok(Expr::Signal(name)),
+            other => Err(format!("unexpected {:?}", other)),
+        }
+    }
+
+    /// Arguments of `name(`, the opening parenthesis is consumed
+    fn call(&mut self, name: &str) -> Result<Expr, String> {
+        // min, max and avg over a time window when the second argument is a duration
+        let window = match name {
+            "min" => Some(Window::Min),
+            "max" => Some(Window::Max),
+            "avg" => Some(Window::Avg),
+            _ => None,
+        };
+
+        if let Some(window) = window {
+            let expr = self.expression()?;
+            self.expect(Token::Comma)?;
+
+            if let Some(Lexeme::Token(Token::Duration(duration))) = self.peek().cloned() {
+                self.pos += 1;
+                self.expect(Token::Close)?;
+                if duration.is_zero() {
+                    return Err(format!("{} window must not be zero", name));
+                }
+                self.slots += 1;
+                return Ok(Expr::Window(window, Box::new(expr), duration, self.slots - 1));
+            }
+
+            let function = match window {
+                Window::Min => Function::Min,
+                Window::Max => Function::Max,
+                Window::Avg => return Err("avg expects a window like 10s".to_string()),
+            };
+            let other = self.expression()?;
+            self.expect(Token::Close)?;
+            return Ok(Expr::Call(function, vec![expr, other]));
+        }
+
+        let mut args = vec![self.expression()?];
+        while self.peek() == Some(&Lexeme::Token(Token::Comma)) {
+            self.pos += 1;
+            args.push(self.expression()?);
+        }
+        self.expect(Token::Close)?;
+
+        if args.len() != 1 {
+            return Err(format!("{} expects one argument", name));
+        }
+
+        match name {
+            "integrate" => {
+                self.slots += 1;
+                Ok(Expr::Integrate(Box::new(args.remove(0)), self.slots - 1))
+            }
+            "abs" => Ok(Expr::Call(Function::Abs, args)),
+            "sqrt" => Ok(Expr::Call(Function::Sqrt, args)),
+            _ => Err(format!("unknown function {}", name)),
+        }
+    }
+}
+
+impl Expr {
+    fn inputs(&self, names: &mut HashSet<String>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Signal(name) => {
+                names.insert(name.clone());
+            }
+            Expr::Neg(expr) | Expr::Integrate(expr, _) | Expr::Window(_, expr, _, _) => expr.inputs(names),
+            Expr::Binary(_, left, right) => {
+                left.inputs(names);
+                right.inputs(names);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.inputs(names)),
+        }
+    }
+
+    /// None while an input was never received or the result is not a finite number.
+    /// All operands are evaluated, so integrals and windows see every sample.
+    fn eval(&self, values: &HashMap<String, f64>, time: DateTime<Utc>, states: &mut [SlotState]) -> Option<f64> {
+        let value = match self {
+            Expr::Number(value) => *value,
+            Expr::Signal(name) => *values.get(name)?,
+            Expr::Neg(expr) => -expr.eval(values, time, states)?,
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.eval(values, time, states), right.eval(values, time, states));
+                let (left, right) = (left?, right?);
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    Operator::Div => left / right,
+                }
+            }
+            Expr::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(values, time, states))
+                    .collect::<Vec<Option<f64>>>()
+                    .into_iter()
+                    .collect::<Option<Vec<f64>>>()?;
+                match function {
+                    Function::Abs => args[0].abs(),
+                    Function::Sqrt => args[0].sqrt(),
+                    Function::Min => args[0].min(args[1]),
+                    Function::Max => args[0].max(args[1]),
+                }
+            }
+            Expr::Integrate(expr, slot) => {
+                let value = expr.eval(values, time, states)?;
+                states[*slot].integrate(time, value)
+            }
+            Expr::Window(window, expr, duration, slot) => {
+                let value = expr.eval(values, time, states)?;
+                states[*slot].window(*window, *duration, time, value)
+            }
+        };
+
+        value.is_finite().then_some(value)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SlotState {
+    last: Option<(DateTime<Utc>, f64)>,
+    total: f64,
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl SlotState {
+    fn integrate(&mut self, time: DateTime<Utc>, value: f64) -> f64 {
+        if let Some((last_time, last_value)) = self.last {
+            // out of order samples, e.g. of a merged stream, do not count negatively
+            let dt = (time - last_time).num_microseconds().unwrap_or(0).max(0) as f64 / 1e6;
+            self.total += (value + last_value) / 2.0 * dt;
+        }
+        self.last = Some((time, value));
+        self.total
+    }
+
+    fn window(&mut self, window: Window, duration: Duration, time: DateTime<Utc>, value: f64) -> f64 {
+        self.samples.push_back((time, value));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(sample_time, _)| (time - *sample_time).to_std().is_ok_and(|age| age > duration))
+        {
+            self.samples.pop_front();
+        }
+
+        let values = self.samples.iter().map(|(_, value)| *value);
+        match window {
+            Window::Min => values.fold(f64::INFINITY, f64::min),
+            Window::Max => values.fold(f64::NEG_INFINITY, f64::max),
+            Window::Avg => values.sum::<f64>() / self.samples.len() as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct VirtualSignal {
+    name: String,
+    expr: Expr,
+    inputs: HashSet<String>,
+    states: Vec<SlotState>,
+}
+
+/// Signals computed from decoded values, e.g. `PackPower = BMS_Voltage * BMS_Current`
+///
+/// A signal is evaluated whenever one of its inputs is part of an update, using the
+/// latest value of the other inputs. Signals may use the ones defined before them.
+/// Expressions support `+ - * /`, parentheses, `abs`, `sqrt`, `min(a, b)`, `max(a, b)`,
+/// `integrate(x)` over time in seconds and the time windows `min(x, 10s)`,
+/// `max(x, 1m)` and `avg(x, 500ms)`.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualSignals {
+    signals: Vec<VirtualSignal>,
+    latest: HashMap<String, f64>,
+}
+
+impl VirtualSignals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads `<name> = <expression>` lines, `#` starts a comment
+    pub fn from_config_file(path: &Path) -> Result<Self, CanToolError> {
+        let signals = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Self::from_config_str(&text))
+            .map_err(|e| CanToolError::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+
+        info!("Loaded {} virtual signals from {}", signals.signals.len(), path.display());
+        Ok(signals)
+    }
+
+    /// Parses `<name> = <expression>` lines, `#` starts a comment
+    pub fn from_config_str(text: &str) -> Result<Self, String> {
+        let mut signals = VirtualSignals::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, expression) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected <name> = <expression>", number + 1))?;
+            signals.add(name.trim(), expression).map_err(|e| match e {
+                CanToolError::InvalidConfig(reason) => format!("line {}: {}", number + 1, reason),
+                e => format!("line {}: {}", number + 1, e),
+            })?;
+        }
+
+        Ok(signals)
+    }
+
+    /// Adds a signal, its expression may use the signals added before
+    pub fn add(&mut self, name: &str, expression: &str) -> Result<(), CanToolError> {
+        let valid_name = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+        if !valid_name {
+            return Err(CanToolError::InvalidConfig(format!("invalid signal name '{}'", name)));
+        }
+        if self.signals.iter().any(|signal| signal.name == name) {
+            return Err(CanToolError::InvalidConfig(format!("{} is defined twice", name)));
+        }
+
+        if let Some(user) = self.signals.iter().find(|signal| signal.inputs.contains(name)) {
+            return Err(CanToolError::InvalidConfig(format!("{} is used by {} before its definition", name, user.name)));
+        }
+
+        let (expr, slots) = Parser::parse(expression)
+            .map_err(|e| CanToolError::InvalidConfig(format!("{}: {}", name, e)))?;
+
+        let mut inputs = HashSet::new();
+        expr.inputs(&mut inputs);
+        if inputs.contains(name) {
+            return Err(CanToolError::InvalidConfig(format!("{} refers to itself", name)));
+        }
+
+        self.signals.push(VirtualSignal {
+            name: name.to_string(),
+            expr,
+            inputs,
+            states: vec![SlotState::default(); slots],
+        });
+        Ok(())
+    }
+
+    /// Names of the virtual signals in evaluation order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.signals.iter().map(|signal| signal.name.as_str())
+    }
+
+    /// Signals used by the expressions that are not virtual signals themselves
+    pub fn inputs(&self) -> HashSet<String> {
+        let virtual_names: HashSet<&str> = self.names().collect();
+        self.signals
+            .iter()
+            .flat_map(|signal| signal.inputs.iter())
+            .filter(|name| !virtual_names.contains(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Evaluates the signals depending on `signals` and adds their values to it
+    pub fn update(&mut self, timestamp: DateTime<Utc>, signals: &mut HashMap<String, f64>) {
+        let mut updated: HashSet<String> = signals.keys().cloned().collect();
+        self.latest.extend(signals.iter().map(|(name, value)| (name.clone(), *value)));
+
+        for signal in self.signals.iter_mut() {
+            if signal.inputs.is_disjoint(&updated) {
+                continue;
+            }
+            if let Some(value) = signal.expr.eval(&self.latest, timestamp, &mut signal.states) {
+                self.latest.insert(signal.name.clone(), value);
+                signals.insert(signal.name.clone(), value);
+                updated.insert(signal.name.clone());
+            }
+        }
+    }
+
+    /// Restarts the integrals and windows of a signal, e.g. a trip energy counter
+    pub fn reset(&mut self, name: &str) {
+        if let Some(signal) = self.signals.iter_mut().find(|signal| signal.name == name) {
+            signal.states.iter_mut().for_each(|state| *state = SlotState::default());
+            self.latest.remove(name);
+        }
+    }
+}
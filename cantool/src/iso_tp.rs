@@ -0,0 +1,424 @@
+use log::{debug, warn};
+use tokio::time::{Duration, Instant};
+use crate::can_frame::RawFrame;
+use crate::can_error::CanToolError;
+use crate::can_tool::CanUtils;
+
+const PCI_SINGLE_FRAME: u8 = 0x0;
+const PCI_FIRST_FRAME: u8 = 0x1;
+const PCI_CONSECUTIVE_FRAME: u8 = 0x2;
+const PCI_FLOW_CONTROL: u8 = 0x3;
+
+const FC_CONTINUE_TO_SEND: u8 = 0x0;
+const FC_WAIT: u8 = 0x1;
+const FC_OVERFLOW: u8 = 0x2;
+
+/// Maximum number of FC.WAIT frames accepted in a row (N_WFTmax)
+const MAX_WAIT_FRAMES: u32 = 10;
+/// Largest payload with the 12-bit first frame length, longer ones use the escape sequence
+const FF_DL_12BIT_MAX: usize = 4095;
+
+/// Addressing, flow control parameters and timeouts of an ISO-TP channel
+#[derive(Debug, Clone, Copy)]
+pub struct IsoTpConfig {
+    pub tx_id: u32,
+    pub rx_id: u32,
+    /// Block size advertised to the sender, 0 means no further flow control
+    pub block_size: u8,
+    /// Minimum separation time advertised to the sender
+    pub st_min: Duration,
+    /// Fill byte for unused payload bytes, None sends short frames
+    pub padding: Option<u8>,
+    /// Largest payload accepted from the peer
+    pub max_rx_len: usize,
+    pub n_as: Duration,
+    pub n_bs: Duration,
+    pub n_cr: Duration,
+}
+
+impl IsoTpConfig {
+    pub fn new(tx_id: u32, rx_id: u32) -> Self {
+        IsoTpConfig {
+            tx_id,
+            rx_id,
+            block_size: 0,
+            st_min: Duration::ZERO,
+            padding: Some(0xCC),
+            max_rx_len: FF_DL_12BIT_MAX,
+            n_as: Duration::from_millis(1000),
+            n_bs: Duration::from_millis(1000),
+            n_cr: Duration::from_millis(1000),
+        }
+    }
+
+    fn frame(&self, mut data: Vec<u8>) -> RawFrame {
+        if let Some(padding) = self.padding {
+            data.resize(8, padding);
+        }
+        RawFrame::new(self.tx_id, &data)
+    }
+}
+
+/// Encodes a separation time into the STmin byte
+pub fn encode_st_min(st_min: Duration) -> u8 {
+    let micros = st_min.as_micros();
+    match micros {
+        0 => 0,
+        1..=900 => 0xF0 + micros.div_ceil(100) as u8,
+        _ => st_min.as_millis().min(0x7F) as u8,
+    }
+}
+
+/// Decodes the STmin byte, reserved values are treated as the maximum of 127 ms
+pub fn decode_st_min(byte: u8) -> Duration {
+    match byte {
+        0x00..=0x7F => Duration::from_millis(byte as u64),
+        0xF1..=0xF9 => Duration::from_micros((byte - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Flow control instruction received by a sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowStatus {
+    ContinueToSend { block_size: u8, st_min: Duration },
+    Wait,
+    Overflow,
+}
+
+/// Parses a flow control frame
+pub fn parse_flow_control(data: &[u8]) -> Option<FlowStatus> {
+    if data.len() < 3 || data[0] >> 4 != PCI_FLOW_CONTROL {
+        return None;
+    }
+
+    match data[0] & 0x0F {
+        FC_CONTINUE_TO_SEND => Some(FlowStatus::ContinueToSend {
+            block_size: data[1],
+            st_min: decode_st_min(data[2]),
+        }),
+        FC_WAIT => Some(FlowStatus::Wait),
+        FC_OVERFLOW => Some(FlowStatus::Overflow),
+        _ => None,
+    }
+}
+
+/// Sender side state machine of a segmented transfer
+#[derive(Debug)]
+pub struct IsoTpSender {
+    payload: Vec<u8>,
+    offset: usize,
+    sequence: u8,
+    block_remaining: Option<u8>,
+    st_min: Duration,
+    waits: u32,
+}
+
+/// Next step of a sender
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxStep {
+    /// Send the frame after the separation time, then ask again
+    Send(RawFrame, Duration),
+    /// Wait for a flow control frame (N_Bs)
+    AwaitFlowControl,
+    Done,
+}
+
+impl IsoTpSender {
+    pub fn new(payload: Vec<u8>) -> Result<Self, CanToolError> {
+        if payload.is_empty() || payload.len() > u32::MAX as usize {
+            return Err(CanToolError::Protocol("Invalid ISO-TP payload length.".to_string()));
+        }
+
+        Ok(IsoTpSender {
+            payload,
+            offset: 0,
+            sequence: 1,
+            block_remaining: None,
+            st_min: Duration::ZERO,
+            waits: 0,
+        })
+    }
+
+    /// Single frame for short payloads, first frame otherwise
+    pub fn first_frame(&mut self, config: &IsoTpConfig) -> RawFrame {
+        let len = self.payload.len();
+
+        if len <= 7 {
+            self.offset = len;
+            let mut data = vec![(PCI_SINGLE_FRAME << 4) | len as u8];
+            data.extend_from_slice(&self.payload);
+            return config.frame(data);
+        }
+
+        let mut data = if len <= FF_DL_12BIT_MAX {
+            vec![(PCI_FIRST_FRAME << 4) | (len >> 8) as u8, len as u8]
+        } else {
+            let mut data = vec![PCI_FIRST_FRAME << 4, 0];
+            data.extend_from_slice(&(len as u32).to_be_bytes());
+            data
+        };
+
+        let chunk = 8 - data.len();
+        data.extend_from_slice(&self.payload[..chunk]);
+        self.offset = chunk;
+        config.frame(data)
+    }
+
+    /// Applies a received flow control frame
+    pub fn on_flow_control(&mut self, data: &[u8]) -> Result<(), CanToolError> {
+        match parse_flow_control(data) {
+            Some(FlowStatus::ContinueToSend { block_size, st_min }) => {
+                self.block_remaining = Some(block_size);
+                self.st_min = st_min;
+                self.waits = 0;
+                Ok(())
+            }
+            Some(FlowStatus::Wait) => {
+                self.waits += 1;
+                if self.waits > MAX_WAIT_FRAMES {
+                    return Err(CanToolError::Protocol("ISO-TP receiver sent too many wait frames.".to_string()));
+                }
+                Ok(())
+            }
+            Some(FlowStatus::Overflow) => Err(CanToolError::Protocol("ISO-TP receiver buffer overflow.".to_string())),
+            None => Err(CanToolError::Protocol("Invalid ISO-TP flow control frame.".to_string())),
+        }
+    }
+
+    pub fn next_step(&mut self, config: &IsoTpConfig) -> TxStep {
+        if self.offset >= self.payload.len() {
+            return TxStep::Done;
+        }
+
+        match self.block_remaining {
+            None => TxStep::AwaitFlowControl,
+            Some(remaining) => {
+                let end = (self.offset + 7).min(self.payload.len());
+                let mut data = vec![(PCI_CONSECUTIVE_FRAME << 4) | self.sequence];
+                data.extend_from_slice(&self.payload[self.offset..end]);
+
+                self.offset = end;
+                self.sequence = (self.sequence + 1) & 0x0F;
+                // block size 0 means the receiver wants no further flow control
+                self.block_remaining = match remaining {
+                    0 => Some(0),
+                    1 => None,
+                    n => Some(n - 1),
+                };
+
+                TxStep::Send(config.frame(data), self.st_min)
+            }
+        }
+    }
+}
+
+/// Result of feeding a frame into a receiver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RxEvent {
+    /// The frame is not part of a transfer
+    Ignored,
+    /// Transfer in progress, optionally send this flow control frame
+    InProgress(Option<RawFrame>),
+    Complete(Vec<u8>),
+}
+
+/// Receiver side state machine of a segmented transfer
+#[derive(Debug, Default)]
+pub struct IsoTpReceiver {
+    buffer: Vec<u8>,
+    expected_len: usize,
+    sequence: u8,
+    block_count: u8,
+    active: bool,
+}
+
+impl IsoTpReceiver {
+    pub fn new() -> Self {
+        IsoTpReceiver::default()
+    }
+
+    /// Whether a segmented transfer is in progress (N_Cr applies)
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn flow_control(config: &IsoTpConfig, status: u8) -> RawFrame {
+        config.frame(vec![(PCI_FLOW_CONTROL << 4) | status, config.block_size, encode_st_min(config.st_min)])
+    }
+
+    pub fn on_frame(&mut self, config: &IsoTpConfig, data: &[u8]) -> Result<RxEvent, CanToolError> {
+        if data.is_empty() {
+            return Ok(RxEvent::Ignored);
+        }
+
+        match data[0] >> 4 {
+            PCI_SINGLE_FRAME => {
+                let len = (data[0] & 0x0F) as usize;
+                if len == 0 || len + 1 > data.len() {
+                    return Err(CanToolError::Protocol("Invalid ISO-TP single frame.".to_string()));
+                }
+                if self.active {
+                    warn!("ISO-TP transfer interrupted by a single frame");
+                    self.active = false;
+                }
+                Ok(RxEvent::Complete(data[1..1 + len].to_vec()))
+            }
+            PCI_FIRST_FRAME => {
+                if data.len() < 8 {
+                    return Err(CanToolError::Protocol("Invalid ISO-TP first frame.".to_string()));
+                }
+
+                let mut len = (((data[0] & 0x0F) as usize) << 8) | data[1] as usize;
+                let mut header = 2;
+                if len == 0 {
+                    len = u32::from_be_bytes([data[2], data[3], data[4], data[5]]) as usize;
+                    header = 6;
+                }
+
+                // shorter payloads fit a single frame, the escape sequence starts above 12 bits
+                let min_len = if header == 2 { 8 } else { FF_DL_12BIT_MAX + 1 };
+                if len < min_len {
+                    self.active = false;
+                    return Err(CanToolError::Protocol("Invalid ISO-TP first frame length.".to_string()));
+                }
+
+                if len > config.max_rx_len {
+                    self.active = false;
+                    return Ok(RxEvent::InProgress(Some(Self::flow_control(config, FC_OVERFLOW))));
+                }
+
+                self.buffer = data[header..].to_vec();
+                self.expected_len = len;
+                self.sequence = 1;
+                self.block_count = 0;
+                self.active = true;
+                Ok(RxEvent::InProgress(Some(Self::flow_control(config, FC_CONTINUE_TO_SEND))))
+            }
+            PCI_CONSECUTIVE_FRAME if self.active => {
+                if data[0] & 0x0F != self.sequence {
+                    self.active = false;
+                    return Err(CanToolError::Protocol("ISO-TP consecutive frame out of sequence.".to_string()));
+                }
+
+                let remaining = self.expected_len.saturating_sub(self.buffer.len());
+                let chunk = remaining.min(data.len() - 1);
+                self.buffer.extend_from_slice(&data[1..1 + chunk]);
+                self.sequence = (self.sequence + 1) & 0x0F;
+
+                if self.buffer.len() >= self.expected_len {
+                    self.active = false;
+                    return Ok(RxEvent::Complete(std::mem::take(&mut self.buffer)));
+                }
+
+                self.block_count = self.block_count.wrapping_add(1);
+                if config.block_size != 0 && self.block_count == config.block_size {
+                    self.block_count = 0;
+                    return Ok(RxEvent::InProgress(Some(Self::flow_control(config, FC_CONTINUE_TO_SEND))));
+                }
+                Ok(RxEvent::InProgress(None))
+            }
+            _ => Ok(RxEvent::Ignored),
+        }
+    }
+}
+
+/// ISO-TP channel over the socket of a `CanUtils` instance
+///
+/// Frames that do not carry the receive ID are dropped while the channel waits,
+/// so use a CAN filter on the response ID for long diagnostic sessions.
+pub struct IsoTpChannel<'a> {
+    can: &'a mut CanUtils,
+    config: IsoTpConfig,
+    receiver: IsoTpReceiver,
+}
+
+impl<'a> IsoTpChannel<'a> {
+    pub fn new(can: &'a mut CanUtils, config: IsoTpConfig) -> Self {
+        IsoTpChannel { can, config, receiver: IsoTpReceiver::new() }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    async fn transmit(&self, frame: &RawFrame) -> Result<(), CanToolError> {
+        match tokio::time::timeout(self.config.n_as, self.can.send_frame(frame)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("ISO-TP N_As timeout");
+                Err(CanToolError::Timeout)
+            }
+        }
+    }
+
+    /// Waits for the next frame with the receive ID
+    async fn receive(&mut self, timeout: Duration) -> Result<Vec<u8>, CanToolError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let frame = self.can.recv_frame(remaining).await?;
+            if frame.id == self.config.rx_id {
+                return Ok(frame.data);
+            }
+            debug!("ISO-TP dropping frame {:x}", frame.id);
+        }
+    }
+
+    /// Sends a payload, segmenting it when it exceeds a single frame
+    pub async fn send(&mut self, payload: &[u8]) -> Result<(), CanToolError> {
+        let mut sender = IsoTpSender::new(payload.to_vec())?;
+        let first = sender.first_frame(&self.config);
+        self.transmit(&first).await?;
+
+        loop {
+            match sender.next_step(&self.config) {
+                TxStep::Done => return Ok(()),
+                TxStep::Send(frame, st_min) => {
+                    tokio::time::sleep(st_min).await;
+                    self.transmit(&frame).await?;
+                }
+                TxStep::AwaitFlowControl => {
+                    let data = match self.receive(self.config.n_bs).await {
+                        Ok(data) => data,
+                        Err(CanToolError::Timeout) => {
+                            warn!("ISO-TP N_Bs timeout");
+                            return Err(CanToolError::Timeout);
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    sender.on_flow_control(&data)?;
+                }
+            }
+        }
+    }
+
+    /// Receives a complete payload, `timeout` bounds the wait for its first frame
+    pub async fn recv(&mut self, timeout: Duration) -> Result<Vec<u8>, CanToolError> {
+        let mut wait = timeout;
+        loop {
+            let data = match self.receive(wait).await {
+                Ok(data) => data,
+                Err(CanToolError::Timeout) if self.receiver.is_active() => {
+                    warn!("ISO-TP N_Cr timeout");
+                    return Err(CanToolError::Timeout);
+                }
+                Err(e) => return Err(e),
+            };
+
+            match self.receiver.on_frame(&self.config, &data)? {
+                RxEvent::Ignored => {}
+                RxEvent::InProgress(flow_control) => {
+                    if let Some(frame) = flow_control {
+                        self.transmit(&frame).await?;
+                    }
+                    if !self.receiver.is_active() {
+                        return Err(CanToolError::Protocol("ISO-TP payload exceeds the receive buffer.".to_string()));
+                    }
+                    wait = self.config.n_cr;
+                }
+                RxEvent::Complete(payload) => return Ok(payload),
+            }
+        }
+    }
+}
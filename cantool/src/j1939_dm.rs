@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use crate::can_error::CanToolError;
+use crate::j1939_tp::J1939Message;
+
+/// Active diagnostic trouble codes
+pub const PGN_DM1: u32 = 0xFECA;
+/// Previously active diagnostic trouble codes
+pub const PGN_DM2: u32 = 0xFECB;
+/// Clear/reset of active diagnostic trouble codes
+pub const PGN_DM11: u32 = 0xFED3;
+pub const PGN_REQUEST: u32 = 0xEA00;
+
+/// State of a 2-bit lamp field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LampState {
+    Off,
+    On,
+    Error,
+    NotAvailable,
+}
+
+impl LampState {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x3 {
+            0 => LampState::Off,
+            1 => LampState::On,
+            2 => LampState::Error,
+            _ => LampState::NotAvailable,
+        }
+    }
+}
+
+/// Lamp status byte of a DM1/DM2 message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LampStatus {
+    pub malfunction_indicator: LampState,
+    pub red_stop: LampState,
+    pub amber_warning: LampState,
+    pub protect: LampState,
+}
+
+impl LampStatus {
+    pub fn from_byte(byte: u8) -> Self {
+        LampStatus {
+            malfunction_indicator: LampState::from_bits(byte >> 6),
+            red_stop: LampState::from_bits(byte >> 4),
+            amber_warning: LampState::from_bits(byte >> 2),
+            protect: LampState::from_bits(byte),
+        }
+    }
+}
+
+/// J1939 diagnostic trouble code (conversion method 4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dtc {
+    pub spn: u32,
+    pub fmi: u8,
+    pub occurrence_count: u8,
+    pub lamp_status: LampStatus,
+}
+
+impl Dtc {
+    /// Decodes the 4 byte DTC field
+    fn from_bytes(bytes: &[u8], lamp_status: LampStatus) -> Self {
+        Dtc {
+            spn: bytes[0] as u32 | (bytes[1] as u32) << 8 | ((bytes[2] & 0xE0) as u32) << 11,
+            fmi: bytes[2] & 0x1F,
+            occurrence_count: bytes[3] & 0x7F,
+            lamp_status,
+        }
+    }
+}
+
+/// Decoded DM1 or DM2 payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmMessage {
+    pub lamp_status: LampStatus,
+    pub dtcs: Vec<Dtc>,
+}
+
+/// Decodes a DM1/DM2 payload, single frame (8 bytes) or reassembled
+pub fn decode_dm(data: &[u8]) -> Result<DmMessage, CanToolError> {
+    if data.len() < 6 {
+        return Err(CanToolError::DecodeFailed("DM payload too short.".to_string()));
+    }
+
+    let lamp_status = LampStatus::from_byte(data[0]);
+    let dtcs = data[2..]
+        .chunks_exact(4)
+        // an all zero DTC means "no active faults", 0xFF is padding
+        .filter(|bytes| bytes[..3] != [0, 0, 0] && bytes[..3] != [0xFF, 0xFF, 0xFF])
+        .map(|bytes| Dtc::from_bytes(bytes, lamp_status))
+        .collect();
+
+    Ok(DmMessage { lamp_status, dtcs })
+}
+
+/// Request for a DM11 clear of active DTCs, `destination` 0xFF addresses every node
+pub fn dm11_clear_request(source: u8, destination: u8) -> J1939Message {
+    let pgn = PGN_DM11.to_le_bytes();
+    J1939Message::new(PGN_REQUEST, source, destination, vec![pgn[0], pgn[1], pgn[2]])
+}
+
+/// DTCs raised and cleared since the previous DM1 of a node
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultChanges {
+    pub source: u8,
+    pub raised: Vec<Dtc>,
+    pub cleared: Vec<Dtc>,
+}
+
+impl FaultChanges {
+    pub fn is_empty(&self) -> bool {
+        self.raised.is_empty() && self.cleared.is_empty()
+    }
+}
+
+/// Tracks the active DTCs per source address from DM1 messages
+#[derive(Debug, Default)]
+pub struct ActiveFaultTracker {
+    active: HashMap<u8, HashMap<(u32, u8), Dtc>>,
+}
+
+impl ActiveFaultTracker {
+    pub fn new() -> Self {
+        ActiveFaultTracker::default()
+    }
+
+    /// Replaces the active DTCs of `source`, returns the difference to the previous set
+    pub fn update(&mut self, source: u8, dtcs: &[Dtc]) -> FaultChanges {
+        let current: HashMap<(u32, u8), Dtc> = dtcs.iter().map(|dtc| ((dtc.spn, dtc.fmi), *dtc)).collect();
+        let previous = self.active.remove(&source).unwrap_or_default();
+
+        let raised = current
+            .iter()
+            .filter(|(key, _)| !previous.contains_key(key))
+            .map(|(_, dtc)| *dtc)
+            .collect();
+        let cleared = previous
+            .iter()
+            .filter(|(key, _)| !current.contains_key(key))
+            .map(|(_, dtc)| *dtc)
+            .collect();
+
+        self.active.insert(source, current);
+        FaultChanges { source, raised, cleared }
+    }
+
+    /// Feeds a J1939 message, returns the changes when it is a DM1
+    pub fn handle_message(&mut self, message: &J1939Message) -> Option<FaultChanges> {
+        if message.pgn != PGN_DM1 {
+            return None;
+        }
+
+        let dm1 = decode_dm(&message.data).ok()?;
+        Some(self.update(message.source, &dm1.dtcs))
+    }
+
+    /// Currently active DTCs of a node
+    pub fn active(&self, source: u8) -> Vec<Dtc> {
+        self.active
+            .get(&source)
+            .map(|dtcs| dtcs.values().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the DTCs of every node, e.g. after sending a DM11
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+}
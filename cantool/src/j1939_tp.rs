@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use log::{debug, warn};
+use tokio::time::{Duration, Instant};
+use crate::can_frame::RawFrame;
+use crate::can_error::CanToolError;
+
+pub const PGN_TP_CM: u32 = 0xEC00;
+pub const PGN_TP_DT: u32 = 0xEB00;
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+const TP_CM_RTS: u8 = 16;
+const TP_CM_CTS: u8 = 17;
+const TP_CM_EOMA: u8 = 19;
+const TP_CM_BAM: u8 = 32;
+const TP_CM_ABORT: u8 = 255;
+
+/// Largest payload the transport protocol can carry (255 packets of 7 bytes)
+pub const TP_MAX_LEN: usize = 1785;
+const TP_DEFAULT_PRIORITY: u8 = 7;
+
+/// Timeout between data packets (T1)
+const TP_T1: Duration = Duration::from_millis(750);
+/// Timeout waiting for data after a CTS (T2)
+const TP_T2: Duration = Duration::from_millis(1250);
+/// Timeout waiting for a CTS or EOMA (T3)
+pub const TP_T3: Duration = Duration::from_millis(1250);
+/// Gap between BAM data packets
+pub const TP_BAM_GAP: Duration = Duration::from_millis(50);
+
+/// Abort reasons of a TP.CM_Abort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpAbortReason {
+    Busy = 1,
+    NoResources = 2,
+    Timeout = 3,
+    Other = 0xFE,
+}
+
+/// Fields of a 29-bit J1939 identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct J1939Id {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+}
+
+impl J1939Id {
+    pub fn from_can_id(id: u32) -> Self {
+        let id = id & 0x1FFFFFFF;
+        let pf = ((id >> 16) & 0xFF) as u8;
+        let ps = ((id >> 8) & 0xFF) as u8;
+        let dp = (id >> 8) & 0x30000;
+
+        // PDU1 carries the destination address in PS
+        let (pgn, destination) = if pf < 240 {
+            (dp | (pf as u32) << 8, ps)
+        } else {
+            (dp | (pf as u32) << 8 | ps as u32, GLOBAL_ADDRESS)
+        };
+
+        J1939Id {
+            priority: ((id >> 26) & 0x7) as u8,
+            pgn,
+            source: (id & 0xFF) as u8,
+            destination,
+        }
+    }
+
+    pub fn to_can_id(&self) -> u32 {
+        let pf = (self.pgn >> 8) & 0xFF;
+        let ps = if pf < 240 { self.destination as u32 } else { self.pgn & 0xFF };
+
+        ((self.priority as u32 & 0x7) << 26) | (self.pgn & 0x30000) << 8 | pf << 16 | ps << 8 | self.source as u32
+    }
+}
+
+/// A complete J1939 message, single frame or reassembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct J1939Message {
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+    pub priority: u8,
+    pub data: Vec<u8>,
+}
+
+impl J1939Message {
+    pub fn new(pgn: u32, source: u8, destination: u8, data: Vec<u8>) -> Self {
+        J1939Message { pgn, source, destination, priority: TP_DEFAULT_PRIORITY, data }
+    }
+
+    /// CAN identifier the message is (or would be) sent with as a single frame
+    pub fn can_id(&self) -> u32 {
+        J1939Id {
+            priority: self.priority,
+            pgn: self.pgn,
+            source: self.source,
+            destination: self.destination,
+        }
+        .to_can_id()
+    }
+}
+
+/// Result of feeding a frame into the transport protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TpEvent {
+    /// A multi-packet message was reassembled
+    Complete(J1939Message),
+    /// A frame that has to be transmitted (CTS, EOMA, abort)
+    Send(RawFrame),
+    /// A session was aborted by the peer or timed out
+    Aborted { pgn: u32, source: u8 },
+}
+
+#[derive(Debug)]
+struct RxSession {
+    pgn: u32,
+    destination: u8,
+    size: usize,
+    total_packets: u8,
+    next_seq: u8,
+    /// Packets granted per CTS and last sequence number of the current window
+    max_per_cts: u8,
+    window_end: u8,
+    data: Vec<u8>,
+    deadline: Instant,
+}
+
+fn tp_frame(pgn: u32, source: u8, destination: u8, data: [u8; 8]) -> RawFrame {
+    let id = J1939Id { priority: TP_DEFAULT_PRIORITY, pgn, source, destination };
+    RawFrame::new(id.to_can_id(), &data)
+}
+
+/// TP.CM frame, `header` holds the control byte and the four control specific bytes
+fn cm_frame(source: u8, destination: u8, header: [u8; 5], pgn: u32) -> RawFrame {
+    let pgn = pgn.to_le_bytes();
+    let [control, b1, b2, b3, b4] = header;
+    tp_frame(PGN_TP_CM, source, destination, [control, b1, b2, b3, b4, pgn[0], pgn[1], pgn[2]])
+}
+
+fn abort_frame(source: u8, destination: u8, reason: TpAbortReason, pgn: u32) -> RawFrame {
+    cm_frame(source, destination, [TP_CM_ABORT, reason as u8, 0xFF, 0xFF, 0xFF], pgn)
+}
+
+fn packet_count(len: usize) -> u8 {
+    len.div_ceil(7) as u8
+}
+
+/// Reassembles BAM and RTS/CTS transfers, answering RTS sessions addressed to `address`
+#[derive(Debug)]
+pub struct TpReassembler {
+    address: Option<u8>,
+    sessions: HashMap<(u8, u8), RxSession>,
+}
+
+impl TpReassembler {
+    /// `address` is our own source address, None only listens to BAM transfers
+    pub fn new(address: Option<u8>) -> Self {
+        TpReassembler { address, sessions: HashMap::new() }
+    }
+
+    pub fn set_address(&mut self, address: Option<u8>) {
+        self.address = address;
+    }
+
+    /// Whether the CAN identifier belongs to TP.CM or TP.DT
+    pub fn is_tp_frame(can_id: u32) -> bool {
+        let pgn = J1939Id::from_can_id(can_id).pgn;
+        pgn == PGN_TP_CM || pgn == PGN_TP_DT
+    }
+
+    /// Feeds a received frame, non transport protocol frames are ignored
+    pub fn handle_frame(&mut self, can_id: u32, data: &[u8], now: Instant) -> Vec<TpEvent> {
+        let mut events = self.expire(now);
+        let id = J1939Id::from_can_id(can_id);
+        if data.len() < 8 {
+            return events;
+        }
+
+        match id.pgn {
+            PGN_TP_CM => self.handle_cm(id, data, now, &mut events),
+            PGN_TP_DT => self.handle_dt(id, data, now, &mut events),
+            _ => {}
+        }
+
+        events
+    }
+
+    fn handle_cm(&mut self, id: J1939Id, data: &[u8], now: Instant, events: &mut Vec<TpEvent>) {
+        let pgn = u32::from_le_bytes([data[5], data[6], data[7], 0]);
+        let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let key = (id.source, id.destination);
+
+        match data[0] {
+            TP_CM_BAM if id.destination == GLOBAL_ADDRESS => {
+                // a broadcast cannot be aborted, an inconsistent announcement is just ignored
+                if size > TP_MAX_LEN || data[3] != packet_count(size) {
+                    warn!("Invalid BAM from {:02x}: {} bytes in {} packets", id.source, size, data[3]);
+                    return;
+                }
+                if self.sessions.contains_key(&key) {
+                    debug!("BAM from {:02x} restarted before completion", id.source);
+                }
+                self.sessions.insert(key, RxSession {
+                    pgn,
+                    destination: GLOBAL_ADDRESS,
+                    size,
+                    total_packets: data[3],
+                    next_seq: 1,
+                    max_per_cts: data[3],
+                    window_end: data[3],
+                    data: Vec::with_capacity(size),
+                    deadline: now + TP_T1,
+                });
+            }
+            TP_CM_RTS if Some(id.destination) == self.address => {
+                if size > TP_MAX_LEN || data[3] != packet_count(size) {
+                    events.push(TpEvent::Send(abort_frame(id.destination, id.source, TpAbortReason::NoResources, pgn)));
+                    return;
+                }
+
+                // request as many packets at once as the sender allows
+                let total_packets = data[3];
+                let count = total_packets.min(data[4]);
+                self.sessions.insert(key, RxSession {
+                    pgn,
+                    destination: id.destination,
+                    size,
+                    total_packets,
+                    next_seq: 1,
+                    max_per_cts: data[4],
+                    window_end: count,
+                    data: Vec::with_capacity(size),
+                    deadline: now + TP_T2,
+                });
+
+                events.push(TpEvent::Send(cm_frame(id.destination, id.source, [TP_CM_CTS, count, 1, 0xFF, 0xFF], pgn)));
+            }
+            TP_CM_ABORT => {
+                if self.sessions.remove(&key).is_some() {
+                    warn!("TP session {:x} from {:02x} aborted, reason {}", pgn, id.source, data[1]);
+                    events.push(TpEvent::Aborted { pgn, source: id.source });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_dt(&mut self, id: J1939Id, data: &[u8], now: Instant, events: &mut Vec<TpEvent>) {
+        let key = (id.source, id.destination);
+        let session = match self.sessions.get_mut(&key) {
+            Some(session) => session,
+            None => return,
+        };
+
+        if data[0] != session.next_seq {
+            warn!("TP sequence error from {:02x}: expected {}, got {}", id.source, session.next_seq, data[0]);
+            let session = self.sessions.remove(&key).unwrap();
+            if session.destination != GLOBAL_ADDRESS {
+                events.push(TpEvent::Send(abort_frame(id.destination, id.source, TpAbortReason::Other, session.pgn)));
+            }
+            events.push(TpEvent::Aborted { pgn: session.pgn, source: id.source });
+            return;
+        }
+
+        session.data.extend_from_slice(&data[1..8]);
+        session.next_seq = session.next_seq.wrapping_add(1);
+        session.deadline = now + TP_T1;
+
+        if data[0] == session.total_packets {
+            let mut session = self.sessions.remove(&key).unwrap();
+            session.data.truncate(session.size);
+
+            if session.destination != GLOBAL_ADDRESS {
+                let size = session.size;
+                events.push(TpEvent::Send(cm_frame(
+                    session.destination,
+                    id.source,
+                    [TP_CM_EOMA, size as u8, (size >> 8) as u8, session.total_packets, 0xFF],
+                    session.pgn,
+                )));
+            }
+
+            events.push(TpEvent::Complete(J1939Message {
+                pgn: session.pgn,
+                source: id.source,
+                destination: session.destination,
+                priority: id.priority,
+                data: session.data,
+            }));
+        } else if data[0] == session.window_end && session.destination != GLOBAL_ADDRESS {
+            // window received, grant the next one
+            let count = (session.total_packets - data[0]).min(session.max_per_cts);
+            session.window_end = data[0] + count;
+            session.deadline = now + TP_T2;
+            events.push(TpEvent::Send(cm_frame(
+                session.destination,
+                id.source,
+                [TP_CM_CTS, count, session.next_seq, 0xFF, 0xFF],
+                session.pgn,
+            )));
+        }
+    }
+
+    /// Drops sessions that exceeded their timeout
+    pub fn expire(&mut self, now: Instant) -> Vec<TpEvent> {
+        let expired: Vec<(u8, u8)> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| now > session.deadline)
+            .map(|(key, _)| *key)
+            .collect();
+
+        let mut events = Vec::new();
+        for key in expired {
+            if let Some(session) = self.sessions.remove(&key) {
+                warn!("TP session {:x} from {:02x} timed out", session.pgn, key.0);
+                if session.destination != GLOBAL_ADDRESS {
+                    events.push(TpEvent::Send(abort_frame(session.destination, key.0, TpAbortReason::Timeout, session.pgn)));
+                }
+                events.push(TpEvent::Aborted { pgn: session.pgn, source: key.0 });
+            }
+        }
+
+        events
+    }
+}
+
+/// Splits a payload into TP.DT frames
+fn data_frames(message: &J1939Message, first_seq: u8, count: u8) -> Vec<RawFrame> {
+    message
+        .data
+        .chunks(7)
+        .enumerate()
+        .skip(first_seq as usize - 1)
+        .take(count as usize)
+        .map(|(i, chunk)| {
+            let mut data = [0xFFu8; 8];
+            data[0] = (i + 1) as u8;
+            data[1..1 + chunk.len()].copy_from_slice(chunk);
+            tp_frame(PGN_TP_DT, message.source, message.destination, data)
+        })
+        .collect()
+}
+
+/// Segments a broadcast message into a BAM announcement followed by its data packets
+pub fn segment_bam(message: &J1939Message) -> Result<Vec<RawFrame>, CanToolError> {
+    if message.data.len() <= 8 || message.data.len() > TP_MAX_LEN {
+        return Err(CanToolError::Protocol("Payload size not suitable for the J1939 transport protocol.".to_string()));
+    }
+
+    let size = message.data.len();
+    let packets = packet_count(size);
+    let mut frames = vec![cm_frame(
+        message.source,
+        GLOBAL_ADDRESS,
+        [TP_CM_BAM, size as u8, (size >> 8) as u8, packets, 0xFF],
+        message.pgn,
+    )];
+    frames.extend(data_frames(message, 1, packets));
+
+    Ok(frames)
+}
+
+/// Progress of a destination specific (RTS/CTS) transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TpSendState {
+    /// Waiting for the next CTS or the EOMA
+    Waiting,
+    /// Transmit these data packets, then keep waiting
+    SendData(Vec<RawFrame>),
+    Done,
+    Aborted(u8),
+}
+
+/// Sender side of an RTS/CTS transfer
+#[derive(Debug)]
+pub struct TpSender {
+    message: J1939Message,
+}
+
+impl TpSender {
+    pub fn new(message: J1939Message) -> Result<Self, CanToolError> {
+        if message.data.len() <= 8 || message.data.len() > TP_MAX_LEN {
+            return Err(CanToolError::Protocol("Payload size not suitable for the J1939 transport protocol.".to_string()));
+        }
+        if message.destination == GLOBAL_ADDRESS {
+            return Err(CanToolError::Protocol("Global messages must be sent with BAM.".to_string()));
+        }
+
+        Ok(TpSender { message })
+    }
+
+    /// The RTS frame opening the session
+    pub fn request_to_send(&self) -> RawFrame {
+        let size = self.message.data.len();
+        cm_frame(
+            self.message.source,
+            self.message.destination,
+            [TP_CM_RTS, size as u8, (size >> 8) as u8, packet_count(size), 0xFF],
+            self.message.pgn,
+        )
+    }
+
+    /// Feeds a received frame and returns what to do next
+    pub fn handle_frame(&mut self, can_id: u32, data: &[u8]) -> TpSendState {
+        let id = J1939Id::from_can_id(can_id);
+        let own_session = id.pgn == PGN_TP_CM
+            && id.source == self.message.destination
+            && id.destination == self.message.source
+            && data.len() >= 8
+            && u32::from_le_bytes([data[5], data[6], data[7], 0]) == self.message.pgn;
+
+        if !own_session {
+            return TpSendState::Waiting;
+        }
+
+        match data[0] {
+            // a CTS for zero packets means "hold the connection open"
+            TP_CM_CTS if data[1] == 0 => TpSendState::Waiting,
+            TP_CM_CTS => TpSendState::SendData(data_frames(&self.message, data[2].max(1), data[1])),
+            TP_CM_EOMA => TpSendState::Done,
+            TP_CM_ABORT => TpSendState::Aborted(data[1]),
+            _ => TpSendState::Waiting,
+        }
+    }
+}
@@ -1 +1,24 @@
 pub mod can_tool;
+pub mod can_codec;
+pub mod can_dbc;
+pub mod can_fd;
+pub mod can_frame;
+pub mod can_cache;
+pub mod can_log;
+pub mod j1939_tp;
+pub mod j1939_dm;
+pub mod iso_tp;
+pub mod uds_client;
+pub mod can_error;
+pub mod can_retry;
+pub mod can_filter;
+pub mod can_manager;
+pub mod can_stats;
+pub mod can_signal;
+pub mod can_scheduler;
+pub mod can_e2e;
+pub mod can_transport;
+pub mod can_sim;
+pub mod can_virtual;
+pub mod can_trigger;
+pub mod can_bridge;
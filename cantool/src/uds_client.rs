@@ -0,0 +1,374 @@
+use std::error::Error as StdError;
+use std::fmt;
+use log::{debug, info, warn};
+use tokio::time::Duration;
+use crate::can_error::CanToolError;
+use crate::iso_tp::IsoTpChannel;
+
+const SID_DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+const SID_ECU_RESET: u8 = 0x11;
+const SID_READ_DTC_INFORMATION: u8 = 0x19;
+const SID_READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+const SID_SECURITY_ACCESS: u8 = 0x27;
+const SID_WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+const SID_ROUTINE_CONTROL: u8 = 0x31;
+const SID_REQUEST_DOWNLOAD: u8 = 0x34;
+const SID_TRANSFER_DATA: u8 = 0x36;
+const SID_REQUEST_TRANSFER_EXIT: u8 = 0x37;
+const SID_TESTER_PRESENT: u8 = 0x3E;
+const SID_NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+/// reportDTCByStatusMask sub-function of ReadDTCInformation
+pub const REPORT_DTC_BY_STATUS_MASK: u8 = 0x02;
+
+const DEFAULT_P2: Duration = Duration::from_millis(50);
+const DEFAULT_P2_STAR: Duration = Duration::from_millis(5000);
+
+/// UDS negative response codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nrc {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupported,
+    IncorrectMessageLengthOrInvalidFormat,
+    ResponseTooLong,
+    BusyRepeatRequest,
+    ConditionsNotCorrect,
+    RequestSequenceError,
+    NoResponseFromSubnetComponent,
+    FailurePreventsExecutionOfRequestedAction,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    InvalidKey,
+    ExceededNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    UploadDownloadNotAccepted,
+    TransferDataSuspended,
+    GeneralProgrammingFailure,
+    WrongBlockSequenceCounter,
+    ResponsePending,
+    SubFunctionNotSupportedInActiveSession,
+    ServiceNotSupportedInActiveSession,
+    Other(u8),
+}
+
+impl Nrc {
+    pub fn from_u8(code: u8) -> Nrc {
+        match code {
+            0x10 => Nrc::GeneralReject,
+            0x11 => Nrc::ServiceNotSupported,
+            0x12 => Nrc::SubFunctionNotSupported,
+            0x13 => Nrc::IncorrectMessageLengthOrInvalidFormat,
+            0x14 => Nrc::ResponseTooLong,
+            0x21 => Nrc::BusyRepeatRequest,
+            0x22 => Nrc::ConditionsNotCorrect,
+            0x24 => Nrc::RequestSequenceError,
+            0x25 => Nrc::NoResponseFromSubnetComponent,
+            0x26 => Nrc::FailurePreventsExecutionOfRequestedAction,
+            0x31 => Nrc::RequestOutOfRange,
+            0x33 => Nrc::SecurityAccessDenied,
+            0x35 => Nrc::InvalidKey,
+            0x36 => Nrc::ExceededNumberOfAttempts,
+            0x37 => Nrc::RequiredTimeDelayNotExpired,
+            0x70 => Nrc::UploadDownloadNotAccepted,
+            0x71 => Nrc::TransferDataSuspended,
+            0x72 => Nrc::GeneralProgrammingFailure,
+            0x73 => Nrc::WrongBlockSequenceCounter,
+            0x78 => Nrc::ResponsePending,
+            0x7E => Nrc::SubFunctionNotSupportedInActiveSession,
+            0x7F => Nrc::ServiceNotSupportedInActiveSession,
+            other => Nrc::Other(other),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UdsError {
+    /// The ECU answered with a negative response
+    NegativeResponse { service: u8, nrc: Nrc },
+    /// The response does not match the request
+    InvalidResponse(String),
+    /// The request was not sent because an argument is out of range
+    InvalidArgument(String),
+    /// ISO-TP or socket failure, including timeouts
+    Transport(CanToolError),
+}
+
+impl fmt::Display for UdsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdsError::NegativeResponse { service, nrc: Nrc::Other(code) } => {
+                write!(f, "Negative response {:02x} to service {:02x}", code, service)
+            }
+            UdsError::NegativeResponse { service, nrc } => {
+                write!(f, "Negative response {:?} to service {:02x}", nrc, service)
+            }
+            UdsError::InvalidResponse(reason) => write!(f, "Invalid UDS response: {}", reason),
+            UdsError::InvalidArgument(reason) => write!(f, "Invalid UDS request: {}", reason),
+            UdsError::Transport(e) => write!(f, "UDS transport error: {}", e),
+        }
+    }
+}
+
+impl StdError for UdsError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            UdsError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CanToolError> for UdsError {
+    fn from(err: CanToolError) -> UdsError {
+        UdsError::Transport(err)
+    }
+}
+
+/// Diagnostic sessions of DiagnosticSessionControl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticSession {
+    Default = 0x01,
+    Programming = 0x02,
+    ExtendedDiagnostic = 0x03,
+}
+
+/// Control types of RoutineControl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineControlType {
+    Start = 0x01,
+    Stop = 0x02,
+    RequestResults = 0x03,
+}
+
+/// Computes the SecurityAccess key for a seed, implemented for closures
+pub trait SecurityAlgorithm {
+    fn compute_key(&self, level: u8, seed: &[u8]) -> Vec<u8>;
+}
+
+impl<F: Fn(u8, &[u8]) -> Vec<u8>> SecurityAlgorithm for F {
+    fn compute_key(&self, level: u8, seed: &[u8]) -> Vec<u8> {
+        self(level, seed)
+    }
+}
+
+/// DTC reported by ReadDTCInformation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdsDtc {
+    /// 3 byte DTC number
+    pub code: u32,
+    pub status: u8,
+}
+
+/// UDS (ISO 14229) client on top of an ISO-TP channel
+pub struct UdsClient<'a> {
+    channel: IsoTpChannel<'a>,
+    p2: Duration,
+    p2_star: Duration,
+}
+
+impl<'a> UdsClient<'a> {
+    pub fn new(channel: IsoTpChannel<'a>) -> Self {
+        UdsClient { channel, p2: DEFAULT_P2, p2_star: DEFAULT_P2_STAR }
+    }
+
+    /// Sends a raw request and waits for its positive response
+    pub async fn request(&mut self, request: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let service = match request.first() {
+            Some(service) => *service,
+            None => return Err(UdsError::InvalidArgument("Empty request".to_string())),
+        };
+
+        self.channel.send(request).await?;
+        let mut wait = self.p2;
+
+        loop {
+            let response = self.channel.recv(wait).await?;
+
+            match response.as_slice() {
+                [SID_NEGATIVE_RESPONSE, sid, code, ..] if *sid == service => match Nrc::from_u8(*code) {
+                    Nrc::ResponsePending => {
+                        debug!("UDS service {:02x} response pending", service);
+                        wait = self.p2_star;
+                    }
+                    nrc => {
+                        warn!("UDS service {:02x} negative response {:?}", service, nrc);
+                        return Err(UdsError::NegativeResponse { service, nrc });
+                    }
+                },
+                [sid, ..] if *sid == service.wrapping_add(POSITIVE_RESPONSE_OFFSET) => return Ok(response),
+                _ => {
+                    return Err(UdsError::InvalidResponse(format!(
+                        "Unexpected response {:02x?} to service {:02x}",
+                        response, service
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Switches the diagnostic session, the ECU timing parameters are taken over
+    pub async fn diagnostic_session_control(&mut self, session: DiagnosticSession) -> Result<(), UdsError> {
+        let response = self.request(&[SID_DIAGNOSTIC_SESSION_CONTROL, session as u8]).await?;
+
+        if let [_, _, p2_hi, p2_lo, p2s_hi, p2s_lo, ..] = response.as_slice() {
+            self.p2 = Duration::from_millis(u16::from_be_bytes([*p2_hi, *p2_lo]) as u64);
+            self.p2_star = Duration::from_millis(u16::from_be_bytes([*p2s_hi, *p2s_lo]) as u64 * 10);
+        }
+
+        info!("UDS session {:?} active", session);
+        Ok(())
+    }
+
+    pub async fn ecu_reset(&mut self, reset_type: u8) -> Result<(), UdsError> {
+        self.request(&[SID_ECU_RESET, reset_type]).await?;
+        Ok(())
+    }
+
+    /// Keeps a non default session alive
+    pub async fn tester_present(&mut self) -> Result<(), UdsError> {
+        self.request(&[SID_TESTER_PRESENT, 0x00]).await?;
+        Ok(())
+    }
+
+    pub async fn read_data_by_identifier(&mut self, did: u16) -> Result<Vec<u8>, UdsError> {
+        let did = did.to_be_bytes();
+        let response = self.request(&[SID_READ_DATA_BY_IDENTIFIER, did[0], did[1]]).await?;
+
+        if response.len() < 3 || response[1..3] != did {
+            return Err(UdsError::InvalidResponse("Data identifier mismatch".to_string()));
+        }
+        Ok(response[3..].to_vec())
+    }
+
+    pub async fn write_data_by_identifier(&mut self, did: u16, data: &[u8]) -> Result<(), UdsError> {
+        let mut request = vec![SID_WRITE_DATA_BY_IDENTIFIER];
+        request.extend_from_slice(&did.to_be_bytes());
+        request.extend_from_slice(data);
+
+        self.request(&request).await?;
+        Ok(())
+    }
+
+    /// Unlocks the odd security `level` (0x01 to 0x7D) with the key computed by `algorithm`
+    pub async fn security_access(&mut self, level: u8, algorithm: &dyn SecurityAlgorithm) -> Result<(), UdsError> {
+        if level % 2 == 0 || level >= 0x7F {
+            return Err(UdsError::InvalidArgument(format!(
+                "Security level {:02x} is not an odd requestSeed level below 0x7F",
+                level
+            )));
+        }
+
+        let response = self.request(&[SID_SECURITY_ACCESS, level]).await?;
+        let seed = response.get(2..).unwrap_or_default();
+
+        // a zero seed means the level is already unlocked
+        if seed.iter().all(|byte| *byte == 0) {
+            info!("UDS security level {:02x} already unlocked", level);
+            return Ok(());
+        }
+
+        let mut request = vec![SID_SECURITY_ACCESS, level + 1];
+        request.extend(algorithm.compute_key(level, seed));
+        self.request(&request).await?;
+
+        info!("UDS security level {:02x} unlocked", level);
+        Ok(())
+    }
+
+    /// Controls a routine, returns the routine status record
+    pub async fn routine_control(
+        &mut self,
+        control: RoutineControlType,
+        routine_id: u16,
+        option: &[u8],
+    ) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![SID_ROUTINE_CONTROL, control as u8];
+        request.extend_from_slice(&routine_id.to_be_bytes());
+        request.extend_from_slice(option);
+
+        let response = self.request(&request).await?;
+        Ok(response.get(4..).unwrap_or_default().to_vec())
+    }
+
+    /// Raw ReadDTCInformation, returns the response after the sub-function
+    pub async fn read_dtc_information(&mut self, sub_function: u8, parameters: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![SID_READ_DTC_INFORMATION, sub_function];
+        request.extend_from_slice(parameters);
+
+        let response = self.request(&request).await?;
+        Ok(response.get(2..).unwrap_or_default().to_vec())
+    }
+
+    /// DTCs matching a status mask (reportDTCByStatusMask)
+    pub async fn read_dtc_by_status_mask(&mut self, mask: u8) -> Result<Vec<UdsDtc>, UdsError> {
+        let response = self.read_dtc_information(REPORT_DTC_BY_STATUS_MASK, &[mask]).await?;
+
+        // availability mask followed by 4 byte records
+        Ok(response
+            .get(1..)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .map(|record| UdsDtc {
+                code: u32::from_be_bytes([0, record[0], record[1], record[2]]),
+                status: record[3],
+            })
+            .collect())
+    }
+
+    /// Starts a download to `address`, returns the maximum TransferData block length
+    pub async fn request_download(&mut self, address: u32, size: u32, data_format: u8) -> Result<usize, UdsError> {
+        // 4 byte memory size and 4 byte memory address
+        let mut request = vec![SID_REQUEST_DOWNLOAD, data_format, 0x44];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&size.to_be_bytes());
+
+        let response = self.request(&request).await?;
+        let len = match response.get(1) {
+            Some(format) => (format >> 4) as usize,
+            None => return Err(UdsError::InvalidResponse("Missing length format".to_string())),
+        };
+
+        match response.get(2..2 + len) {
+            Some(bytes) if len > 0 && len <= 8 => {
+                Ok(bytes.iter().fold(0usize, |acc, byte| (acc << 8) | *byte as usize))
+            }
+            _ => Err(UdsError::InvalidResponse("Invalid maxNumberOfBlockLength".to_string())),
+        }
+    }
+
+    pub async fn transfer_data(&mut self, sequence: u8, data: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![SID_TRANSFER_DATA, sequence];
+        request.extend_from_slice(data);
+
+        let response = self.request(&request).await?;
+        if response.get(1) != Some(&sequence) {
+            return Err(UdsError::InvalidResponse("Block sequence counter mismatch".to_string()));
+        }
+        Ok(response[2..].to_vec())
+    }
+
+    pub async fn request_transfer_exit(&mut self) -> Result<Vec<u8>, UdsError> {
+        let response = self.request(&[SID_REQUEST_TRANSFER_EXIT]).await?;
+        Ok(response[1..].to_vec())
+    }
+
+    /// Downloads an image: RequestDownload, TransferData blocks and RequestTransferExit
+    pub async fn download(&mut self, address: u32, image: &[u8], data_format: u8) -> Result<(), UdsError> {
+        let max_block_len = self.request_download(address, image.len() as u32, data_format).await?;
+        // the block length includes the service ID and the sequence counter
+        let chunk = max_block_len.saturating_sub(2).max(1);
+
+        let mut sequence: u8 = 1;
+        for (i, block) in image.chunks(chunk).enumerate() {
+            debug!("UDS transfer block {} ({} bytes)", i + 1, block.len());
+            self.transfer_data(sequence, block).await?;
+            sequence = sequence.wrapping_add(1);
+        }
+
+        self.request_transfer_exit().await?;
+        info!("UDS download of {} bytes to {:08x} finished", image.len(), address);
+        Ok(())
+    }
+}
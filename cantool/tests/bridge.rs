@@ -0,0 +1,259 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use cantool::can_bridge::{
+    BridgeConfig, BridgeStats, FileSink, MqttConfig, MqttSink, TelemetryBatch, TelemetryBatcher, TelemetryBridge,
+    TelemetryFormat, TelemetrySink,
+};
+use cantool::can_error::CanToolError;
+use cantool::can_sim::SimBus;
+use cantool::can_tool::SignalUpdate;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde_json::Value;
+
+fn start() -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(1_700_000_000_000).unwrap()
+}
+
+fn update(offset_ms: i64, signals: &[(&str, f64)]) -> SignalUpdate {
+    SignalUpdate {
+        timestamp: start() + Duration::milliseconds(offset_ms),
+        frame_id: 0x100,
+        signals: signals.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+        e2e: None,
+    }
+}
+
+fn config() -> BridgeConfig {
+    let mut config = BridgeConfig::new("vehicle-1", TelemetryFormat::JsonLines);
+    config.min_interval = std::time::Duration::from_millis(100);
+    config.signal_intervals = HashMap::from([("Speed".to_string(), std::time::Duration::ZERO)]);
+    config
+}
+
+fn sample_batch() -> TelemetryBatch {
+    let mut batcher = TelemetryBatcher::new(config());
+    for i in 0..10 {
+        batcher.push(&update(i * 20, &[("Torque", i as f64), ("Speed", 2.0 * i as f64)]));
+    }
+    batcher.take_batch().expect("batch")
+}
+
+#[test]
+fn downsamples_per_signal() {
+    let mut config = config();
+    config.signals = ["Torque".to_string(), "Speed".to_string()].into();
+    let mut batcher = TelemetryBatcher::new(config);
+
+    for i in 0..10 {
+        batcher.push(&update(i * 20, &[("Torque", i as f64), ("Speed", 2.0 * i as f64), ("Current", 1.0)]));
+    }
+
+    // Torque at 0 and 100 ms, every Speed sample, Current is not selected
+    assert_eq!(batcher.len(), 12);
+    assert_eq!(batcher.dropped(), 8);
+
+    let batch = batcher.take_batch().expect("batch");
+    let torque: Vec<f64> = batch.signals["Torque"].iter().map(|sample| sample.value).collect();
+    assert_eq!(torque, vec![0.0, 5.0]);
+    assert_eq!(batch.signals["Speed"].len(), 10);
+    assert!(!batch.signals.contains_key("Current"));
+    assert!(batcher.take_batch().is_none());
+}
+
+#[test]
+fn batches_are_numbered() {
+    let mut config = config();
+    config.max_batch_samples = 2;
+    let mut batcher = TelemetryBatcher::new(config);
+
+    batcher.push(&update(0, &[("Speed", 1.0)]));
+    assert!(!batcher.is_full());
+    batcher.push(&update(10, &[("Speed", 2.0)]));
+    assert!(batcher.is_full());
+    assert_eq!(batcher.take_batch().unwrap().sequence, 0);
+
+    batcher.push(&update(20, &[("Speed", 3.0)]));
+    assert_eq!(batcher.take_batch().unwrap().sequence, 1);
+}
+
+#[test]
+fn json_and_cbor_carry_the_same_batch() {
+    let batch = sample_batch();
+
+    let json = batch.encode(TelemetryFormat::JsonLines).unwrap();
+    assert_eq!(json.last(), Some(&b'\n'));
+    assert_eq!(json.iter().filter(|byte| **byte == b'\n').count(), 1);
+    let from_json: Value = serde_json::from_slice(&json).unwrap();
+
+    let cbor = batch.encode(TelemetryFormat::Cbor).unwrap();
+    let from_cbor: Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+
+    assert_eq!(from_json, from_cbor);
+    assert!(cbor.len() < json.len());
+    assert_eq!(from_json["src"], "vehicle-1");
+    assert_eq!(from_json["seq"], 0);
+    assert_eq!(from_json["signals"]["Torque"][1][0], 1_700_000_000_100i64);
+    assert_eq!(from_json["signals"]["Torque"][1][1], 5.0);
+}
+
+#[tokio::test]
+async fn file_sink_appends_json_lines() {
+    let path = std::env::temp_dir().join(format!("cantool-bridge-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut sink = FileSink::open(&path).await.unwrap();
+    let mut batcher = TelemetryBatcher::new(config());
+    for i in 0..3 {
+        batcher.push(&update(i * 1000, &[("Torque", i as f64)]));
+        let payload = batcher.take_batch().unwrap().encode(TelemetryFormat::JsonLines).unwrap();
+        sink.publish(&payload).await.unwrap();
+    }
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let sequences: Vec<u64> = contents
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(sequences, vec![0, 1, 2]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Records the published payloads, fails while `failing` is set
+#[derive(Clone, Default)]
+struct TestSink {
+    failing: Arc<AtomicBool>,
+    published: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl TelemetrySink for TestSink {
+    fn publish<'a>(&'a mut self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), CanToolError>> {
+        async move {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(CanToolError::Publish("broker unreachable".to_string()));
+            }
+            self.published.lock().unwrap().push(payload.to_vec());
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl TestSink {
+    fn sequences(&self) -> Vec<u64> {
+        self.published
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|payload| serde_json::from_slice::<Value>(payload).unwrap()["seq"].as_u64().unwrap())
+            .collect()
+    }
+}
+
+/// Waits until the bridge counters satisfy `done`
+async fn wait_for(bridge: &TelemetryBridge, done: impl Fn(&BridgeStats) -> bool) -> BridgeStats {
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let stats = bridge.stats();
+            if done(&stats) {
+                return stats;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("bridge stats {:?}", bridge.stats()))
+}
+
+#[tokio::test]
+async fn failing_sink_keeps_max_pending_batches() {
+    let bus = SimBus::new();
+    let utils = common::sim_utils(&bus).await;
+    let sink = TestSink::default();
+    sink.failing.store(true, Ordering::SeqCst);
+
+    // every Motor frame fills a batch
+    let mut config = config();
+    config.min_interval = std::time::Duration::ZERO;
+    config.max_batch_samples = 1;
+    config.max_batch_age = std::time::Duration::from_secs(3600);
+    config.max_pending = 2;
+    let bridge = TelemetryBridge::spawn(utils, config, Box::new(sink.clone()));
+
+    for i in 0..5 {
+        bus.inject_data(0x100, &[i, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        wait_for(&bridge, |stats| stats.pending as u64 + stats.batches_dropped == i as u64 + 1).await;
+    }
+
+    let stats = bridge.stats();
+    assert_eq!(stats.batches_published, 0);
+    assert_eq!(stats.batches_dropped, 3);
+    assert_eq!(stats.pending, 2);
+    // one per attempt, the batch age timer retries as well
+    assert!(stats.publish_errors >= 5);
+    assert!(sink.sequences().is_empty());
+
+    // a new batch counts against max_pending too, the pending ones go out before it
+    sink.failing.store(false, Ordering::SeqCst);
+    bus.inject_data(0x100, &[5, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    let stats = wait_for(&bridge, |stats| stats.pending == 0).await;
+    assert_eq!(sink.sequences(), vec![4, 5]);
+    assert_eq!(stats.batches_published, 2);
+    assert_eq!(stats.batches_dropped, 4);
+    assert_eq!(stats.samples_published, 6);
+    assert_eq!(stats.pending, 0);
+}
+
+#[tokio::test]
+async fn zero_batch_age_is_clamped() {
+    let bus = SimBus::new();
+    let utils = common::sim_utils(&bus).await;
+    let sink = TestSink::default();
+
+    let mut config = config();
+    config.max_batch_age = std::time::Duration::ZERO;
+    let bridge = TelemetryBridge::spawn(utils, config, Box::new(sink.clone()));
+
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    wait_for(&bridge, |stats| stats.batches_published == 1).await;
+    assert!(bridge.is_running());
+    assert_eq!(sink.sequences(), vec![0]);
+}
+
+/// Needs a broker, e.g. `mosquitto -p 1883`, on `MQTT_BROKER` or localhost
+#[tokio::test]
+#[ignore]
+async fn mqtt_sink_publishes_to_broker() {
+    let host = std::env::var("MQTT_BROKER").unwrap_or_else(|_| "localhost".to_string());
+    let topic = format!("cantool/test/{}", std::process::id());
+
+    let (subscriber, mut eventloop) = AsyncClient::new(MqttOptions::new("cantool-test-subscriber", &host, 1883), 10);
+    subscriber.subscribe(&topic, QoS::AtLeastOnce).await.unwrap();
+    loop {
+        if let Event::Incoming(Packet::SubAck(_)) = eventloop.poll().await.unwrap() {
+            break;
+        }
+    }
+
+    let mut sink = MqttSink::connect(&MqttConfig::new(&host, 1883, "cantool-test-sink", &topic));
+    let payload = sample_batch().encode(TelemetryFormat::Cbor).unwrap();
+    sink.publish(&payload).await.unwrap();
+
+    let received = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Event::Incoming(Packet::Publish(publish)) = eventloop.poll().await.unwrap() {
+                return publish;
+            }
+        }
+    })
+    .await
+    .expect("batch from the broker");
+    assert_eq!(received.topic, topic);
+    assert_eq!(received.payload.as_ref(), payload.as_slice());
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::sync::Arc;
+use cantool::can_cache::{SignalCache, SignalCacheConfig};
+use cantool::can_retry::HealthMonitor;
+use cantool::can_sim::SimBus;
+use cantool::can_tool::CanUtils;
+use tokio::time::Duration;
+
+/// Odometer of the sample DBC, an extended frame without `GenMsgCycleTime`
+const ODOMETER_ID: u32 = 2566844926 & 0x1FFFFFFF;
+
+/// Lets the cache task take the injected frames, then advances the paused clock
+async fn advance(millis: u64) {
+    tokio::task::yield_now().await;
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+}
+
+fn stale(cache: &SignalCache) -> Vec<String> {
+    let mut stale = cache.stale_signals();
+    stale.sort();
+    stale
+}
+
+#[tokio::test(start_paused = true)]
+async fn dbc_cycle_time_sets_the_timeout() {
+    let bus = SimBus::new();
+    let cache = SignalCache::spawn(common::sim_utils(&bus).await, SignalCacheConfig::default());
+    assert!(cache.get("Torque").is_none());
+
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    bus.inject_data(ODOMETER_ID, &[0; 8]).unwrap();
+    advance(1).await;
+    let torque = cache.get("Torque").unwrap();
+    assert_eq!(torque.frame_id, 0x100);
+    assert!(!torque.stale);
+    assert_eq!(cache.get("TotalDistance").unwrap().frame_id, 2566844926);
+    assert!(stale(&cache).is_empty());
+
+    // Motor is sent every 10 ms, three missed cycles make it stale
+    advance(29).await;
+    assert!(stale(&cache).is_empty());
+    advance(1).await;
+    assert_eq!(stale(&cache), ["Current", "Speed", "Torque"]);
+    assert!(cache.get("Torque").unwrap().age > Duration::from_millis(30));
+
+    // Odometer has no cycle time and falls back to the default of 5 s
+    advance(4968).await;
+    assert_eq!(stale(&cache).len(), 3);
+    advance(2).await;
+    assert_eq!(stale(&cache), ["Current", "Energy", "Speed", "Torque", "TotalDistance"]);
+
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    advance(1).await;
+    assert_eq!(stale(&cache), ["Energy", "TotalDistance"]);
+    assert!(cache.is_running());
+}
+
+#[tokio::test(start_paused = true)]
+async fn extended_messages_use_their_cycle_time() {
+    let path = std::env::temp_dir().join(format!("cantool-cache-{}.dbc", std::process::id()));
+    let mut dbc = std::fs::read_to_string(common::sample_dbc_path()).unwrap();
+    dbc.push_str("BA_ \"GenMsgCycleTime\" BO_ 2566844926 100;\n");
+    std::fs::write(&path, dbc).unwrap();
+
+    let bus = SimBus::new();
+    let utils = CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&path),
+        Vec::new(),
+        common::no_retry(),
+        HealthMonitor::new(),
+    )
+    .await;
+    std::fs::remove_file(&path).unwrap();
+    let config = SignalCacheConfig { default_timeout: Duration::from_secs(1), cycle_time_factor: 2 };
+    let cache = SignalCache::spawn(utils.unwrap(), config);
+
+    bus.inject_data(ODOMETER_ID, &[0; 8]).unwrap();
+    advance(200).await;
+    assert!(!cache.get("TotalDistance").unwrap().stale);
+    advance(1).await;
+    assert!(cache.get("TotalDistance").unwrap().stale);
+}
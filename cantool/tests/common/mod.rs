@@ -0,0 +1,40 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use cantool::can_retry::{HealthMonitor, RetryPolicy};
+use cantool::can_sim::SimBus;
+use cantool::can_tool::CanUtils;
+use tokio::time::Duration;
+
+pub fn sample_dbc_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/sample.dbc")
+}
+
+/// Fails on the first error instead of backing off
+pub fn no_retry() -> RetryPolicy {
+    RetryPolicy { max_attempts: Some(1), ..RetryPolicy::default() }
+}
+
+/// Retries quickly, e.g. for `SimBus::fail_opens`
+pub fn fast_retry(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: Some(max_attempts),
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+        ..RetryPolicy::default()
+    }
+}
+
+pub async fn sim_utils(bus: &SimBus) -> CanUtils {
+    CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&sample_dbc_path()),
+        Vec::new(),
+        no_retry(),
+        HealthMonitor::new(),
+    )
+    .await
+    .expect("CanUtils on SimBus")
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+use cantool::can_dbc::{DbcInfo, Multiplex};
+use cantool::can_error::CanToolError;
+
+fn sample_dbc() -> DbcInfo {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sample.dbc");
+    DbcInfo::from_dbc_file(&path).expect("sample DBC")
+}
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+}
+
+struct Case {
+    message: &'static str,
+    data: [u8; 8],
+    /// (signal, raw, physical value)
+    expected: &'static [(&'static str, i64, f64)],
+    absent: &'static [&'static str],
+}
+
+const CASES: &[Case] = &[
+    // 32 bit counters beyond the f32 mantissa
+    Case {
+        message: "Odometer",
+        data: [0xFE, 0xFF, 0xFF, 0xFF, 0x4E, 0x61, 0xBC, 0x00],
+        expected: &[("TotalDistance", 4294967294, 4294967.294), ("Energy", 12345678, 1233567.8)],
+        absent: &[],
+    },
+    // Intel signed, Motorola unsigned and Motorola signed across a byte boundary
+    Case {
+        message: "Motor",
+        data: [0x2E, 0xFB, 0x12, 0x34, 0xF9, 0xC0, 0x00, 0x00],
+        expected: &[("Torque", -1234, -123.4), ("Speed", 0x1234, 2330.0), ("Current", -100, -25.0)],
+        absent: &[],
+    },
+    Case {
+        message: "Motor",
+        data: [0xFF, 0x7F, 0xFF, 0xFF, 0x7F, 0xF0, 0x00, 0x00],
+        expected: &[("Torque", 32767, 3276.7), ("Speed", 0xFFFF, 32767.5), ("Current", 2047, 511.75)],
+        absent: &[],
+    },
+    // multiplexor 0 selects Voltage, 1 selects Fault, other values none of them
+    Case {
+        message: "Diag",
+        data: [0x00, 0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0x05],
+        expected: &[("Page", 0, 0.0), ("Voltage", 10000, 100.0), ("Status", 5, 5.0)],
+        absent: &["Fault"],
+    },
+    Case {
+        message: "Diag",
+        data: [0x01, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06],
+        expected: &[("Page", 1, 1.0), ("Fault", 7, 7.0), ("Status", 6, 6.0)],
+        absent: &["Voltage"],
+    },
+    Case {
+        message: "Diag",
+        data: [0x02, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        expected: &[("Page", 2, 2.0), ("Status", 0, 0.0)],
+        absent: &["Voltage", "Fault"],
+    },
+];
+
+#[test]
+fn decodes_sample_messages() {
+    let dbc = sample_dbc();
+
+    for case in CASES {
+        let message = dbc.message(case.message).expect(case.message);
+        let decoded = message.decode(&case.data).expect(case.message);
+
+        assert_eq!(decoded.len(), case.expected.len(), "{} {:02X?}", case.message, case.data);
+        for (signal, raw, value) in case.expected {
+            let signal_value = decoded.get(*signal).unwrap_or_else(|| panic!("{} missing", signal));
+            assert_eq!(signal_value.raw, *raw, "{} raw", signal);
+            assert!(approx_eq(signal_value.value, *value), "{} = {}, expected {}", signal, signal_value.value, value);
+        }
+        for signal in case.absent {
+            assert!(!decoded.contains_key(*signal), "{} must not be decoded", signal);
+        }
+    }
+}
+
+#[test]
+fn encodes_what_it_decodes() {
+    let dbc = sample_dbc();
+
+    for case in CASES {
+        let message = dbc.message(case.message).unwrap();
+        let mut data = [0u8; 8];
+        for (signal, _, value) in case.expected {
+            dbc.signal(signal).unwrap().layout.encode(*value, &mut data).unwrap();
+        }
+
+        assert_eq!(message.decode(&data).unwrap(), message.decode(&case.data).unwrap(), "{}", case.message);
+    }
+}
+
+#[test]
+fn keeps_64_bit_raw_values() {
+    let dbc = sample_dbc();
+    let decoded = dbc.message("Ticks").unwrap().decode(&[0xFF; 8]).unwrap();
+
+    assert_eq!(decoded["TickCount"].raw as u64, u64::MAX);
+    assert!(approx_eq(decoded["TickCount"].value, u64::MAX as f64));
+}
+
+#[test]
+fn rejects_short_payloads() {
+    let dbc = sample_dbc();
+    let result = dbc.message("Motor").unwrap().decode(&[0x2E, 0xFB]);
+
+    assert!(matches!(result, Err(CanToolError::DecodeFailed(signal)) if signal == "Speed"));
+}
+
+#[test]
+fn parses_signal_definitions() {
+    let dbc = sample_dbc();
+
+    let speed = dbc.signal("Speed").unwrap();
+    assert!(!speed.layout.little_endian && !speed.layout.signed);
+    assert_eq!((speed.layout.start_bit, speed.layout.bit_len), (23, 16));
+    assert_eq!((speed.min, speed.max, speed.unit.as_str()), (0.0, 32767.5, "rpm"));
+
+    let torque = dbc.signal("Torque").unwrap();
+    assert!(torque.layout.little_endian && torque.layout.signed);
+    assert_eq!(torque.layout.scale, 0.1);
+
+    assert_eq!(dbc.signal("Page").unwrap().multiplex, Some(Multiplex::Multiplexor));
+    assert_eq!(dbc.signal("Fault").unwrap().multiplex, Some(Multiplex::Multiplexed(1)));
+    assert_eq!(dbc.signal("Status").unwrap().multiplex, None);
+    assert_eq!(dbc.value_text(512, "Page", 1), Some("FAULTS"));
+}
@@ -0,0 +1,90 @@
+use cantool::can_e2e::ChecksumAlgorithm;
+
+const CHECK: &[u8] = b"123456789";
+
+/// Test data of the AUTOSAR CRC library specification with the SAE J1850 and 8H2F results
+const AUTOSAR_CRC8: &[(&[u8], u8, u8)] = &[
+    (&[0x00, 0x00, 0x00, 0x00], 0x59, 0x12),
+    (&[0xF2, 0x01, 0x83], 0x37, 0xC2),
+    (&[0x0F, 0xAA, 0x00, 0x55], 0x79, 0xC6),
+    (&[0x00, 0xFF, 0x55, 0x11], 0xB8, 0x77),
+    (&[0x33, 0x22, 0x55, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], 0xCB, 0x11),
+    (&[0x92, 0x6B, 0x55], 0x8C, 0x33),
+    (&[0xFF, 0xFF, 0xFF, 0xFF], 0x74, 0x6C),
+];
+
+/// CRC-8H2F through profile 2, whose data ID byte is appended after the payload
+fn crc8h2f(data: &[u8]) -> u8 {
+    let (last, payload) = data.split_last().unwrap();
+    ChecksumAlgorithm::AutosarP2 { data_ids: [*last; 16] }.compute(payload, &[], 0)
+}
+
+#[test]
+fn crc8_sae_j1850_known_answers() {
+    assert_eq!(ChecksumAlgorithm::Crc8SaeJ1850.compute(CHECK, &[], 0), 0x4B);
+    for (data, expected, _) in AUTOSAR_CRC8 {
+        assert_eq!(ChecksumAlgorithm::Crc8SaeJ1850.compute(data, &[], 0), *expected, "{:02x?}", data);
+    }
+}
+
+#[test]
+fn crc8h2f_known_answers() {
+    assert_eq!(crc8h2f(CHECK), 0xDF);
+    for (data, _, expected) in AUTOSAR_CRC8 {
+        assert_eq!(crc8h2f(data), *expected, "{:02x?}", data);
+    }
+}
+
+/// Bit serial CRC-8 without final XOR, feeds each message bit into the register MSB first
+fn bitwise_crc8(poly: u8, init: u8, bytes: &[u8]) -> u8 {
+    let mut crc = init;
+    for byte in bytes {
+        for bit in (0..8).rev() {
+            let feedback = (crc >> 7) ^ ((byte >> bit) & 1);
+            crc <<= 1;
+            if feedback != 0 {
+                crc ^= poly;
+            }
+        }
+    }
+    crc
+}
+
+#[test]
+fn bitwise_crc8_matches_the_specification() {
+    for (data, j1850, h2f) in AUTOSAR_CRC8 {
+        assert_eq!(bitwise_crc8(0x1D, 0xFF, data) ^ 0xFF, *j1850, "{:02X?}", data);
+        assert_eq!(bitwise_crc8(0x2F, 0xFF, data) ^ 0xFF, *h2f, "{:02X?}", data);
+    }
+}
+
+#[test]
+fn autosar_profile_1_covers_both_data_id_bytes() {
+    // CRC in byte 0, counter 1 in the low nibble of byte 1, data ID 0x123 low byte first
+    let frame = [0x00, 0x01, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+    let expected = bitwise_crc8(0x1D, 0x00, &[&[0x23, 0x01], &frame[1..]].concat());
+    let algorithm = ChecksumAlgorithm::AutosarP1 { data_id: 0x123 };
+    assert_eq!(algorithm.compute(&frame, &[0], 1), expected);
+
+    // the data ID is part of the CRC
+    let other = ChecksumAlgorithm::AutosarP1 { data_id: 0x124 };
+    assert_ne!(other.compute(&frame, &[0], 1), expected);
+}
+
+#[test]
+fn autosar_profile_2_appends_the_data_id_of_the_counter() {
+    // CRC in byte 0, counter 5 selects data ID 0x55 of the list
+    let frame = [0x00, 0x05, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60];
+    let data_ids: [u8; 16] = std::array::from_fn(|i| (i as u8) * 0x11);
+    let expected = bitwise_crc8(0x2F, 0xFF, &[&frame[1..], &[0x55]].concat()) ^ 0xFF;
+    let algorithm = ChecksumAlgorithm::AutosarP2 { data_ids };
+    assert_eq!(algorithm.compute(&frame, &[0], 5), expected);
+
+    // another counter selects another data ID
+    assert_ne!(algorithm.compute(&frame, &[0], 6), expected);
+}
+
+#[test]
+fn xor_skips_the_checksum_byte() {
+    assert_eq!(ChecksumAlgorithm::Xor.compute(&[0xFF, 0x01, 0x02, 0x04], &[0], 0), 0x07);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::collections::HashMap;
+use cantool::can_codec::SignalLayout;
+use cantool::can_sim::SimBus;
+
+fn layout(start_bit: usize, bit_len: usize, little_endian: bool, signed: bool, scale: f64, offset: f64) -> SignalLayout {
+    SignalLayout { start_bit, bit_len, little_endian, signed, scale, offset }
+}
+
+struct Case {
+    name: &'static str,
+    layout: SignalLayout,
+    value: f64,
+    data: [u8; 8],
+    /// Value read back, differs from `value` when clamped
+    decoded: f64,
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case { name: "intel unsigned", layout: layout(8, 16, true, false, 0.5, 0.0), value: 1000.0, data: [0, 0xD0, 0x07, 0, 0, 0, 0, 0], decoded: 1000.0 },
+        Case { name: "intel signed", layout: layout(0, 16, true, true, 0.1, 0.0), value: -123.4, data: [0x2E, 0xFB, 0, 0, 0, 0, 0, 0], decoded: -123.4 },
+        Case { name: "intel offset", layout: layout(4, 8, true, false, 1.0, -40.0), value: 25.0, data: [0x10, 0x04, 0, 0, 0, 0, 0, 0], decoded: 25.0 },
+        Case { name: "motorola unsigned", layout: layout(23, 16, false, false, 0.5, 0.0), value: 2330.0, data: [0, 0, 0x12, 0x34, 0, 0, 0, 0], decoded: 2330.0 },
+        Case { name: "motorola signed", layout: layout(39, 12, false, true, 0.25, 0.0), value: -25.0, data: [0, 0, 0, 0, 0xF9, 0xC0, 0, 0], decoded: -25.0 },
+        Case { name: "unsigned clamped high", layout: layout(0, 8, true, false, 1.0, 0.0), value: 300.0, data: [0xFF, 0, 0, 0, 0, 0, 0, 0], decoded: 255.0 },
+        Case { name: "unsigned clamped low", layout: layout(0, 8, true, false, 1.0, 0.0), value: -5.0, data: [0; 8], decoded: 0.0 },
+        Case { name: "signed clamped high", layout: layout(0, 8, true, true, 1.0, 0.0), value: 200.0, data: [0x7F, 0, 0, 0, 0, 0, 0, 0], decoded: 127.0 },
+        Case { name: "signed clamped low", layout: layout(0, 8, true, true, 1.0, 0.0), value: -200.0, data: [0x80, 0, 0, 0, 0, 0, 0, 0], decoded: -128.0 },
+        Case { name: "motorola clamped", layout: layout(7, 4, false, true, 1.0, 0.0), value: -9.0, data: [0x80, 0, 0, 0, 0, 0, 0, 0], decoded: -8.0 },
+    ]
+}
+
+#[test]
+fn encodes_and_decodes_layouts() {
+    for case in cases() {
+        let mut data = [0u8; 8];
+        case.layout.encode(case.value, &mut data).unwrap();
+        assert_eq!(data, case.data, "{}", case.name);
+
+        let decoded = case.layout.decode(&data).unwrap();
+        assert!((decoded - case.decoded).abs() < 1e-9, "{}: {} != {}", case.name, decoded, case.decoded);
+    }
+}
+
+#[test]
+fn encoding_keeps_other_bits() {
+    let mut data = [0xFF; 8];
+    layout(8, 16, true, false, 1.0, 0.0).encode(0.0, &mut data).unwrap();
+    assert_eq!(data, [0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn rejects_unencodable_layouts() {
+    let mut data = [0u8; 2];
+    assert!(layout(8, 16, true, false, 1.0, 0.0).encode(1.0, &mut data).is_err());
+
+    let empty = layout(0, 0, true, true, 1.0, 0.0);
+    assert_eq!(empty.to_raw(5.0), 0);
+    assert!(empty.encode(5.0, &mut [0u8; 8]).is_err());
+}
+
+#[tokio::test]
+async fn encoded_frames_keep_the_dbc_frame_format() {
+    let bus = SimBus::new();
+    let can_utils = common::sim_utils(&bus).await;
+
+    let heartbeat = can_utils.encode_message("Heartbeat", &HashMap::from([("Alive".to_string(), 7.0)])).unwrap();
+    assert_eq!((heartbeat.id, heartbeat.extended), (0x311, true));
+    assert_eq!(heartbeat.data, vec![7, 0]);
+
+    let motor = can_utils.encode_message("Motor", &HashMap::from([("Torque".to_string(), -123.4)])).unwrap();
+    assert_eq!((motor.id, motor.extended), (0x100, false));
+    assert_eq!(&motor.data[..2], &[0x2E, 0xFB]);
+
+    // an extended ID below 0x800 stays extended on the classic socket
+    can_utils.send_frame(&heartbeat).await.unwrap();
+    can_utils.send_frame(&motor).await.unwrap();
+    let transmitted = bus.take_transmitted();
+    assert_eq!(transmitted.len(), 2);
+    assert!(transmitted[0].is_extended());
+    assert_eq!((transmitted[0].id(), transmitted[0].data()), (0x311, &[7, 0][..]));
+    assert!(!transmitted[1].is_extended());
+    assert_eq!(transmitted[1].id(), 0x100);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+use cantool::can_error::CanToolError;
+use cantool::can_filter::FrameFilter;
+use cantool::can_retry::HealthMonitor;
+use cantool::can_sim::SimBus;
+use cantool::can_tool::CanUtils;
+use tokio::time::Duration;
+
+async fn filtered_utils(bus: &SimBus, filters: Vec<FrameFilter>) -> Result<CanUtils, CanToolError> {
+    CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&common::sample_dbc_path()),
+        filters,
+        common::no_retry(),
+        HealthMonitor::new(),
+    )
+    .await
+}
+
+/// IDs received after injecting `ids`, a short timeout ends the read
+async fn received(utils: &mut CanUtils, bus: &SimBus, ids: &[u32]) -> Vec<u32> {
+    for id in ids {
+        bus.inject_data(*id, &[0; 8]).unwrap();
+    }
+
+    let mut received = Vec::new();
+    while let Ok(frame) = utils.recv_frame(Duration::from_millis(20)).await {
+        received.push(frame.id);
+    }
+    received
+}
+
+#[tokio::test]
+async fn filters_select_dbc_frames() {
+    let bus = SimBus::new();
+    let filters = vec![FrameFilter::Message("Motor".to_string()), FrameFilter::Id(0x300)];
+    let mut utils = filtered_utils(&bus, filters).await.unwrap();
+
+    assert_eq!(received(&mut utils, &bus, &[0x100, 0x200, 0x300, 0x400]).await, vec![0x100, 0x300]);
+}
+
+#[tokio::test]
+async fn inverted_filters_exclude_every_entry() {
+    let bus = SimBus::new();
+    let filters = vec![
+        FrameFilter::Message("Motor".to_string()).inverted(),
+        FrameFilter::Message("Diag".to_string()).inverted(),
+    ];
+    let mut utils = filtered_utils(&bus, filters).await.unwrap();
+
+    assert_eq!(received(&mut utils, &bus, &[0x100, 0x200, 0x300, 0x400]).await, vec![0x300, 0x400]);
+}
+
+#[tokio::test]
+async fn inverted_and_plain_filters_are_rejected() {
+    let bus = SimBus::new();
+    let filters = vec![FrameFilter::Message("Motor".to_string()).inverted(), FrameFilter::Id(0x300)];
+
+    let result = filtered_utils(&bus, filters).await;
+    assert!(matches!(result, Err(CanToolError::InvalidConfig(_))));
+
+    let mut utils = filtered_utils(&bus, Vec::new()).await.unwrap();
+    let result = utils.set_filters(vec![FrameFilter::Id(0x300), FrameFilter::Pgn(0xFECA).inverted()]);
+    assert!(matches!(result, Err(CanToolError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn clearing_filters_receives_everything() {
+    let bus = SimBus::new();
+    let mut utils = filtered_utils(&bus, vec![FrameFilter::Id(0x300)]).await.unwrap();
+    assert_eq!(received(&mut utils, &bus, &[0x100, 0x300]).await, vec![0x300]);
+
+    utils.set_filters(Vec::new()).unwrap();
+    assert_eq!(received(&mut utils, &bus, &[0x100, 0x300]).await, vec![0x100, 0x300]);
+
+    let mut unfiltered = filtered_utils(&bus, Vec::new()).await.unwrap();
+    assert_eq!(received(&mut unfiltered, &bus, &[0x200]).await, vec![0x200]);
+}
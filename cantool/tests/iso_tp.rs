@@ -0,0 +1,164 @@
+use cantool::iso_tp::{
+    decode_st_min, encode_st_min, parse_flow_control, FlowStatus, IsoTpConfig, IsoTpReceiver, IsoTpSender, RxEvent,
+    TxStep,
+};
+use tokio::time::Duration;
+
+fn config() -> IsoTpConfig {
+    IsoTpConfig::new(0x7E0, 0x7E8)
+}
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+#[test]
+fn single_frame() {
+    let config = config();
+    let mut sender = IsoTpSender::new(vec![0x22, 0xF1, 0x90]).unwrap();
+    let frame = sender.first_frame(&config);
+    assert_eq!(frame.data, vec![0x03, 0x22, 0xF1, 0x90, 0xCC, 0xCC, 0xCC, 0xCC]);
+    assert_eq!(sender.next_step(&config), TxStep::Done);
+
+    let mut receiver = IsoTpReceiver::new();
+    assert_eq!(receiver.on_frame(&config, &frame.data).unwrap(), RxEvent::Complete(vec![0x22, 0xF1, 0x90]));
+    assert!(!receiver.is_active());
+
+    assert!(receiver.on_frame(&config, &[0x00, 0xCC]).is_err());
+    assert!(receiver.on_frame(&config, &[0x07, 0x01]).is_err());
+}
+
+#[test]
+fn multi_frame_with_block_size_and_st_min() {
+    let mut rx_config = config();
+    rx_config.block_size = 2;
+    rx_config.st_min = Duration::from_millis(5);
+    let tx_config = config();
+
+    let data = payload(40);
+    let mut sender = IsoTpSender::new(data.clone()).unwrap();
+    let mut receiver = IsoTpReceiver::new();
+
+    let first = sender.first_frame(&tx_config);
+    assert_eq!(&first.data[..2], &[0x10, 40]);
+    let RxEvent::InProgress(Some(flow_control)) = receiver.on_frame(&rx_config, &first.data).unwrap() else {
+        panic!("first frame not acknowledged");
+    };
+    assert_eq!(&flow_control.data[..3], &[0x30, 2, 5]);
+
+    let mut flow_controls = 1;
+    let mut sequence = Vec::new();
+    let mut pending = Some(flow_control);
+    let received = loop {
+        match sender.next_step(&tx_config) {
+            TxStep::AwaitFlowControl => {
+                let flow_control = pending.take().expect("sender waits without a flow control");
+                sender.on_flow_control(&flow_control.data).unwrap();
+            }
+            TxStep::Send(frame, st_min) => {
+                assert_eq!(st_min, Duration::from_millis(5));
+                sequence.push(frame.data[0]);
+                match receiver.on_frame(&rx_config, &frame.data).unwrap() {
+                    RxEvent::InProgress(Some(flow_control)) => {
+                        flow_controls += 1;
+                        pending = Some(flow_control);
+                    }
+                    RxEvent::InProgress(None) => {}
+                    RxEvent::Complete(received) => break received,
+                    RxEvent::Ignored => panic!("consecutive frame ignored"),
+                }
+            }
+            TxStep::Done => panic!("sender done before the receiver"),
+        }
+    };
+
+    assert_eq!(received, data);
+    // 6 bytes in the first frame, 34 in five consecutive frames, a flow control every 2 of them
+    assert_eq!(sequence, vec![0x21, 0x22, 0x23, 0x24, 0x25]);
+    assert_eq!(flow_controls, 3);
+}
+
+#[test]
+fn sequence_numbers_wrap() {
+    let config = config();
+    let data = payload(200);
+    let mut sender = IsoTpSender::new(data.clone()).unwrap();
+    let mut receiver = IsoTpReceiver::new();
+
+    receiver.on_frame(&config, &sender.first_frame(&config).data).unwrap();
+    sender.on_flow_control(&[0x30, 0, 0]).unwrap();
+
+    let mut last = None;
+    while let TxStep::Send(frame, _) = sender.next_step(&config) {
+        last = Some(receiver.on_frame(&config, &frame.data).unwrap());
+    }
+    assert_eq!(last, Some(RxEvent::Complete(data)));
+}
+
+#[test]
+fn wrong_sequence_number_aborts() {
+    let config = config();
+    let mut receiver = IsoTpReceiver::new();
+
+    receiver.on_frame(&config, &[0x10, 20, 0, 1, 2, 3, 4, 5]).unwrap();
+    assert!(receiver.is_active());
+    assert!(receiver.on_frame(&config, &[0x22, 6, 7, 8, 9, 10, 11, 12]).is_err());
+    assert!(!receiver.is_active());
+
+    // without a transfer consecutive frames are ignored
+    assert_eq!(receiver.on_frame(&config, &[0x21, 6, 7, 8, 9, 10, 11, 12]).unwrap(), RxEvent::Ignored);
+}
+
+#[test]
+fn oversized_transfer_is_refused() {
+    let mut config = config();
+    config.max_rx_len = 100;
+    let mut receiver = IsoTpReceiver::new();
+
+    let RxEvent::InProgress(Some(flow_control)) = receiver.on_frame(&config, &[0x10, 101, 0, 1, 2, 3, 4, 5]).unwrap() else {
+        panic!("no overflow flow control");
+    };
+    assert_eq!(parse_flow_control(&flow_control.data), Some(FlowStatus::Overflow));
+    assert!(!receiver.is_active());
+
+    let mut sender = IsoTpSender::new(payload(101)).unwrap();
+    sender.first_frame(&config);
+    assert!(sender.on_flow_control(&flow_control.data).is_err());
+}
+
+#[test]
+fn malformed_first_frames_are_rejected() {
+    let config = config();
+    let mut receiver = IsoTpReceiver::new();
+
+    // lengths that fit a single frame
+    for len in [1u8, 6, 7] {
+        assert!(receiver.on_frame(&config, &[0x10, len, 0, 1, 2, 3, 4, 5]).is_err());
+        assert!(!receiver.is_active());
+    }
+    // escape sequence with a length that fits 12 bits
+    assert!(receiver.on_frame(&config, &[0x10, 0x00, 0, 0, 0x0F, 0xFF, 0, 1]).is_err());
+    assert!(receiver.on_frame(&config, &[0x10, 0x00, 0, 0, 0, 2, 0, 1]).is_err());
+    assert!(!receiver.is_active());
+    // a consecutive frame after a rejected first frame is ignored
+    assert_eq!(receiver.on_frame(&config, &[0x21, 0, 1, 2, 3, 4, 5, 6]).unwrap(), RxEvent::Ignored);
+    // truncated first frame
+    assert!(receiver.on_frame(&config, &[0x10, 20, 0, 1]).is_err());
+
+    let mut config = config;
+    config.max_rx_len = 5000;
+    let frame = [0x10, 0x00, 0, 0, 0x10, 0x00, 0, 1];
+    assert!(matches!(receiver.on_frame(&config, &frame).unwrap(), RxEvent::InProgress(Some(_))));
+}
+
+#[test]
+fn st_min_encoding() {
+    assert_eq!(encode_st_min(Duration::ZERO), 0x00);
+    assert_eq!(encode_st_min(Duration::from_micros(300)), 0xF3);
+    assert_eq!(encode_st_min(Duration::from_millis(20)), 20);
+    assert_eq!(encode_st_min(Duration::from_secs(1)), 0x7F);
+
+    assert_eq!(decode_st_min(0xF5), Duration::from_micros(500));
+    assert_eq!(decode_st_min(0x0A), Duration::from_millis(10));
+    assert_eq!(decode_st_min(0xFA), Duration::from_millis(127));
+}
@@ -0,0 +1,45 @@
+use cantool::j1939_dm::{decode_dm, ActiveFaultTracker, LampState, PGN_DM1};
+use cantool::j1939_tp::J1939Message;
+
+/// Lamp byte with the malfunction indicator and the amber warning lamp on
+const LAMPS: u8 = 0x44;
+
+#[test]
+fn dm1_dtcs_use_conversion_method_4() {
+    // SPN 100 FMI 1 OC 5, SPN 0x7FDFE FMI 12 OC 127 with the CM bit set
+    let data = [LAMPS, 0xFF, 0x64, 0x00, 0x01, 0x05, 0xFE, 0xFD, 0xEC, 0xFF];
+    let dm1 = decode_dm(&data).unwrap();
+
+    assert_eq!(dm1.lamp_status.malfunction_indicator, LampState::On);
+    assert_eq!(dm1.lamp_status.red_stop, LampState::Off);
+    assert_eq!(dm1.lamp_status.amber_warning, LampState::On);
+    assert_eq!(dm1.lamp_status.protect, LampState::Off);
+
+    let dtcs: Vec<(u32, u8, u8)> = dm1.dtcs.iter().map(|dtc| (dtc.spn, dtc.fmi, dtc.occurrence_count)).collect();
+    assert_eq!(dtcs, vec![(100, 1, 5), (0x7FDFE, 12, 127)]);
+}
+
+#[test]
+fn dm1_without_faults_has_no_dtcs() {
+    let dm1 = decode_dm(&[0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF]).unwrap();
+    assert!(dm1.dtcs.is_empty());
+    assert_eq!(dm1.lamp_status.malfunction_indicator, LampState::Off);
+
+    assert!(decode_dm(&[0x00, 0xFF, 0x00]).is_err());
+}
+
+#[test]
+fn tracker_reports_raised_and_cleared_dtcs() {
+    let mut tracker = ActiveFaultTracker::new();
+    let dm1 = |data: Vec<u8>| J1939Message::new(PGN_DM1, 0x00, 0xFF, data);
+
+    let changes = tracker.handle_message(&dm1(vec![LAMPS, 0xFF, 0x64, 0x00, 0x01, 0x05, 0xFF, 0xFF])).unwrap();
+    assert_eq!(changes.raised.len(), 1);
+    assert!(changes.cleared.is_empty());
+    assert_eq!(tracker.active(0x00)[0].spn, 100);
+
+    let changes = tracker.handle_message(&dm1(vec![0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF])).unwrap();
+    assert!(changes.raised.is_empty());
+    assert_eq!(changes.cleared[0].spn, 100);
+    assert!(tracker.active(0x00).is_empty());
+}
@@ -0,0 +1,165 @@
+use cantool::j1939_tp::{J1939Id, TpEvent, TpReassembler, GLOBAL_ADDRESS, PGN_TP_CM, PGN_TP_DT};
+use tokio::time::{Duration, Instant};
+
+const PGN: u32 = 0xFECA;
+const SENDER: u8 = 0x00;
+const US: u8 = 0xF9;
+
+fn can_id(pgn: u32, source: u8, destination: u8) -> u32 {
+    J1939Id { priority: 7, pgn, source, destination }.to_can_id()
+}
+
+fn cm(control: u8, size: u16, packets: u8, b4: u8, destination: u8) -> (u32, [u8; 8]) {
+    let [lo, hi] = size.to_le_bytes();
+    (can_id(PGN_TP_CM, SENDER, destination), [control, lo, hi, packets, b4, 0xCA, 0xFE, 0x00])
+}
+
+fn dt(seq: u8, destination: u8) -> (u32, [u8; 8]) {
+    // every packet repeats its sequence number as payload
+    (can_id(PGN_TP_DT, SENDER, destination), [seq; 8])
+}
+
+/// Payload the `dt` packets carry for `size` bytes
+fn payload(size: usize) -> Vec<u8> {
+    (1..=size.div_ceil(7) as u8).flat_map(|seq| [seq; 7]).take(size).collect()
+}
+
+fn feed(tp: &mut TpReassembler, frame: (u32, [u8; 8]), now: Instant) -> Vec<TpEvent> {
+    tp.handle_frame(frame.0, &frame.1, now)
+}
+
+/// Control byte and destination of every frame the reassembler wants to send
+fn sent(events: &[TpEvent]) -> Vec<(u8, u8)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            TpEvent::Send(frame) => Some((frame.data[0], J1939Id::from_can_id(frame.id).destination)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn bam_is_reassembled() {
+    let mut tp = TpReassembler::new(None);
+    let now = Instant::now();
+
+    assert!(feed(&mut tp, cm(32, 17, 3, 0xFF, GLOBAL_ADDRESS), now).is_empty());
+    assert!(feed(&mut tp, dt(1, GLOBAL_ADDRESS), now).is_empty());
+    assert!(feed(&mut tp, dt(2, GLOBAL_ADDRESS), now).is_empty());
+
+    match feed(&mut tp, dt(3, GLOBAL_ADDRESS), now).as_slice() {
+        [TpEvent::Complete(message)] => {
+            assert_eq!(message.pgn, PGN);
+            assert_eq!(message.source, SENDER);
+            assert_eq!(message.destination, GLOBAL_ADDRESS);
+            assert_eq!(message.data, payload(17));
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn bam_with_inconsistent_size_is_ignored() {
+    let mut tp = TpReassembler::new(None);
+    let now = Instant::now();
+
+    // 1785 bytes announced in 2 packets
+    assert!(feed(&mut tp, cm(32, 1785, 2, 0xFF, GLOBAL_ADDRESS), now).is_empty());
+    assert!(feed(&mut tp, dt(1, GLOBAL_ADDRESS), now).is_empty());
+    assert!(feed(&mut tp, dt(2, GLOBAL_ADDRESS), now).is_empty());
+
+    // larger than the protocol allows
+    assert!(feed(&mut tp, cm(32, 1786, 0, 0xFF, GLOBAL_ADDRESS), now).is_empty());
+    assert!(feed(&mut tp, dt(1, GLOBAL_ADDRESS), now).is_empty());
+}
+
+#[test]
+fn rts_cts_session_is_acknowledged() {
+    let mut tp = TpReassembler::new(Some(US));
+    let now = Instant::now();
+
+    // 20 bytes in 3 packets, at most 2 packets per CTS
+    let events = feed(&mut tp, cm(16, 20, 3, 2, US), now);
+    assert_eq!(sent(&events), vec![(17, SENDER)]);
+    let TpEvent::Send(cts) = &events[0] else { unreachable!() };
+    assert_eq!(&cts.data[1..3], &[2, 1]);
+
+    assert!(feed(&mut tp, dt(1, US), now).is_empty());
+    let events = feed(&mut tp, dt(2, US), now);
+    assert_eq!(sent(&events), vec![(17, SENDER)]);
+    let TpEvent::Send(cts) = &events[0] else { unreachable!() };
+    assert_eq!(&cts.data[1..3], &[1, 3]);
+
+    let events = feed(&mut tp, dt(3, US), now);
+    assert_eq!(sent(&events), vec![(19, SENDER)]);
+    match events.last() {
+        Some(TpEvent::Complete(message)) => {
+            assert_eq!(message.destination, US);
+            assert_eq!(message.data, payload(20));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn rts_with_inconsistent_size_is_aborted() {
+    let mut tp = TpReassembler::new(Some(US));
+
+    let events = feed(&mut tp, cm(16, 1785, 2, 0xFF, US), Instant::now());
+    assert_eq!(sent(&events), vec![(255, SENDER)]);
+}
+
+#[test]
+fn rts_to_another_node_is_ignored() {
+    let mut tp = TpReassembler::new(Some(US));
+
+    assert!(feed(&mut tp, cm(16, 20, 3, 2, 0x17), Instant::now()).is_empty());
+}
+
+#[test]
+fn peer_abort_ends_the_session() {
+    let mut tp = TpReassembler::new(Some(US));
+    let now = Instant::now();
+
+    feed(&mut tp, cm(16, 20, 3, 0xFF, US), now);
+    let events = feed(&mut tp, cm(255, 0xFFFF, 0xFF, 0xFF, US), now);
+    assert_eq!(events, vec![TpEvent::Aborted { pgn: PGN, source: SENDER }]);
+
+    // the session is gone, further data is ignored
+    assert!(feed(&mut tp, dt(1, US), now).is_empty());
+}
+
+#[test]
+fn stalled_sessions_time_out() {
+    let mut tp = TpReassembler::new(Some(US));
+    let now = Instant::now();
+
+    feed(&mut tp, cm(16, 20, 3, 0xFF, US), now);
+    feed(&mut tp, cm(32, 17, 3, 0xFF, GLOBAL_ADDRESS), now);
+    assert!(tp.expire(now + Duration::from_millis(700)).is_empty());
+
+    let events = tp.expire(now + Duration::from_millis(1300));
+    // only the RTS/CTS peer is told, both sessions are reported
+    assert_eq!(sent(&events), vec![(255, SENDER)]);
+    let aborted = events.iter().filter(|event| matches!(event, TpEvent::Aborted { pgn: PGN, source: SENDER })).count();
+    assert_eq!(aborted, 2);
+    assert!(tp.expire(now + Duration::from_secs(10)).is_empty());
+}
+
+#[test]
+fn out_of_order_packet_aborts() {
+    let mut tp = TpReassembler::new(Some(US));
+    let now = Instant::now();
+
+    feed(&mut tp, cm(16, 20, 3, 0xFF, US), now);
+    feed(&mut tp, dt(1, US), now);
+    let events = feed(&mut tp, dt(3, US), now);
+    assert_eq!(sent(&events), vec![(255, SENDER)]);
+    assert_eq!(events.last(), Some(&TpEvent::Aborted { pgn: PGN, source: SENDER }));
+
+    // a broadcast is dropped without an abort frame
+    feed(&mut tp, cm(32, 17, 3, 0xFF, GLOBAL_ADDRESS), now);
+    let events = feed(&mut tp, dt(2, GLOBAL_ADDRESS), now);
+    assert_eq!(events, vec![TpEvent::Aborted { pgn: PGN, source: SENDER }]);
+}
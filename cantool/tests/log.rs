@@ -0,0 +1,138 @@
+mod common;
+
+use std::io::Cursor;
+use std::path::Path;
+use cantool::can_frame::RawFrame;
+use cantool::can_log::{self, CanLogWriter, LogEntry, LogFormat};
+use cantool::can_sim::SimBus;
+use futures_util::StreamExt;
+
+fn data_path(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name)
+}
+
+fn entries() -> Vec<LogEntry> {
+    let frame = |id, extended, data: &[u8], fd, brs| RawFrame { id, extended, data: data.to_vec(), fd, brs };
+    vec![
+        LogEntry { timestamp: 1436509052.249713, ifname: "can0".into(), frame: frame(0x123, false, &[0xDE, 0xAD, 0xBE, 0xEF], false, false) },
+        LogEntry { timestamp: 1436509052.250001, ifname: "can0".into(), frame: frame(0x18FEF1FE, true, &[1, 2, 3, 4, 5, 6, 7, 8], false, false) },
+        LogEntry { timestamp: 1436509052.5, ifname: "can0".into(), frame: frame(0x7FF, false, &[], false, false) },
+        LogEntry { timestamp: 1436509053.0, ifname: "can0".into(), frame: frame(0x42, true, &[0xAA; 12], true, true) },
+        LogEntry { timestamp: 1436509054.75, ifname: "can0".into(), frame: frame(0x100, false, &[0x55; 64], true, false) },
+    ]
+}
+
+fn assert_same(read: &[LogEntry], written: &[LogEntry]) {
+    assert_eq!(read.len(), written.len());
+    for (read, written) in read.iter().zip(written) {
+        assert!((read.timestamp - written.timestamp).abs() < 2e-6, "{} != {}", read.timestamp, written.timestamp);
+        assert_eq!(read.frame, written.frame);
+    }
+}
+
+fn round_trip(format: LogFormat) -> (String, Vec<LogEntry>) {
+    let mut buffer = Vec::new();
+    let mut writer = CanLogWriter::new(&mut buffer, format).unwrap();
+    for entry in entries() {
+        writer.write_entry(&entry).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let text = String::from_utf8(buffer).unwrap();
+    let read = can_log::read_log(Cursor::new(text.as_bytes()), format, "can0").unwrap();
+    (text, read)
+}
+
+#[test]
+fn candump_round_trip() {
+    let (text, read) = round_trip(LogFormat::Candump);
+
+    assert!(text.starts_with("(1436509052.249713) can0 123#DEADBEEF\n"));
+    assert!(text.contains(" 00000042##1AAAA"));
+    assert_same(&read, &entries());
+}
+
+#[test]
+fn asc_round_trip_counts_from_the_measurement_start() {
+    let (text, read) = round_trip(LogFormat::Asc);
+
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "date Fri Jul 10 06:17:32.249 am 2015");
+    assert_eq!(lines[3], "Begin Triggerblock Fri Jul 10 06:17:32.249 am 2015");
+    assert!(lines[5].trim_start().starts_with("0.000713 1 123"), "{}", lines[5]);
+    assert_eq!(lines.last(), Some(&"End TriggerBlock"));
+    assert_same(&read, &entries());
+}
+
+#[test]
+fn reads_vector_asc_export() {
+    let entries = can_log::read_log_file(&data_path("vector.asc"), "can0").unwrap();
+    // Sat Sep 30 15:06:13.191 2017 UTC
+    let start = 1506783973.191;
+
+    let frames: Vec<(f64, u32, bool, usize, bool, bool)> = entries
+        .iter()
+        .map(|e| (e.timestamp - start, e.frame.id, e.frame.extended, e.frame.data.len(), e.frame.fd, e.frame.brs))
+        .collect();
+    let expected = [
+        (17.876708, 0x6F9, false, 8, false, false),
+        (20.105214, 0x18EBFF00, true, 8, false, false),
+        (30.005021, 0x300, false, 8, true, true),
+        (30.005071, 0x30A, false, 8, true, true),
+        (30.1, 0x1C4D80A7, true, 12, true, false),
+    ];
+
+    assert_eq!(frames.len(), expected.len());
+    for (frame, expected) in frames.iter().zip(expected.iter()) {
+        assert!((frame.0 - expected.0).abs() < 1e-6, "{:?} != {:?}", frame, expected);
+        assert_eq!(frame.1, expected.1);
+        assert_eq!((frame.2, frame.3, frame.4, frame.5), (expected.2, expected.3, expected.4, expected.5));
+    }
+    assert_eq!(entries[2].frame.data, vec![0x11, 0xC2, 0x03, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn reads_relative_asc_timestamps() {
+    let log = "base hex  timestamps relative\n   0.500000 1  123 Rx d 1 01\n   0.250000 1  123 Rx d 1 02\n";
+    let entries = can_log::read_log(Cursor::new(log), LogFormat::Asc, "can0").unwrap();
+
+    let timestamps: Vec<f64> = entries.iter().map(|entry| entry.timestamp).collect();
+    assert_eq!(timestamps, vec![0.5, 0.75]);
+}
+
+#[test]
+fn reads_candump_fixture() {
+    let entries = can_log::read_log_file(&data_path("candump.log"), "can0").unwrap();
+
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].ifname, "vcan0");
+    assert_eq!(entries[0].frame, RawFrame::new(0x123, &[0xDE, 0xAD, 0xBE, 0xEF]));
+    assert!(entries[1].frame.extended);
+    assert!(entries[2].frame.fd && entries[2].frame.brs && !entries[2].frame.extended);
+    assert_eq!(entries[2].frame.data.len(), 12);
+    // remote frame
+    assert!(entries[3].frame.data.is_empty());
+}
+
+#[test]
+fn skips_invalid_candump_lines() {
+    let log = "(1.0) can0 123#ABC\n(x) can0 123#00\n(2.0) can0 123#00\n";
+    let entries = can_log::read_log(Cursor::new(log), LogFormat::Candump, "can0").unwrap();
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].timestamp, 2.0);
+}
+
+#[tokio::test]
+async fn replayed_updates_carry_the_dbc_id() {
+    let bus = SimBus::new();
+    let utils = common::sim_utils(&bus).await;
+    let frame = |id, extended| RawFrame { id, extended, data: vec![0; 8], fd: false, brs: false };
+    let entries = vec![
+        LogEntry { timestamp: 1.0, ifname: "can0".into(), frame: frame(2566844926 & 0x1FFFFFFF, true) },
+        LogEntry { timestamp: 1.001, ifname: "can0".into(), frame: frame(0x100, false) },
+    ];
+
+    let ids: Vec<u32> = can_log::replay_signals(&utils, entries, 0.0).map(|update| update.frame_id).collect().await;
+    assert_eq!(ids, [2566844926, 0x100]);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use cantool::can_manager::{CanManager, InterfaceConfig};
+use cantool::can_sim::SimBus;
+use futures_util::StreamExt;
+use tokio::time::Duration;
+
+fn config() -> InterfaceConfig {
+    let mut config = InterfaceConfig::new("vcan0", Some(&common::sample_dbc_path()));
+    config.policy = common::no_retry();
+    config
+}
+
+#[tokio::test]
+async fn sending_does_not_need_the_stream_drained() {
+    let bus = SimBus::new();
+    let mut manager = CanManager::open_with_backend(Arc::new(bus.clone()), vec![config()]).await.unwrap();
+    assert_eq!(manager.route("Motor"), Some("vcan0"));
+
+    // more updates than the queue holds
+    for i in 0..1500u16 {
+        bus.inject_data(0x100, &[0, 0, 0, 0, i as u8, (i >> 8) as u8, 0, 0]).unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while manager.dropped_updates() < 1500 - 1024 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("updates dropped");
+
+    for _ in 0..3 {
+        let values = HashMap::from([("Torque".to_string(), 10.0)]);
+        tokio::time::timeout(Duration::from_secs(5), manager.send("Motor", values))
+            .await
+            .expect("send does not hang")
+            .unwrap();
+    }
+    let transmitted = bus.take_transmitted();
+    assert_eq!(transmitted.len(), 3);
+    assert!(transmitted.iter().all(|frame| frame.id() == 0x100));
+
+    // the queued updates are still delivered
+    let mut signals = manager.signal_stream();
+    let first = signals.next().await.unwrap();
+    assert_eq!(first.ifname, "vcan0");
+    assert_eq!(first.update.frame_id, 0x100);
+    drop(signals);
+    assert!(manager.is_running("vcan0"));
+}
+
+#[tokio::test]
+async fn receiving_continues_between_sends() {
+    let bus = SimBus::new();
+    let mut manager = CanManager::open_with_backend(Arc::new(bus.clone()), vec![config()]).await.unwrap();
+    let motor = |torque: f64| HashMap::from([("Torque".to_string(), torque)]);
+
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    manager.send("Motor", motor(1.0)).await.unwrap();
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    manager.send_on("vcan0", "Motor", motor(2.0)).await.unwrap();
+
+    let mut signals = manager.signal_stream();
+    for _ in 0..2 {
+        let update = tokio::time::timeout(Duration::from_secs(5), signals.next()).await.unwrap().unwrap();
+        assert_eq!(update.update.frame_id, 0x100);
+    }
+    drop(signals);
+
+    assert_eq!(bus.take_transmitted().len(), 2);
+    assert!(manager.send_on("vcan1", "Motor", motor(3.0)).await.is_err());
+    assert!(manager.send("NoSuchMessage", motor(3.0)).await.is_err());
+    assert_eq!(manager.dropped_updates(), 0);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use cantool::can_scheduler::{ChecksumSignal, CounterSignal, CyclicMessage, TxScheduler, ValueSource};
+use cantool::can_sim::SimBus;
+use tokio::time::Duration;
+
+/// Sum of all payload bytes, the checksum byte is zero while computing
+fn byte_sum(data: &[u8]) -> u64 {
+    data.iter().map(|byte| *byte as u64).sum::<u64>() & 0xFF
+}
+
+#[tokio::test(start_paused = true)]
+async fn cyclic_message_with_counter_and_checksum() {
+    let bus = SimBus::new();
+    let mut scheduler = TxScheduler::new(common::sim_utils(&bus).await);
+
+    let values = Arc::new(RwLock::new(HashMap::from([("BrakePressure".to_string(), 12.5)])));
+    let mut message = CyclicMessage::new("Brake", Duration::from_millis(10), ValueSource::Shared(values));
+    message.counter = Some(CounterSignal { signal: "BrakeCounter".to_string(), modulo: 16 });
+    message.checksum = Some(ChecksumSignal { signal: "BrakeChecksum".to_string(), checksum: Arc::new(byte_sum) });
+    scheduler.add(message).unwrap();
+
+    scheduler.start("Brake").unwrap();
+    // frames at 0, 10, ..., 170 ms
+    tokio::time::sleep(Duration::from_millis(175)).await;
+    scheduler.stop("Brake");
+
+    let frames = bus.take_transmitted();
+    assert_eq!(frames.len(), 18);
+
+    let counters: Vec<u8> = frames.iter().map(|frame| frame.data()[6] & 0x0F).collect();
+    let expected: Vec<u8> = (0..18).map(|i| (i % 16) as u8).collect();
+    assert_eq!(counters, expected);
+
+    for frame in frames.iter() {
+        let data = frame.data();
+        assert_eq!(frame.id(), 0x400);
+        assert_eq!(u16::from_le_bytes([data[0], data[1]]), 125);
+        let mut unprotected = data.to_vec();
+        unprotected[7] = 0;
+        assert_eq!(data[7] as u64, byte_sum(&unprotected));
+    }
+
+    let stats = scheduler.stats("Brake").unwrap();
+    assert_eq!(stats.sent, 18);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(stats.min_period, Some(Duration::from_millis(10)));
+    assert_eq!(stats.max_period, Some(Duration::from_millis(10)));
+}
+
+#[tokio::test]
+async fn unknown_counter_signal_is_rejected() {
+    let bus = SimBus::new();
+    let mut scheduler = TxScheduler::new(common::sim_utils(&bus).await);
+
+    let mut message = CyclicMessage::new("Brake", Duration::from_millis(10), ValueSource::Closure(Box::new(HashMap::new)));
+    message.counter = Some(CounterSignal { signal: "Torque".to_string(), modulo: 16 });
+    assert!(scheduler.add(message).is_err());
+
+    let message = CyclicMessage::new("Brake", Duration::ZERO, ValueSource::Closure(Box::new(HashMap::new)));
+    assert!(scheduler.add(message).is_err());
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use cantool::can_dbc::DbcInfo;
+use cantool::can_retry::HealthMonitor;
+use cantool::can_sim::SimBus;
+use cantool::can_tool::CanUtils;
+
+/// Two messages with a `Mode` signal of the same name and different value tables
+const SHARED_NAMES_DBC: &str = r#"VERSION ""
+
+BU_: ECU
+
+BO_ 100 Pump: 8 ECU
+ SG_ Mode : 0|8@1+ (1,0) [0|3] "" ECU
+
+BO_ 200 Fan: 8 ECU
+ SG_ Mode : 0|8@1+ (1,0) [0|3] "" ECU
+
+VAL_ 100 Mode 0 "OFF" 1 "ON" ;
+VAL_ 200 Mode 0 "IDLE" 1 "RUN" ;
+"#;
+
+#[tokio::test]
+async fn value_tables_belong_to_their_message() {
+    let dbc = DbcInfo::from_dbc_str(SHARED_NAMES_DBC);
+    assert_eq!(dbc.value_text(100, "Mode", 1), Some("ON"));
+    assert_eq!(dbc.value_text(200, "Mode", 1), Some("RUN"));
+    assert_eq!(dbc.value_text(300, "Mode", 1), None);
+
+    let path = std::env::temp_dir().join(format!("cantool-signal-{}.dbc", std::process::id()));
+    std::fs::write(&path, SHARED_NAMES_DBC).unwrap();
+    let utils = CanUtils::new_with_backend(
+        Arc::new(SimBus::new()),
+        "vcan0",
+        Some(&path),
+        Vec::new(),
+        common::no_retry(),
+        HealthMonitor::new(),
+    )
+    .await;
+    std::fs::remove_file(&path).unwrap();
+    let utils = utils.unwrap();
+
+    let signals = HashMap::from([("Mode".to_string(), 0.0)]);
+    assert_eq!(utils.describe_all(100, &signals)["Mode"].text.as_deref(), Some("OFF"));
+    assert_eq!(utils.describe_all(200, &signals)["Mode"].text.as_deref(), Some("IDLE"));
+    assert_eq!(utils.describe(200, "Mode", 1.0).unwrap().to_string(), "RUN");
+}
+
+#[tokio::test]
+async fn meta_comes_from_the_dbc_definition() {
+    let utils = common::sim_utils(&SimBus::new()).await;
+
+    let torque = utils.signal_meta(0x100, "Torque").unwrap();
+    assert_eq!(torque.unit, "Nm");
+    assert_eq!((torque.min, torque.max), (-3276.8, 3276.7));
+    assert_eq!((torque.scale, torque.offset), (0.1, 0.0));
+    assert!(torque.has_range());
+    assert_eq!(torque.raw_value(-123.4), -1234);
+    assert!(torque.value_table.is_none());
+
+    let page = utils.signal_meta(512, "Page").unwrap();
+    assert_eq!(page.value_table.unwrap()[&1], "FAULTS");
+    assert!(!utils.signal_meta(768, "TickCount").unwrap().has_range());
+    assert!(utils.signal_meta(0x100, "NoSuchSignal").is_none());
+}
+
+#[tokio::test]
+async fn values_are_described_with_range_and_text() {
+    let utils = common::sim_utils(&SimBus::new()).await;
+
+    let cases: &[(u32, &str, f64, bool, &str)] = &[
+        (0x100, "Torque", 12.5, false, "12.5 Nm"),
+        // the range bounds are part of it
+        (0x100, "Torque", 3276.7, false, "3276.7 Nm"),
+        (0x100, "Torque", 3276.8, true, "3276.8 Nm"),
+        (0x100, "Current", -512.25, true, "-512.25 A"),
+        (512, "Page", 0.0, false, "POWER"),
+        // no value table entry, no unit
+        (512, "Page", 7.0, false, "7"),
+        (512, "Page", 256.0, true, "256"),
+        // a [0|0] range is not specified
+        (768, "TickCount", 1e12, false, "1000000000000"),
+    ];
+
+    for (frame_id, signal, value, out_of_range, text) in cases {
+        let described = utils.describe(*frame_id, signal, *value).unwrap();
+        assert_eq!(described.out_of_range, *out_of_range, "{} {}", signal, value);
+        assert_eq!(described.to_string(), *text, "{} {}", signal, value);
+    }
+
+    let signals = HashMap::from([
+        ("Torque".to_string(), 5000.0),
+        ("Speed".to_string(), 100.0),
+        ("Virtual".to_string(), 1.0),
+    ]);
+    let described = utils.describe_all(0x100, &signals);
+    assert_eq!(described.len(), 2);
+    assert!(described["Torque"].out_of_range);
+    assert!(!described["Speed"].out_of_range);
+    assert_eq!((described["Speed"].min, described["Speed"].max), (0.0, 32767.5));
+}
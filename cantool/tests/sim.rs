@@ -0,0 +1,229 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use cantool::can_e2e::{ChecksumAlgorithm, E2eAction, E2eChecksum, E2eConfig};
+use cantool::can_error::CanToolError;
+use cantool::can_filter::FrameFilter;
+use cantool::can_retry::{ConnectionState, HealthMonitor};
+use cantool::can_sim::SimBus;
+use cantool::can_tool::CanUtils;
+use futures_util::StreamExt;
+use tokio::time::{Duration, Instant};
+
+/// Extended identifier of the Odometer message of the sample DBC
+const ODOMETER_ID: u32 = 2566844926 & 0x1FFFFFFF;
+const ODOMETER_DATA: [u8; 8] = [0xFE, 0xFF, 0xFF, 0xFF, 0x4E, 0x61, 0xBC, 0x00];
+
+#[tokio::test]
+async fn configuration_errors_are_not_retried() {
+    let bus = SimBus::new();
+    let health = HealthMonitor::new();
+    let result = CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&common::sample_dbc_path()),
+        vec![FrameFilter::Signal("NoSuchSignal".to_string())],
+        common::fast_retry(5),
+        health.clone(),
+    )
+    .await;
+    assert!(matches!(result, Err(CanToolError::SignalNotInDbc(_))));
+    assert_eq!(health.status().attempt, 1);
+    assert_eq!(bus.sockets(), 0);
+}
+
+#[tokio::test]
+async fn closed_and_fd_disabled_are_not_retried() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+
+    let values = HashMap::from([("Torque".to_string(), 1.0)]);
+    let result = utils.encode_and_send_fd("Motor", values, true).await;
+    assert!(matches!(result, Err(CanToolError::FdDisabled)));
+    assert!(!CanToolError::FdDisabled.is_retryable());
+    assert!(!CanToolError::Closed.is_retryable());
+}
+
+#[tokio::test]
+async fn missing_dbc_is_retried() {
+    let bus = SimBus::new();
+    let health = HealthMonitor::new();
+    let missing = common::sample_dbc_path().with_file_name("missing.dbc");
+
+    let result = CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&missing),
+        Vec::new(),
+        common::fast_retry(5),
+        health.clone(),
+    )
+    .await;
+    assert!(matches!(result, Err(CanToolError::DbcLoad(_))));
+    assert_eq!(health.status().attempt, 5);
+    assert_eq!(health.state(), ConnectionState::Failed);
+    assert_eq!(bus.sockets(), 0);
+}
+
+#[tokio::test]
+async fn dbc_appearing_during_retries_is_loaded() {
+    let bus = SimBus::new();
+    let health = HealthMonitor::new();
+    let path = std::env::temp_dir().join(format!("cantool-retry-{}.dbc", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let deploy = {
+        let path = path.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            std::fs::copy(common::sample_dbc_path(), &path).unwrap();
+        })
+    };
+    let utils = CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&path),
+        Vec::new(),
+        common::fast_retry(50),
+        health.clone(),
+    )
+    .await;
+    deploy.await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(utils.unwrap().dbc_info().message("Motor").is_some());
+    assert!(health.status().attempt > 1);
+    assert!(health.is_connected());
+}
+
+#[tokio::test]
+async fn socket_errors_are_retried() {
+    let bus = SimBus::new();
+    let health = HealthMonitor::new();
+    bus.fail_opens(2, io::ErrorKind::NotFound);
+
+    let _utils = CanUtils::new_with_backend(
+        Arc::new(bus.clone()),
+        "vcan0",
+        Some(&common::sample_dbc_path()),
+        Vec::new(),
+        common::fast_retry(5),
+        health.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(health.status().attempt, 3);
+    assert!(health.is_connected());
+    assert_eq!(bus.sockets(), 1);
+}
+
+#[tokio::test]
+async fn received_frames_are_decoded() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+
+    // unknown IDs are skipped
+    bus.inject_data(0x7FF, &[0; 8]).unwrap();
+    bus.inject_data(ODOMETER_ID, &ODOMETER_DATA).unwrap();
+
+    let signals = utils.get_signals().await.unwrap();
+    assert_eq!(signals["TotalDistance"], 4294967.294);
+    assert_eq!(signals["Energy"], 1233567.8);
+}
+
+#[tokio::test]
+async fn stream_updates_carry_the_dbc_id() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+
+    bus.inject_data(ODOMETER_ID, &ODOMETER_DATA).unwrap();
+    let update = utils.signal_stream().next().await.unwrap();
+    assert_eq!(update.frame_id, 2566844926);
+    assert_eq!(utils.dbc_info().message_by_id(update.frame_id).unwrap().name, "Odometer");
+}
+
+#[tokio::test]
+async fn standard_frames_are_decoded() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+
+    bus.inject_data(0x100, &[0x2E, 0xFB, 0x12, 0x34, 0xF9, 0xC0, 0x00, 0x00]).unwrap();
+    bus.inject_data(0x100, &[0xFF, 0x7F, 0xFF, 0xFF, 0x7F, 0xF0, 0x00, 0x00]).unwrap();
+
+    let signals = utils.get_signals().await.unwrap();
+    assert_eq!(signals["Torque"], -123.4);
+    assert_eq!(signals["Speed"], 2330.0);
+    assert_eq!(signals["Current"], -25.0);
+
+    let update = utils.signal_stream().next().await.unwrap();
+    assert_eq!(update.frame_id, 0x100);
+    assert_eq!(update.signals["Speed"], 32767.5);
+}
+
+#[tokio::test]
+async fn standard_frames_are_e2e_checked() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+    let config = E2eConfig {
+        checksum: Some(E2eChecksum { signal: "BrakeChecksum".to_string(), algorithm: ChecksumAlgorithm::Xor }),
+        action: E2eAction::Drop,
+        ..E2eConfig::default()
+    };
+    utils.set_e2e("Brake", &config).unwrap();
+
+    // BrakePressure 12.5 bar, the first frame has a wrong checksum
+    bus.inject_data(0x400, &[0x7D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]).unwrap();
+    bus.inject_data(0x400, &[0x7D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7D]).unwrap();
+
+    let signals = utils.get_signals().await.unwrap();
+    assert_eq!(signals["BrakePressure"], 12.5);
+
+    let stats = utils.e2e_stats("Brake").unwrap();
+    assert_eq!(stats.ok, 1);
+    assert_eq!(stats.checksum_errors, 1);
+    assert_eq!(stats.dropped, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn socket_is_restarted_after_read_errors() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+    let health = utils.health();
+    utils.set_retry_policy(common::fast_retry(3));
+
+    // the first reopen fails as well
+    bus.inject_read_error(io::ErrorKind::ConnectionReset);
+    bus.fail_opens(1, io::ErrorKind::NotFound);
+    let injector = {
+        let bus = bus.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            bus.inject_data(ODOMETER_ID, &ODOMETER_DATA).unwrap();
+        })
+    };
+
+    let signals = utils.get_signals().await.unwrap();
+    injector.await.unwrap();
+
+    assert!(signals.contains_key("TotalDistance"));
+    assert_eq!(bus.sockets(), 1);
+    let status = health.status();
+    assert_eq!(status.state, ConnectionState::Connected);
+    assert_eq!(status.restarts, 1);
+    assert_eq!(status.attempt, 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn receiving_times_out_without_frames() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+
+    let start = Instant::now();
+    assert!(matches!(utils.get_signals().await, Err(CanToolError::Timeout)));
+    assert_eq!(start.elapsed(), Duration::from_secs(10));
+
+    assert!(matches!(utils.recv_frame(Duration::from_millis(50)).await, Err(CanToolError::Timeout)));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use cantool::can_filter::{CAN_EFF_FLAG, CAN_ERR_BUSOFF, CAN_ERR_CRTL, CAN_ERR_RESTARTED};
+use cantool::can_sim::SimBus;
+use cantool::can_stats::{BusStats, ErrorState};
+use tokio::time::{Duration, Instant};
+
+/// `CAN_ERR_CRTL` status bytes, the status is in data[1]
+const RX_WARNING: [u8; 8] = [0, 0x04, 0, 0, 0, 0, 0, 0];
+const TX_PASSIVE: [u8; 8] = [0, 0x20, 0, 0, 0, 0, 0, 0];
+const ACTIVE: [u8; 8] = [0, 0x40, 0, 0, 0, 0, 0, 0];
+
+/// Statistics with an interval starting at the returned instant
+fn started(bitrate: u32) -> (BusStats, Instant) {
+    let mut stats = BusStats::new(bitrate);
+    let start = Instant::now();
+    stats.snapshot(start);
+    (stats, start)
+}
+
+#[test]
+fn bus_load_counts_frame_bits() {
+    let (mut stats, start) = started(500_000);
+
+    // 100 standard frames of 8 bytes every 10 ms: 47 + 64 bits each
+    for i in 0..100 {
+        stats.record_frame(0x100, false, 8, false, start + Duration::from_millis(i * 10));
+    }
+    // one extended FD frame of 64 bytes: 67 + 10 + 512 bits
+    stats.record_frame(0x18FEF100, true, 64, true, start);
+
+    let snapshot = stats.snapshot(start + Duration::from_secs(1));
+    assert_eq!(snapshot.total_frames, 101);
+    assert!((snapshot.frames_per_s - 101.0).abs() < 1e-9);
+    assert!((snapshot.bytes_per_s - 864.0).abs() < 1e-9);
+    assert!((snapshot.bus_load - (100.0 * 111.0 + 589.0) / 500_000.0 * 100.0).abs() < 1e-9);
+
+    let motor = &snapshot.ids[&0x100];
+    assert_eq!(motor.frames, 100);
+    assert_eq!(motor.mean_cycle.map(|cycle| cycle.as_micros()), Some(10_000));
+    assert_eq!(motor.min_cycle, Some(Duration::from_millis(10)));
+    assert_eq!(motor.max_cycle, Some(Duration::from_millis(10)));
+    assert!(motor.jitter.unwrap() < Duration::from_micros(1));
+
+    // counters restart with the next interval, totals do not
+    let snapshot = stats.snapshot(start + Duration::from_secs(2));
+    assert_eq!((snapshot.frames_per_s, snapshot.bus_load), (0.0, 0.0));
+    assert_eq!(snapshot.total_frames, 101);
+    assert!(snapshot.ids.is_empty());
+}
+
+#[test]
+fn bus_load_is_capped_and_zero_without_bitrate() {
+    let (mut stats, start) = started(10_000);
+    for _ in 0..1000 {
+        stats.record_frame(0x100, false, 8, false, start);
+    }
+    assert_eq!(stats.snapshot(start + Duration::from_secs(1)).bus_load, 100.0);
+
+    let (mut stats, start) = started(0);
+    stats.record_frame(0x100, false, 8, false, start);
+    assert_eq!(stats.snapshot(start + Duration::from_secs(1)).bus_load, 0.0);
+}
+
+#[test]
+fn standard_and_extended_ids_are_counted_apart() {
+    let (mut stats, start) = started(500_000);
+    stats.record_frame(0x123, false, 8, false, start);
+    stats.record_frame(0x123, true, 8, false, start);
+    stats.record_frame(0x123, true, 8, false, start + Duration::from_millis(5));
+
+    let snapshot = stats.snapshot(start + Duration::from_secs(1));
+    assert_eq!(snapshot.ids.len(), 2);
+    assert_eq!(snapshot.ids[&0x123].frames, 1);
+    assert_eq!(snapshot.ids[&(0x123 | CAN_EFF_FLAG)].frames, 2);
+    assert_eq!(snapshot.ids[&(0x123 | CAN_EFF_FLAG)].mean_cycle, Some(Duration::from_millis(5)));
+}
+
+#[test]
+fn error_frames_move_the_error_state() {
+    let (mut stats, start) = started(500_000);
+    let cases: &[(u32, &[u8], ErrorState, u64, u64)] = &[
+        (CAN_ERR_CRTL, &RX_WARNING, ErrorState::Warning, 0, 0),
+        (CAN_ERR_CRTL, &TX_PASSIVE, ErrorState::Passive, 1, 0),
+        // staying passive is not another transition
+        (CAN_ERR_CRTL, &TX_PASSIVE, ErrorState::Passive, 1, 0),
+        (CAN_ERR_BUSOFF, &[0; 8], ErrorState::BusOff, 1, 1),
+        (CAN_ERR_RESTARTED, &[0; 8], ErrorState::Active, 1, 1),
+        (CAN_ERR_CRTL, &TX_PASSIVE, ErrorState::Passive, 2, 1),
+        (CAN_ERR_CRTL, &ACTIVE, ErrorState::Active, 2, 1),
+        // a status without state bits keeps the state
+        (CAN_ERR_CRTL, &[0; 8], ErrorState::Active, 2, 1),
+    ];
+
+    for (i, (class, data, state, passive, bus_off)) in cases.iter().enumerate() {
+        stats.record_error(*class, data);
+        let snapshot = stats.snapshot(start + Duration::from_secs(i as u64 + 1));
+        assert_eq!(snapshot.error_state, *state, "{}", i);
+        assert_eq!((snapshot.error_passive_count, snapshot.bus_off_count), (*passive, *bus_off), "{}", i);
+        assert_eq!(snapshot.error_frames, 1);
+        assert_eq!(snapshot.total_error_frames, i as u64 + 1);
+    }
+}
+
+#[tokio::test]
+async fn monitor_counts_received_frames() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+    let monitor = utils.enable_stats(500_000).unwrap();
+
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    bus.inject_data(0x100, &[0; 8]).unwrap();
+    bus.inject_data(0x18FEF1FE, &[0; 8]).unwrap();
+    bus.inject_error_frame(CAN_ERR_BUSOFF, &[0; 8]).unwrap();
+    while utils.recv_frame(Duration::from_millis(20)).await.is_ok() {}
+
+    let snapshot = monitor.snapshot();
+    assert_eq!(snapshot.total_frames, 3);
+    assert_eq!(snapshot.total_error_frames, 1);
+    assert_eq!(snapshot.error_state, ErrorState::BusOff);
+    assert_eq!(snapshot.ids[&0x100].frames, 2);
+    assert_eq!(snapshot.ids[&(0x18FEF1FE | CAN_EFF_FLAG)].frames, 1);
+    assert!(snapshot.bus_load > 0.0);
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use cantool::can_trigger::{Trigger, TriggerKind, TriggerSet};
+
+fn at(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(1_700_000_000_000).unwrap() + Duration::milliseconds(millis)
+}
+
+/// Number of events for each value of `signal`, one sample per second
+fn fired(trigger: Trigger, values: &[f64]) -> Vec<usize> {
+    let mut triggers = TriggerSet::new();
+    let signal = trigger.signal.clone();
+    triggers.add(trigger).unwrap();
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| triggers.evaluate(at(i as i64 * 1000), &HashMap::from([(signal.clone(), *value)])))
+        .collect()
+}
+
+#[test]
+fn first_value_sets_the_baseline() {
+    let cases = [
+        Trigger::on_change("Changed", "x", 0.0),
+        Trigger::rising("High", "x", 10.0, 1.0),
+        Trigger::falling("Low", "x", 10.0, 1.0),
+        Trigger::rate_of_change("Fast", "x", 1.0),
+    ];
+
+    for trigger in cases {
+        let name = trigger.name.clone();
+        // a value beyond the threshold is not a crossing
+        let start = if name == "Low" { 0.0 } else { 100.0 };
+        assert_eq!(fired(trigger, &[start, start]), [0, 0], "{}", name);
+    }
+}
+
+#[test]
+fn on_change_deadband() {
+    let cases: &[(f64, &[f64], &[usize])] = &[
+        (0.0, &[1.0, 1.0, 2.0, 2.0, 1.0], &[0, 0, 1, 0, 1]),
+        // relative to the value of the last event, not the previous value
+        (1.0, &[0.0, 0.5, 1.0, 1.5, 2.0, 1.0], &[0, 0, 0, 1, 0, 0]),
+        (1.0, &[0.0, -0.9, -1.1, 0.0, -0.2], &[0, 0, 1, 1, 0]),
+    ];
+
+    for (deadband, values, expected) in cases {
+        assert_eq!(fired(Trigger::on_change("Changed", "x", *deadband), values), *expected, "{}", deadband);
+    }
+}
+
+#[test]
+fn rising_rearms_below_hysteresis() {
+    let values = [5.0, 11.0, 9.5, 12.0, 8.9, 10.5, 10.0, 10.1];
+    assert_eq!(fired(Trigger::rising("High", "x", 10.0, 1.0), &values), [0, 1, 0, 0, 0, 1, 0, 0]);
+    assert_eq!(fired(Trigger::rising("High", "x", 10.0, 0.0), &values), [0, 1, 0, 1, 0, 1, 0, 0]);
+}
+
+#[test]
+fn soc_below_10_percent() {
+    let mut triggers = TriggerSet::new();
+    triggers.add(Trigger::falling("SocLow", "BMS_SOC", 10.0, 1.0)).unwrap();
+    let mut events = triggers.subscribe();
+
+    // noise around 10 % fires once, charging above 11 % re-arms it
+    let soc = [15.0, 12.0, 9.5, 10.2, 9.8, 10.9, 9.0, 11.5, 12.0, 9.9];
+    let fired: Vec<usize> = soc
+        .iter()
+        .enumerate()
+        .map(|(i, value)| triggers.evaluate(at(i as i64 * 1000), &HashMap::from([("BMS_SOC".to_string(), *value)])))
+        .collect();
+    assert_eq!(fired, [0, 0, 1, 0, 0, 0, 0, 0, 0, 1]);
+
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.trigger, "SocLow");
+    assert_eq!(event.signal, "BMS_SOC");
+    assert_eq!(event.kind, TriggerKind::Fell);
+    assert_eq!(event.timestamp, at(2000));
+    assert_eq!((event.value, event.previous), (9.5, 12.0));
+
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.timestamp, at(9000));
+    assert_eq!((event.value, event.previous), (9.9, 12.0));
+    assert!(events.try_recv().is_err());
+}
+
+#[test]
+fn rate_of_change_rearms_within_limit() {
+    let mut triggers = TriggerSet::new();
+    triggers.add(Trigger::rate_of_change("Fast", "x", 2.0)).unwrap();
+    let mut events = triggers.subscribe();
+
+    let samples = [(0, 0.0), (1000, 1.0), (2000, 4.0), (3000, 7.0), (4000, 8.0), (4500, 6.0), (5500, 6.0)];
+    let fired: Vec<usize> = samples
+        .iter()
+        .map(|(millis, value)| triggers.evaluate(at(*millis), &HashMap::from([("x".to_string(), *value)])))
+        .collect();
+    assert_eq!(fired, [0, 0, 1, 0, 0, 1, 0]);
+
+    assert_eq!(events.try_recv().unwrap().kind, TriggerKind::RateExceeded { rate: 3.0 });
+    assert_eq!(events.try_recv().unwrap().kind, TriggerKind::RateExceeded { rate: -4.0 });
+}
+
+#[test]
+fn triggers_are_replaced_and_validated() {
+    let mut triggers = TriggerSet::new();
+    triggers.add(Trigger::rising("Limit", "x", 10.0, 1.0)).unwrap();
+    triggers.add(Trigger::falling("Limit", "y", 5.0, 1.0)).unwrap();
+    assert_eq!(triggers.triggers().map(|trigger| trigger.signal.as_str()).collect::<Vec<_>>(), ["y"]);
+
+    assert!(triggers.add(Trigger::on_change("Bad", "x", -1.0)).is_err());
+    assert!(triggers.add(Trigger::rising("Bad", "x", f64::NAN, 1.0)).is_err());
+    assert!(triggers.add(Trigger::falling("Bad", "x", 1.0, -1.0)).is_err());
+    assert!(triggers.add(Trigger::rate_of_change("Bad", "x", -1.0)).is_err());
+    assert!(triggers.remove("Limit"));
+    assert!(!triggers.remove("Limit"));
+}
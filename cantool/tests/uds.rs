@@ -0,0 +1,103 @@
+mod common;
+
+use cantool::can_sim::SimBus;
+use cantool::can_transport::CanBackend;
+use cantool::iso_tp::{IsoTpChannel, IsoTpConfig};
+use cantool::uds_client::{Nrc, UdsClient, UdsError};
+use futures_util::StreamExt;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tokio_socketcan::CANFrame;
+
+const TESTER_ID: u32 = 0x7E0;
+const ECU_ID: u32 = 0x7E8;
+
+fn single_frame(payload: &[u8]) -> CANFrame {
+    let mut data = vec![payload.len() as u8];
+    data.extend_from_slice(payload);
+    data.resize(8, 0xCC);
+    CANFrame::new(ECU_ID, &data, false, false).unwrap()
+}
+
+/// ECU answering single frame requests, `respond` returns the responses with their delays
+fn spawn_ecu<F>(bus: &SimBus, mut respond: F) -> JoinHandle<Vec<Vec<u8>>>
+where
+    F: FnMut(&[u8]) -> Vec<(Duration, Vec<u8>)> + Send + 'static,
+{
+    let mut socket = bus.open("vcan0").unwrap();
+    tokio::spawn(async move {
+        let mut requests = Vec::new();
+        while let Some(Ok(frame)) = socket.next().await {
+            let data = frame.data();
+            if frame.id() != TESTER_ID || data[0] >> 4 != 0 {
+                continue;
+            }
+
+            let request = data[1..1 + data[0] as usize].to_vec();
+            for (delay, response) in respond(&request) {
+                tokio::time::sleep(delay).await;
+                socket.write_frame(single_frame(&response)).await.unwrap();
+            }
+            requests.push(request);
+        }
+        requests
+    })
+}
+
+fn xor_key(_level: u8, seed: &[u8]) -> Vec<u8> {
+    seed.iter().map(|byte| byte ^ 0xFF).collect()
+}
+
+#[tokio::test]
+async fn security_level_is_validated_before_sending() {
+    let bus = SimBus::new();
+    let mut utils = common::sim_utils(&bus).await;
+    let mut client = UdsClient::new(IsoTpChannel::new(&mut utils, IsoTpConfig::new(TESTER_ID, ECU_ID)));
+
+    for level in [0x00, 0x02, 0x7F, 0xFF] {
+        let result = client.security_access(level, &xor_key).await;
+        assert!(matches!(result, Err(UdsError::InvalidArgument(_))), "level {:02x}: {:?}", level, result);
+    }
+    assert!(bus.transmitted().is_empty());
+}
+
+#[tokio::test]
+async fn security_access_waits_for_pending_responses() {
+    let bus = SimBus::new();
+    let ecu = spawn_ecu(&bus, |request| match request {
+        // longer than P2, only accepted because of the response pending
+        [0x27, 0x01] => vec![
+            (Duration::ZERO, vec![0x7F, 0x27, 0x78]),
+            (Duration::from_millis(200), vec![0x67, 0x01, 0x12, 0x34]),
+        ],
+        [0x27, 0x02, ..] => vec![(Duration::ZERO, vec![0x67, 0x02])],
+        _ => vec![(Duration::ZERO, vec![0x7F, request[0], 0x11])],
+    });
+
+    let mut utils = common::sim_utils(&bus).await;
+    let mut client = UdsClient::new(IsoTpChannel::new(&mut utils, IsoTpConfig::new(TESTER_ID, ECU_ID)));
+    client.security_access(0x01, &xor_key).await.unwrap();
+    drop(client);
+    drop(utils);
+
+    bus.close();
+    let requests = ecu.await.unwrap();
+    assert_eq!(requests, vec![vec![0x27, 0x01], vec![0x27, 0x02, 0xED, 0xCB]]);
+}
+
+#[tokio::test]
+async fn negative_responses_are_reported() {
+    let bus = SimBus::new();
+    let _ecu = spawn_ecu(&bus, |request| match request {
+        [0x27, 0x03] => vec![(Duration::ZERO, vec![0x67, 0x03, 0xAA])],
+        _ => vec![(Duration::ZERO, vec![0x7F, request[0], 0x35])],
+    });
+
+    let mut utils = common::sim_utils(&bus).await;
+    let mut client = UdsClient::new(IsoTpChannel::new(&mut utils, IsoTpConfig::new(TESTER_ID, ECU_ID)));
+
+    match client.security_access(0x03, &xor_key).await {
+        Err(UdsError::NegativeResponse { service: 0x27, nrc: Nrc::InvalidKey }) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use cantool::can_virtual::VirtualSignals;
+
+fn at(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(1_700_000_000_000).unwrap() + Duration::milliseconds(millis)
+}
+
+fn signals(expression: &str) -> VirtualSignals {
+    let mut signals = VirtualSignals::new();
+    signals.add("out", expression).unwrap();
+    signals
+}
+
+/// Value of `out` after an update with `inputs` at `millis`
+fn update(signals: &mut VirtualSignals, millis: i64, inputs: &[(&str, f64)]) -> Option<f64> {
+    let mut values: HashMap<String, f64> = inputs.iter().map(|(name, value)| (name.to_string(), *value)).collect();
+    signals.update(at(millis), &mut values);
+    values.get("out").copied()
+}
+
+/// Value of `out` for each of the samples of `a`
+fn series(expression: &str, samples: &[(i64, f64)]) -> Vec<f64> {
+    let mut signals = signals(expression);
+    samples
+        .iter()
+        .map(|(millis, value)| update(&mut signals, *millis, &[("a", *value)]).unwrap())
+        .collect()
+}
+
+/// Expression, samples of `a` as (milliseconds, value) and the expected last result
+type SeriesCase<'a> = (&'a str, &'a [(i64, f64)], f64);
+
+fn assert_close(actual: f64, expected: f64, context: &str) {
+    assert!((actual - expected).abs() < 1e-9, "{}: {} != {}", context, actual, expected);
+}
+
+#[test]
+fn arithmetic_and_functions() {
+    let cases: &[(&str, Option<f64>)] = &[
+        ("a + b * c", Some(14.0)),
+        ("(a + b) * c", Some(20.0)),
+        ("a - b - c", Some(-5.0)),
+        ("c / a / a", Some(1.0)),
+        ("a * b / c", Some(1.5)),
+        ("-a * b", Some(-6.0)),
+        ("a - -b", Some(5.0)),
+        ("-(a + b)", Some(-5.0)),
+        ("--a", Some(2.0)),
+        ("a * 1e3", Some(2000.0)),
+        ("c * 2.5E-1", Some(1.0)),
+        ("a * 1e+2", Some(200.0)),
+        ("a * .5", Some(1.0)),
+        ("abs(a - c)", Some(2.0)),
+        ("sqrt(c)", Some(2.0)),
+        ("min(a, b)", Some(2.0)),
+        ("max(a, b)", Some(3.0)),
+        ("max(a, b) - min(-a, b * c)", Some(5.0)),
+        ("Pack.Voltage * 2", Some(800.0)),
+        ("a / (b - b)", None),
+        ("sqrt(-a)", None),
+        ("a + missing", None),
+    ];
+
+    for (expression, expected) in cases {
+        let mut signals = signals(expression);
+        let actual = update(&mut signals, 0, &[("a", 2.0), ("b", 3.0), ("c", 4.0), ("Pack.Voltage", 400.0)]);
+        assert_eq!(actual, *expected, "{}", expression);
+    }
+}
+
+#[test]
+fn window_units() {
+    // a sample exactly one window old is still part of it
+    let cases: &[(&str, i64)] = &[
+        ("avg(a, 500ms)", 500),
+        ("avg(a, 2s)", 2_000),
+        ("avg(a, 0.5s)", 500),
+        ("avg(a, 1m)", 60_000),
+        ("avg(a, 1min)", 60_000),
+        ("avg(a, 1h)", 3_600_000),
+    ];
+
+    for (expression, window) in cases {
+        assert_eq!(series(expression, &[(0, 10.0), (*window, 20.0)]), [10.0, 15.0], "{}", expression);
+        assert_eq!(series(expression, &[(0, 10.0), (*window + 1, 20.0)]), [10.0, 20.0], "{}", expression);
+    }
+}
+
+#[test]
+fn integrate_over_seconds() {
+    let cases: &[SeriesCase] = &[
+        ("integrate(a)", &[(0, 2.0)], 0.0),
+        ("integrate(a)", &[(0, 2.0), (1_000, 2.0), (10_000, 2.0)], 20.0),
+        // trapezoids of a ramp
+        ("integrate(a)", &[(0, 0.0), (1_000, 1.0), (2_000, 2.0)], 2.0),
+        ("integrate(a)", &[(0, 0.0), (500, 4.0)], 1.0),
+        ("integrate(a) / 3600", &[(0, 3600.0), (3_600_000, 3600.0)], 3600.0),
+        ("integrate(-a)", &[(0, 1.0), (2_000, 1.0)], -2.0),
+        // an out of order sample does not count negatively
+        ("integrate(a)", &[(0, 1.0), (2_000, 1.0), (1_000, 1.0)], 2.0),
+    ];
+
+    for (expression, samples, expected) in cases {
+        let values = series(expression, samples);
+        assert_close(*values.last().unwrap(), *expected, expression);
+    }
+}
+
+#[test]
+fn windowed_min_max_avg() {
+    let samples = [(0, 5.0), (1_000, 1.0), (2_000, 3.0), (3_000, 4.0), (3_500, 2.0), (10_000, 7.0)];
+    let cases: &[(&str, [f64; 6])] = &[
+        ("min(a, 2s)", [5.0, 1.0, 1.0, 1.0, 2.0, 7.0]),
+        ("max(a, 2s)", [5.0, 5.0, 5.0, 4.0, 4.0, 7.0]),
+        ("avg(a, 2s)", [5.0, 3.0, 3.0, 8.0 / 3.0, 3.0, 7.0]),
+        ("max(a, 2s) - min(a, 2s)", [0.0, 4.0, 4.0, 3.0, 2.0, 0.0]),
+    ];
+
+    for (expression, expected) in cases {
+        for (actual, expected) in series(expression, &samples).into_iter().zip(expected) {
+            assert_close(actual, *expected, expression);
+        }
+    }
+}
+
+#[test]
+fn stateful_functions_see_samples_while_other_inputs_are_missing() {
+    let mut integral = signals("b + integrate(a)");
+    let mut window = signals("b * max(a, 10s)");
+
+    for signals in [&mut integral, &mut window] {
+        assert_eq!(update(signals, 0, &[("a", 2.0)]), None);
+        assert_eq!(update(signals, 1_000, &[("a", 6.0)]), None);
+    }
+
+    assert_eq!(update(&mut integral, 2_000, &[("a", 2.0), ("b", 0.0)]), Some(8.0));
+    assert_eq!(update(&mut window, 2_000, &[("a", 2.0), ("b", 1.0)]), Some(6.0));
+}
+
+#[test]
+fn signals_use_earlier_signals() {
+    let mut signals = VirtualSignals::from_config_str(
+        "# pack power in kW\nPower = Voltage * Current / 1000\nEnergy = integrate(Power) / 3600 # kWh\n",
+    )
+    .unwrap();
+    assert_eq!(signals.names().collect::<Vec<_>>(), ["Power", "Energy"]);
+    assert_eq!(signals.inputs(), ["Voltage", "Current"].map(String::from).into());
+
+    let mut values = HashMap::from([("Voltage".to_string(), 400.0), ("Current".to_string(), 90.0)]);
+    signals.update(at(0), &mut values);
+    assert_eq!(values["Power"], 36.0);
+    assert_eq!(values["Energy"], 0.0);
+
+    let mut values = HashMap::from([("Current".to_string(), 90.0)]);
+    signals.update(at(3_600_000), &mut values);
+    assert_close(values["Energy"], 36.0, "Energy");
+
+    signals.reset("Energy");
+    let mut values = HashMap::from([("Current".to_string(), 90.0)]);
+    signals.update(at(7_200_000), &mut values);
+    assert_eq!(values["Energy"], 0.0);
+}
+
+#[test]
+fn invalid_expressions_are_rejected() {
+    let cases = [
+        "",
+        "a +",
+        "a b",
+        "(a",
+        "a)",
+        "a * (b + c",
+        "1.2.3",
+        "2x",
+        "a $ b",
+        "foo(a)",
+        "abs(a, b)",
+        "sqrt()",
+        "avg(a, b)",
+        "avg(a)",
+        "min(a, 0s)",
+        "avg(a, 1e20s)",
+        "max(a, 1e308h)",
+        "max(a, 10s, b)",
+        "10s",
+    ];
+
+    for expression in cases {
+        assert!(VirtualSignals::new().add("out", expression).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn invalid_definitions_are_rejected() {
+    let cases: &[(&str, &str)] = &[
+        ("Power", "line 1: expected <name> = <expression>"),
+        ("1Power = a", "line 1: invalid signal name '1Power'"),
+        ("Power = a\nPower = b", "line 2: Power is defined twice"),
+        ("Power = Power * 2", "line 1: Power refers to itself"),
+        ("# comment\nPower = Voltage * Current\nVoltage = a", "line 3: Voltage is used by Power before its definition"),
+        ("Power = a +", "line 1: Power: unexpected None"),
+    ];
+
+    for (config, expected) in cases {
+        assert_eq!(VirtualSignals::from_config_str(config).unwrap_err(), *expected, "{}", config);
+    }
+}
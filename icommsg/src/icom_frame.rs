@@ -0,0 +1,232 @@
+use std::error::Error;
+use crate::icom_msg::{crc8, IONICOMPacketType, ICOM_FN_MAX_LEN, ICOM_MSG_MAX_LEN};
+
+/// First byte of a versioned frame. A legacy frame starts with its u16 payload length,
+/// whose high byte is at most 1, so the version byte tells both formats apart.
+pub const ICOM_FRAME_MAGIC: u8 = 0xC5;
+pub const ICOM_FRAME_VERSION: u8 = 2;
+/// Magic, version and function count
+const ICOM_FRAME_HEADER_LEN: usize = 3;
+pub const ICOM_FRAME_MAX_FUNCTIONS: usize = u8::MAX as usize;
+/// Upper bound of a whole frame, header and CRC included
+pub const ICOM_FRAME_MAX_LEN: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcomVersion {
+    /// Fixed 259 byte `IONICOMPacketType` with two 128 byte function slots
+    Legacy,
+    /// Header with the function count and per function lengths
+    V2,
+}
+
+/// ICOM message with a variable number of functions of variable length.
+///
+/// Versioned frame layout, integers little endian:
+///
+/// | bytes      | content                                  |
+/// |------------|------------------------------------------|
+/// | 0          | `ICOM_FRAME_MAGIC`                       |
+/// | 1          | `ICOM_FRAME_VERSION`                     |
+/// | 2          | function count N                         |
+/// | 3..3+2N    | length of each function (u16)            |
+/// | ..         | function data, concatenated              |
+/// | last       | CRC-8 of all preceding bytes             |
+///
+/// An empty function (length 0) keeps the position of the following ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcomFrame {
+    version: IcomVersion,
+    functions: Vec<Vec<u8>>,
+}
+
+impl Default for IcomFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IcomFrame {
+    pub fn new() -> Self {
+        IcomFrame {
+            version: IcomVersion::V2,
+            functions: Vec::new(),
+        }
+    }
+
+    /// Format the frame was decoded from, `V2` for frames built locally
+    pub fn version(&self) -> IcomVersion {
+        self.version
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.functions.len()
+    }
+
+    /// Sum of the function lengths
+    pub fn payload_len(&self) -> usize {
+        self.functions.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        self.payload_len() == 0
+    }
+
+    pub fn get_func(&self, fncode: u8) -> Result<Vec<u8>, &'static str> {
+        match self.functions.get(fncode as usize) {
+            None => Err("Function code out of bounds"),
+            Some(data) if data.is_empty() => Err("Function code fncode empty"),
+            Some(data) => Ok(data.clone()),
+        }
+    }
+
+    /// Sets a function, functions below `fncode` that were not set yet stay empty
+    pub fn set_func(&mut self, fncode: u8, data: Vec<u8>) -> Result<(), &'static str> {
+        let index = fncode as usize;
+        if index >= ICOM_FRAME_MAX_FUNCTIONS || data.len() > u16::MAX as usize {
+            return Err("Data exceeds function or payload bounds");
+        }
+
+        let current = self.functions.get(index).map_or(0, Vec::len);
+        let count = self.functions.len().max(index + 1);
+        if Self::frame_len(count, self.payload_len() - current + data.len()) > ICOM_FRAME_MAX_LEN {
+            return Err("Data exceeds function or payload bounds");
+        }
+
+        if index >= self.functions.len() {
+            self.functions.resize(index + 1, Vec::new());
+        }
+        self.functions[index] = data;
+        Ok(())
+    }
+
+    /// Encodes the versioned frame, see the type documentation for the layout
+    pub fn to_byte_array(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(Self::frame_len(self.functions.len(), self.payload_len()));
+
+        buffer.push(ICOM_FRAME_MAGIC);
+        buffer.push(ICOM_FRAME_VERSION);
+        buffer.push(self.functions.len() as u8);
+        for function in self.functions.iter() {
+            buffer.extend_from_slice(&(function.len() as u16).to_le_bytes());
+        }
+        for function in self.functions.iter() {
+            buffer.extend_from_slice(function);
+        }
+        buffer.push(crc8(&buffer));
+
+        buffer
+    }
+
+    /// Decodes a versioned frame or a legacy 259 byte frame
+    pub fn from_byte_array(rxdata: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if Self::is_versioned(rxdata) {
+            return Self::decode_versioned(rxdata);
+        }
+        if rxdata.len() == ICOM_MSG_MAX_LEN {
+            let packet = IONICOMPacketType::from_byte_array(rxdata.to_vec())?;
+            return Ok(Self::from(&packet));
+        }
+
+        Err("Unknown ICOM frame format.".into())
+    }
+
+    /// Converts to the legacy format, which only holds functions 0 and 1 of up to 128 bytes
+    pub fn to_legacy(&self) -> Result<IONICOMPacketType, &'static str> {
+        let mut packet = IONICOMPacketType::new_dummy();
+
+        for (fncode, function) in self.functions.iter().enumerate() {
+            if function.is_empty() {
+                continue;
+            }
+            // the legacy format marks a slot empty by a zero first byte
+            if fncode >= 2 || function.len() > ICOM_FN_MAX_LEN || function[0] == 0 {
+                return Err("Function not representable in the legacy format");
+            }
+            packet.set_func(fncode as u8, function.clone())?;
+        }
+
+        Ok(packet)
+    }
+
+    pub fn dump(&self) {
+        println!("====================================================");
+        println!("Version: {:?}", self.version);
+        println!("Functions: {}", self.functions.len());
+        println!("Payload Length: {}", self.payload_len());
+        println!("Payload Dump:");
+
+        for (i, function) in self.functions.iter().enumerate() {
+            println!("Function {} ({} bytes):", i, function.len());
+
+            for byte in function {
+                print!("{:02X} ", byte);
+            }
+
+            println!();
+        }
+        println!("====================================================");
+    }
+
+    fn frame_len(function_count: usize, payload_len: usize) -> usize {
+        ICOM_FRAME_HEADER_LEN + 2 * function_count + payload_len + 1
+    }
+
+    fn is_versioned(rxdata: &[u8]) -> bool {
+        rxdata.len() >= 2 && rxdata[0] == ICOM_FRAME_MAGIC && rxdata[1] >= ICOM_FRAME_VERSION
+    }
+
+    fn decode_versioned(rxdata: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if rxdata[1] != ICOM_FRAME_VERSION {
+            return Err(format!("Unsupported ICOM frame version {}.", rxdata[1]).into());
+        }
+        if rxdata.len() < ICOM_FRAME_HEADER_LEN + 1 || rxdata.len() > ICOM_FRAME_MAX_LEN {
+            return Err("Invalid byte array length.".into());
+        }
+
+        let (frame, crc) = rxdata.split_at(rxdata.len() - 1);
+        if crc8(frame) != crc[0] {
+            return Err("CRC check failed.".into());
+        }
+
+        let count = frame[2] as usize;
+        let lengths_end = ICOM_FRAME_HEADER_LEN + 2 * count;
+        if frame.len() < lengths_end {
+            return Err("Package corrupted".into());
+        }
+
+        let lengths: Vec<usize> = frame[ICOM_FRAME_HEADER_LEN..lengths_end]
+            .chunks_exact(2)
+            .map(|length| u16::from_le_bytes([length[0], length[1]]) as usize)
+            .collect();
+        if lengths_end + lengths.iter().sum::<usize>() != frame.len() {
+            return Err("Package corrupted".into());
+        }
+
+        let mut functions = Vec::with_capacity(count);
+        let mut start = lengths_end;
+        for length in lengths {
+            functions.push(frame[start..start + length].to_vec());
+            start += length;
+        }
+
+        Ok(IcomFrame {
+            version: IcomVersion::V2,
+            functions,
+        })
+    }
+}
+
+impl From<&IONICOMPacketType> for IcomFrame {
+    /// Functions 0 and 1 of the legacy slots, empty slots become empty functions
+    fn from(packet: &IONICOMPacketType) -> Self {
+        let mut functions: Vec<Vec<u8>> = (0..2u8).map(|fncode| packet.get_func(fncode).unwrap_or_default()).collect();
+        while functions.last().is_some_and(Vec::is_empty) {
+            functions.pop();
+        }
+
+        IcomFrame {
+            version: IcomVersion::Legacy,
+            functions,
+        }
+    }
+}
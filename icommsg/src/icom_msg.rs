@@ -1,8 +1,8 @@
 use std::{error::Error};
 
 const ICOM_MSG_PAYLOAD_MAX_LEN: usize = 256;
-const ICOM_MSG_MAX_LEN: usize = 259;
-const ICOM_FN_MAX_LEN: usize = 128;
+pub(crate) const ICOM_MSG_MAX_LEN: usize = 259;
+pub(crate) const ICOM_FN_MAX_LEN: usize = 128;
 
 #[derive(Debug, Clone)]
 pub struct IONICOMPacketType {
@@ -37,7 +37,7 @@ const CRC_TABLE: [u8; 256] = [
 ];
 
 
-fn crc8(msg: &[u8]) -> u8 {
+pub(crate) fn crc8(msg: &[u8]) -> u8 {
     let mut crc: u8 = 0;
 
     for &byte in msg {
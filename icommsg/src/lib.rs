@@ -1 +1,2 @@
-pub mod icom_msg;
\ No newline at end of file
+pub mod icom_msg;
+pub mod icom_frame;
@@ -0,0 +1,128 @@
+use icommsg::icom_frame::{IcomFrame, IcomVersion, ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION};
+use icommsg::icom_msg::IONICOMPacketType;
+
+/// CRC-8 with polynomial 0x07 and initial value 0, bit by bit
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+    })
+}
+
+/// `frame` followed by its CRC
+fn with_crc(frame: &[u8]) -> Vec<u8> {
+    let mut data = frame.to_vec();
+    data.push(crc8(frame));
+    data
+}
+
+fn decode_error(data: &[u8]) -> String {
+    IcomFrame::from_byte_array(data).unwrap_err().to_string()
+}
+
+#[test]
+fn versioned_frame_round_trip() {
+    let mut frame = IcomFrame::new();
+    frame.set_func(0, vec![1, 2, 3]).unwrap();
+    frame.set_func(2, vec![0xAA; 300]).unwrap();
+    assert_eq!(frame.function_count(), 3);
+    assert_eq!(frame.payload_len(), 303);
+
+    let data = frame.to_byte_array();
+    assert_eq!(data[..9], [ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 3, 3, 0, 0, 0, 0x2C, 0x01]);
+    assert_eq!(data[9..12], [1, 2, 3]);
+    assert_eq!(data.len(), 9 + 303 + 1);
+    assert_eq!(data[data.len() - 1], crc8(&data[..data.len() - 1]));
+
+    let decoded = IcomFrame::from_byte_array(&data).unwrap();
+    assert_eq!(decoded, frame);
+    assert_eq!(decoded.version(), IcomVersion::V2);
+    assert_eq!(decoded.get_func(0).unwrap(), vec![1, 2, 3]);
+    assert_eq!(decoded.get_func(1), Err("Function code fncode empty"));
+    assert_eq!(decoded.get_func(2).unwrap(), vec![0xAA; 300]);
+    assert_eq!(decoded.get_func(3), Err("Function code out of bounds"));
+}
+
+#[test]
+fn empty_frame_round_trip() {
+    let frame = IcomFrame::new();
+    let data = frame.to_byte_array();
+    assert_eq!(data, with_crc(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 0]));
+
+    let decoded = IcomFrame::from_byte_array(&data).unwrap();
+    assert!(decoded.is_dummy());
+    assert_eq!(decoded.function_count(), 0);
+}
+
+#[test]
+fn crc_mismatch_is_rejected() {
+    let mut frame = IcomFrame::new();
+    frame.set_func(0, vec![0x10, 0x20]).unwrap();
+    let data = frame.to_byte_array();
+
+    for index in [2, 3, 5, data.len() - 1] {
+        let mut corrupted = data.clone();
+        corrupted[index] ^= 0x01;
+        assert_eq!(decode_error(&corrupted), "CRC check failed.", "byte {}", index);
+    }
+}
+
+#[test]
+fn truncated_length_table_is_rejected() {
+    // three functions announced, one length present
+    assert_eq!(decode_error(&with_crc(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 3, 1, 0])), "Package corrupted");
+    // the lengths do not add up to the data
+    assert_eq!(decode_error(&with_crc(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 1, 5, 0, 1, 2])), "Package corrupted");
+    assert_eq!(decode_error(&with_crc(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 1, 1, 0, 1, 2])), "Package corrupted");
+    assert_eq!(decode_error(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION, 0]), "Invalid byte array length.");
+}
+
+#[test]
+fn unknown_version_is_rejected() {
+    let data = with_crc(&[ICOM_FRAME_MAGIC, ICOM_FRAME_VERSION + 1, 0]);
+    assert_eq!(decode_error(&data), format!("Unsupported ICOM frame version {}.", ICOM_FRAME_VERSION + 1));
+
+    // neither versioned nor the legacy length
+    assert_eq!(decode_error(&with_crc(&[ICOM_FRAME_MAGIC, 1, 0])), "Unknown ICOM frame format.");
+    assert_eq!(decode_error(&[]), "Unknown ICOM frame format.");
+}
+
+#[test]
+fn legacy_frame_is_decoded() {
+    let mut packet = IONICOMPacketType::new_dummy();
+    packet.set_func(0, vec![1, 2, 3]).unwrap();
+    packet.set_func(1, vec![0x55; 128]).unwrap();
+    let data = packet.to_byte_array();
+    assert_eq!(data.len(), 259);
+    assert_eq!(data[..2], [0x00, 0x01]);
+
+    let frame = IcomFrame::from_byte_array(&data).unwrap();
+    assert_eq!(frame.version(), IcomVersion::Legacy);
+    assert_eq!(frame.function_count(), 2);
+    // legacy slots are always 128 bytes
+    let mut function = vec![0; 128];
+    function[..3].copy_from_slice(&[1, 2, 3]);
+    assert_eq!(frame.get_func(0).unwrap(), function);
+    assert_eq!(frame.get_func(1).unwrap(), vec![0x55; 128]);
+
+    assert_eq!(frame.to_legacy().unwrap().to_byte_array(), data);
+
+    let mut corrupted = data;
+    corrupted[10] ^= 0x01;
+    assert_eq!(decode_error(&corrupted), "CRC check failed.");
+}
+
+#[test]
+fn legacy_conversion_needs_two_short_functions() {
+    let mut frame = IcomFrame::new();
+    frame.set_func(2, vec![1]).unwrap();
+    assert!(frame.to_legacy().is_err());
+
+    let mut frame = IcomFrame::new();
+    frame.set_func(0, vec![1; 129]).unwrap();
+    assert!(frame.to_legacy().is_err());
+
+    // a zero first byte marks an empty legacy slot
+    let mut frame = IcomFrame::new();
+    frame.set_func(1, vec![0, 1]).unwrap();
+    assert!(frame.to_legacy().is_err());
+}